@@ -0,0 +1,239 @@
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::UrlAnalysis;
+use crate::history::now_secs;
+
+// Expired keys are removed in chunks so a sweep never holds the lock for long.
+const SWEEP_CHUNK: usize = 256;
+// Warm jobs kept for polling; the oldest finished jobs are dropped past this.
+const MAX_WARM_JOBS: usize = 20;
+
+struct CacheEntry {
+    analysis: UrlAnalysis,
+    expires_at: Instant,
+    // Set for trusted low-risk verdicts, which are served as-is until then.
+    trusted_until: Option<Instant>,
+}
+
+impl CacheEntry {
+    fn live_until(&self) -> Instant {
+        self.trusted_until.map_or(self.expires_at, |until| until.max(self.expires_at))
+    }
+}
+
+// One entry in a `cache_file`. `Instant`s do not survive a restart, so times are Unix seconds.
+#[derive(Serialize, Deserialize)]
+struct PersistedEntry {
+    key: String,
+    analysis: UrlAnalysis,
+    expires_at: i64,
+    trusted_until: Option<i64>,
+}
+
+fn to_unix(at: Instant, now: Instant) -> i64 {
+    now_secs() + at.saturating_duration_since(now).as_secs() as i64
+}
+
+// The `Instant` a Unix time maps to, capped at `limit` from now; `None` once it has passed.
+fn from_unix(at: i64, now: Instant, limit: Duration) -> Option<Instant> {
+    let remaining = at - now_secs();
+    (remaining > 0).then(|| now + Duration::from_secs(remaining as u64).min(limit))
+}
+
+// Analyses keyed by normalized URL. Entries expire after `ttl`, trusted ones after
+// `trust_window` if that is later; once `capacity` is reached the least recently used
+// entry is evicted.
+pub struct AnalysisCache {
+    entries: Mutex<LruCache<String, CacheEntry>>,
+    ttl: Duration,
+    trust_window: Duration,
+}
+
+impl AnalysisCache {
+    pub fn new(capacity: usize, ttl: Duration, trust_window: Duration) -> AnalysisCache {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        AnalysisCache {
+            entries: Mutex::new(LruCache::new(capacity)),
+            ttl,
+            trust_window,
+        }
+    }
+
+    // The cached analysis and whether it is still within its trust window.
+    pub fn get(&self, key: &str) -> Option<(UrlAnalysis, bool)> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        match entries.get(key) {
+            Some(entry) if entry.live_until() > now => {
+                let trusted = entry.trusted_until.is_some_and(|until| until > now);
+                Some((entry.analysis.clone(), trusted))
+            }
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, key: String, analysis: UrlAnalysis, trusted: bool) {
+        let now = Instant::now();
+        let entry = CacheEntry {
+            analysis,
+            expires_at: now + self.ttl,
+            trusted_until: (trusted && !self.trust_window.is_zero()).then(|| now + self.trust_window),
+        };
+        self.entries.lock().unwrap().put(key, entry);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    // After a deny-list refresh: drops every untrusted entry, and the trusted ones `still_safe`
+    // rejects (those the new list flags). Returns how many trusted entries were kept.
+    pub fn invalidate_untrusted(&self, still_safe: impl Fn(&str) -> bool) -> usize {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        let stale: Vec<String> = entries
+            .iter()
+            .filter(|(key, entry)| entry.trusted_until.is_none_or(|until| until <= now) || !still_safe(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &stale {
+            entries.pop(key);
+        }
+        entries.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    // Drops expired entries and returns how many were removed.
+    pub fn sweep(&self) -> usize {
+        let now = Instant::now();
+        let expired: Vec<String> = {
+            let entries = self.entries.lock().unwrap();
+            entries.iter().filter(|(_, entry)| entry.live_until() <= now).map(|(key, _)| key.clone()).collect()
+        };
+        for chunk in expired.chunks(SWEEP_CHUNK) {
+            let mut entries = self.entries.lock().unwrap();
+            for key in chunk {
+                // Re-check: the entry may have been refreshed since the scan.
+                if entries.peek(key).is_some_and(|entry| entry.live_until() <= now) {
+                    entries.pop(key);
+                }
+            }
+        }
+        expired.len()
+    }
+
+    // Writes the live entries to `path`, least recently used first, and returns how many.
+    pub fn save(&self, path: &str) -> Result<usize, String> {
+        let now = Instant::now();
+        let persisted: Vec<PersistedEntry> = {
+            let entries = self.entries.lock().unwrap();
+            entries
+                .iter()
+                .rev()
+                .filter(|(_, entry)| entry.live_until() > now)
+                .map(|(key, entry)| PersistedEntry {
+                    key: key.clone(),
+                    analysis: entry.analysis.clone(),
+                    expires_at: to_unix(entry.expires_at, now),
+                    trusted_until: entry.trusted_until.map(|until| to_unix(until, now)),
+                })
+                .collect()
+        };
+        let json = serde_json::to_string(&persisted).map_err(|e| e.to_string())?;
+        // Written aside and renamed, so a crash mid-write never leaves a truncated file.
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, json).and_then(|_| fs::rename(&tmp, path)).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(persisted.len())
+    }
+
+    // Reloads a `save`d file, skipping entries that have expired or were checked against a
+    // deny list other than `generation`. Returns how many were loaded.
+    pub fn load(&self, path: &str, generation: Option<&str>) -> Result<usize, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let persisted: Vec<PersistedEntry> = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        let mut loaded = 0;
+        for PersistedEntry { key, analysis, expires_at, trusted_until } in persisted {
+            if analysis.feed_generation.as_deref() != generation {
+                continue;
+            }
+            // Limits may have shrunk since the file was written.
+            let expires_at = from_unix(expires_at, now, self.ttl);
+            let trusted_until = trusted_until
+                .filter(|_| !self.trust_window.is_zero())
+                .and_then(|until| from_unix(until, now, self.trust_window));
+            if expires_at.is_none() && trusted_until.is_none() {
+                continue;
+            }
+            entries.put(key, CacheEntry { analysis, expires_at: expires_at.unwrap_or(now), trusted_until });
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WarmStatus {
+    pub id: u64,
+    pub running: bool,
+    // Distinct normalized URLs in the job.
+    pub total: usize,
+    pub processed: usize,
+    pub cached: usize,
+    // Invalid URLs and failed or degraded analyses, none of which are cached.
+    pub failed: usize,
+    pub started_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<i64>,
+}
+
+// Progress of `/cache/warm` jobs, by job id.
+#[derive(Default)]
+pub struct WarmJobs {
+    next_id: AtomicU64,
+    jobs: Mutex<BTreeMap<u64, WarmStatus>>,
+}
+
+impl WarmJobs {
+    pub fn start(&self, total: usize) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut jobs = self.jobs.lock().unwrap();
+        while jobs.len() >= MAX_WARM_JOBS {
+            let Some(finished) = jobs.values().find(|job| !job.running).map(|job| job.id) else {
+                break;
+            };
+            jobs.remove(&finished);
+        }
+        jobs.insert(id, WarmStatus { id, running: true, total, started_at: now_secs(), ..Default::default() });
+        id
+    }
+
+    pub fn update(&self, id: u64, f: impl FnOnce(&mut WarmStatus)) {
+        if let Some(status) = self.jobs.lock().unwrap().get_mut(&id) {
+            f(status);
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<WarmStatus> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    pub fn all(&self) -> Vec<WarmStatus> {
+        self.jobs.lock().unwrap().values().cloned().collect()
+    }
+}
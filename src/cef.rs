@@ -0,0 +1,79 @@
+use crate::config::config;
+use crate::{AnalysisResponse, RiskBucket};
+
+const VENDOR: &str = "ferrum";
+const PRODUCT: &str = "ferrum";
+const SIGNATURE_ID: &str = "url-analysis";
+
+// CEF severity (0-10) for a verdict; unknown verdicts use CEF's `Unknown` keyword.
+fn severity(bucket: RiskBucket) -> &'static str {
+    match bucket {
+        RiskBucket::Low => "3",
+        RiskBucket::Medium => "6",
+        RiskBucket::High => "9",
+        RiskBucket::Unknown => "Unknown",
+    }
+}
+
+// Header fields may not contain an unescaped `|` or `\`.
+fn escape_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+// Extension values escape `\` and `=`, and encode line breaks.
+fn escape_extension(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace("\r\n", "\\n")
+        .replace(['\r', '\n'], "\\n")
+}
+
+/// One CEF line for a successful analysis, or `None` for errors (which stay JSON).
+/// In public mode only the bucket-level verdict is carried.
+pub fn format_response(response: &AnalysisResponse) -> Option<String> {
+    let mut extension: Vec<(&str, String)> = Vec::new();
+    let (bucket, name) = match (&response.data, &response.verdict) {
+        (Some(data), _) => {
+            let bucket = data.bucket();
+            extension.push(("request", data.normalized_url.clone()));
+            extension.push(("cs1Label", "riskAssessment".to_string()));
+            extension.push(("cs1", bucket.assessment().to_string()));
+            if let Some(score) = data.risk_score {
+                extension.push(("cfp1Label", "riskScore".to_string()));
+                extension.push(("cfp1", format!("{:.3}", score)));
+            }
+            extension.push(("cs2Label", "signals".to_string()));
+            extension.push(("cs2", data.signals.join(",")));
+            extension.push(("cs3Label", "inputUrl".to_string()));
+            extension.push(("cs3", data.input_url.clone()));
+            extension.push(("cn1Label", "phishingDb".to_string()));
+            extension.push(("cn1", u8::from(data.is_phishing).to_string()));
+            (bucket, bucket.assessment())
+        }
+        (None, Some(verdict)) => {
+            extension.push(("request", response.url.clone()));
+            (RiskBucket::from_name(verdict.risk), verdict.message)
+        }
+        (None, None) => return None,
+    };
+    extension.push(("cs4Label", "riskBucket".to_string()));
+    extension.push(("cs4", bucket.as_str().to_string()));
+    if let Some(action) = response.action {
+        extension.push(("act", action.to_string()));
+    }
+    if let Some(name) = config().instance_name() {
+        extension.push(("dvchost", name.to_string()));
+    }
+    let extension: Vec<String> = extension.iter().map(|(key, value)| format!("{}={}", key, escape_extension(value))).collect();
+    Some(format!(
+        "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+        VENDOR,
+        PRODUCT,
+        env!("CARGO_PKG_VERSION"),
+        SIGNATURE_ID,
+        escape_header(name),
+        severity(bucket),
+        extension.join(" "),
+    ))
+}
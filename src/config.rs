@@ -0,0 +1,718 @@
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Make no network connections at all: no HTTP client is built, feeds are not fetched and every
+    // network-dependent check is skipped, leaving local lists and static heuristics.
+    pub offline: bool,
+    // Typo-tolerant (edit distance <= 1) matching against the deny list.
+    pub fuzzy_deny_match: bool,
+    // MaxMind country database; GeoIP checks are skipped when unset or unreadable.
+    pub geoip_db_path: Option<String>,
+    // Tranco/Umbrella-style `rank,domain` list; popular domains are discounted and near-misses flagged.
+    pub top_sites_path: Option<String>,
+    // How many of the most popular domains are compared for lookalikes.
+    pub top_sites_lookalike_limit: usize,
+    // Outbound requests (DNS, TLS probe, CT, ...) one analysis may make; later network checks are skipped.
+    pub max_outbound_requests: usize,
+    // Registrable domains, and the top sites up to this rank, skip network checks (DNS, TLS, CT).
+    pub fast_path_domains: Vec<String>,
+    pub fast_path_top_n: usize,
+    // Internal TLDs or domains (e.g. `corp`, `intranet.example.com`) whose hosts skip domain-structure signals.
+    pub internal_domains: Vec<String>,
+    // ISO country codes that raise `high_risk_geo` when the host resolves there.
+    pub high_risk_countries: Vec<String>,
+    // TLDs that raise `suspicious_tld`, each with the weight it adds (e.g. `zip` above `info`).
+    pub suspicious_tlds: HashMap<String, f32>,
+    // Extra deny rules matched alongside `filters/caught.json`; entries with a path are exact-URL rules.
+    pub deny_urls: Vec<String>,
+    // Start without `filters/caught.json` instead of refusing to.
+    pub local_deny_optional: bool,
+    // Outbound HTTP: DNS + TCP/TLS connect budget, and the whole-request budget.
+    pub connect_timeout_secs: u64,
+    pub request_timeout_secs: u64,
+    // Per-shortener risk tier; shorteners not listed use `standard`.
+    pub shortener_severity: HashMap<String, ShortenerSeverity>,
+    // Reuse analyses of the same normalized URL for this long; 0 disables the cache.
+    pub cache_ttl_secs: u64,
+    // Least recently used entries are evicted beyond this size.
+    pub cache_max_entries: usize,
+    // Low-risk verdicts are served from the cache without re-checking for this long, whatever
+    // `cache_ttl_secs` is, and survive deny-list refreshes that do not flag them; 0 disables.
+    pub trusted_cache_secs: u64,
+    // How often expired entries are swept from the cache.
+    pub cache_sweep_interval_secs: u64,
+    // Live cache entries are written here on shutdown and reloaded on startup.
+    pub cache_file: Option<String>,
+    // Canonicalize URL paths in `normalize_url` so equivalent URLs share cache and dedup entries.
+    pub canonicalize_paths: bool,
+    // With `canonicalize_paths`, also treat `/a/` and `/a` as the same URL.
+    pub strip_trailing_slash: bool,
+    // Number of recent analyses kept for `/stats`.
+    pub stats_window: usize,
+    // Other high-risk URLs in the stats window sharing a domain or host shape that raise
+    // `recently_trending_pattern`; 0 (the default) disables, since it makes scores depend on traffic history.
+    pub trending_min_count: usize,
+    pub server: ServerConfig,
+    // Lowest TLS version outbound requests may negotiate.
+    pub min_tls_version: MinTlsVersion,
+    // Handshake with https targets to report their TLS version and flag `weak_tls`.
+    pub tls_probe: bool,
+    // Fetch the start of medium-risk pages to look for a password form posting to another domain.
+    pub content_peek: bool,
+    pub content_peek_max_bytes: usize,
+    // Hash each origin's `/favicon.ico` and flag `known_phishing_favicon` on a listed hash.
+    pub favicon_check: bool,
+    pub favicon_max_bytes: usize,
+    // Shodan-style favicon hashes (signed mmh3) of known phishing kits.
+    pub known_phishing_favicons: Vec<i32>,
+    // How long an origin's favicon hash is reused.
+    pub favicon_cache_ttl_secs: u64,
+    // Follow the link's redirects (up to 5) and count a cross-domain redirect as shortened.
+    pub redirect_check: bool,
+    // Send the target one HEAD request and report its status, `target_unreachable` and `target_error_status`.
+    pub reachability_probe: bool,
+    pub reachability_timeout_secs: u64,
+    // Response size guards for the `analysis` map.
+    pub max_analysis_entries: usize,
+    pub max_analysis_value_len: usize,
+    // Largest number of URLs accepted by `/analyze/batch`.
+    pub max_batch_size: usize,
+    // Analyses run at once per `/analyze/batch` request.
+    pub batch_concurrency: usize,
+    // Largest accepted POST body in bytes; anything bigger is answered with 413.
+    pub max_body_bytes: usize,
+    // Per-route overrides of `max_body_bytes`, keyed by path (e.g. `/analyze/text`).
+    pub max_body_bytes_by_route: HashMap<String, usize>,
+    // Whole-request budget for any handler, answered with 504 when exceeded; 0 disables it.
+    pub handler_timeout_secs: u64,
+    // Per-route overrides of `handler_timeout_secs`, keyed by path.
+    pub handler_timeout_secs_by_route: HashMap<String, u64>,
+    // `score` reports risk only; `enforce` also answers 403 at or above `block_threshold`.
+    pub mode: Mode,
+    pub block_threshold: f32,
+    // Signal identifiers (as in `data.signals`) that never score and are left out of responses.
+    pub suppressed_signals: Vec<String>,
+    // Named alternative scorings returned side by side via `/analyze?profiles=`.
+    pub risk_profiles: HashMap<String, RiskProfile>,
+    // Numeric severity reported for each risk bucket (`low`, `medium`, `high`, `unknown`).
+    pub severity_levels: HashMap<String, u8>,
+    // Score range for URLs found in a deny list, and the highest score anything else may reach.
+    pub phishing_score_floor: f32,
+    pub phishing_score_ceiling: f32,
+    pub non_phishing_score_ceiling: f32,
+    // Hosts (and their subdomains) whose redirect query parameters are benign, e.g. `google.com/url?q=`.
+    pub redirect_allow_hosts: Vec<String>,
+    // Free hosting and dynamic-DNS parents whose tenant subdomains raise `shared_hosting`.
+    pub shared_hosting_domains: Vec<String>,
+    // Which repeated query parameters raise `parameter_pollution`.
+    pub parameter_pollution: PollutionScope,
+    // What to answer when every network-dependent check failed.
+    pub on_network_failure: NetworkFailurePolicy,
+    // Paths with more segments than this raise `deep_path`.
+    pub max_path_depth: usize,
+    // Queries with more parameters or characters than these raise `excessive_query`.
+    pub max_query_params: usize,
+    pub max_query_length: usize,
+    // File extensions that raise `dangerous_download` when a URL path ends in one.
+    pub dangerous_extensions: Vec<String>,
+    // Flag `numeric_or_hex_host` for hosts written as numbers or hex, e.g. `0x1f2e3d4c`.
+    pub numeric_host_check: bool,
+    // Shortest all-hex label (containing a digit) treated as hex-like, e.g. `deadbeef1234`.
+    pub hex_label_min_len: usize,
+    // Flag `improbable_ngrams` for registrable labels whose letter pairs average below
+    // `ngram_threshold` (mean log10 bigram frequency), the mark of DGA-generated names.
+    pub ngram_check: bool,
+    pub ngram_threshold: f32,
+    // Labels with fewer letters than this are too short to score.
+    pub ngram_min_length: usize,
+    // Host labels that fake protocol or security cues, e.g. `https` in `https-paypal.evil.com`.
+    // A token ending in `-` only matches as a label prefix (`www-` matches `www-paypal`, not `www`).
+    pub protocol_host_tokens: Vec<String>,
+    // Brand names commonly impersonated by phishing.
+    pub protected_brands: Vec<String>,
+    // Characters and the letters each can stand in for, e.g. `"1": "li"`, for `leetspeak_brand`.
+    pub leetspeak_substitutions: HashMap<String, String>,
+    // Legitimate registrable domains per protected brand; brands not listed default to `<brand>.com`.
+    pub brand_domains: HashMap<String, Vec<String>>,
+    // Query crt.sh for the registrable domain and flag `very_recent_cert_issuance`.
+    pub ct_check: bool,
+    pub ct_timeout_secs: u64,
+    // A certificate issued within this many days counts as very recent.
+    pub ct_recent_days: i64,
+    // How long a domain's CT summary is reused.
+    pub ct_cache_ttl_secs: u64,
+    // Look up SPF and DMARC for `mailto:` domains and flag `weak_email_auth`.
+    pub email_auth_check: bool,
+    pub email_auth_timeout_secs: u64,
+    pub email_auth_cache_ttl_secs: u64,
+    // `ip` or `ip:port` of the DNS resolver; the first `/etc/resolv.conf` nameserver when unset.
+    pub email_auth_resolver: Option<String>,
+    // Analyst-defined regex features, compiled once at startup.
+    pub custom_features: Vec<CustomFeatureRule>,
+    // AND rules over signals, scored after the individual signals.
+    pub composite_rules: Vec<CompositeRule>,
+    // JSON file of boolean detection rules (`{"rules": [...]}`), evaluated after every other signal.
+    pub detection_rules_file: Option<String>,
+    // Withhold detailed signals from callers without a valid API key.
+    pub public_mode: bool,
+    // Default for `compact=` on `/analyze`: omit null, false and empty fields.
+    pub compact_responses: bool,
+    // Most signals named in an `explain=text` summary.
+    pub explain_max_signals: usize,
+    // Keys accepted via `X-API-Key` or `Authorization: Bearer`.
+    pub api_keys: Vec<String>,
+    // SQLite file recording every analysis, needed by `/history/rescan`.
+    pub history_db_path: Option<String>,
+    // External reputation services consulted on every analysis that may use the network.
+    pub reputation_providers: Vec<ReputationProviderConfig>,
+    // Receives a POST for every analysis scoring at or above `webhook_threshold`.
+    pub webhook_url: Option<String>,
+    pub webhook_threshold: f32,
+    // Repeat alerts for the same URL are dropped within this window.
+    pub webhook_cooldown_secs: u64,
+    // Identify this deployment in `/health` and the `X-Served-By` / `X-Environment` headers.
+    pub instance_name: Option<String>,
+    pub environment: Option<String>,
+    // Run the startup self-test (also enabled by `--self-test`).
+    pub self_test: bool,
+    // Deny-list feeds fetched at startup and merged into one list.
+    pub feeds: Vec<FeedConfig>,
+    // Seconds between background feed refreshes; 0 loads the feeds only at startup.
+    pub feed_refresh_interval_secs: u64,
+    // Feed data older than this is stale: responses say `feed_stale` and, optionally, `/readyz` fails.
+    pub max_feed_age_secs: Option<u64>,
+    pub stale_feed_fails_readiness: bool,
+    // Over this many recent seconds, at least `quiet_detection_min_samples` analyses with none
+    // flagged means detection has likely broken; 0 disables the check.
+    pub quiet_detection_window_secs: u64,
+    pub quiet_detection_min_samples: usize,
+    // Answer `/readyz` with 503 while detection is quiet, rather than only logging it.
+    pub quiet_detection_fails_readiness: bool,
+    // Sanity bounds for a fetched feed; payloads outside them are rejected and the previous data kept.
+    pub feed_max_bytes: usize,
+    pub feed_min_entries: usize,
+}
+
+// A scoring variant computed from the same signals as the default verdict.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RiskProfile {
+    // Weight overrides keyed by signal identifier (as listed in `data.signals`); others keep their default.
+    pub weights: HashMap<String, f32>,
+    // Lowest scores bucketed as medium and high risk.
+    pub medium_threshold: f32,
+    pub high_threshold: f32,
+}
+
+impl Default for RiskProfile {
+    fn default() -> Self {
+        RiskProfile {
+            weights: HashMap::new(),
+            medium_threshold: 0.4,
+            high_threshold: 0.7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeedConfig {
+    pub url: String,
+    pub format: FeedFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    // polkadot-js/phishing JSON with a `deny` array.
+    Polkadot,
+    // URLhaus CSV export.
+    Urlhaus,
+    // OpenPhish plaintext, one URL per line.
+    Openphish,
+}
+
+fn default_feeds() -> Vec<FeedConfig> {
+    vec![FeedConfig {
+        url: "https://raw.githubusercontent.com/polkadot-js/phishing/master/all.json".to_string(),
+        format: FeedFormat::Polkadot,
+    }]
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReputationProviderConfig {
+    pub name: String,
+    pub endpoint: String,
+    // Multiplies the provider's 0.0-1.0 score before it is added to the risk score.
+    pub weight: f32,
+    // Sent as `Authorization: Bearer <api_key>`.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    // Falls back to `request_timeout_secs`.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomFeatureRule {
+    pub name: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub target: RuleTarget,
+    pub weight: f32,
+}
+
+// Adds `weight` on top of the individual weights when every one of `signals` fired.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompositeRule {
+    pub name: String,
+    pub signals: Vec<String>,
+    pub weight: f32,
+}
+
+// Which part of the normalized URL a rule's regex is matched against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleTarget {
+    Host,
+    Path,
+    #[default]
+    Url,
+}
+
+fn default_protected_brands() -> Vec<String> {
+    ["paypal", "apple", "google", "microsoft", "amazon", "facebook", "instagram", "netflix", "linkedin", "dropbox"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_protocol_host_tokens() -> Vec<String> {
+    ["http", "https", "www-", "secure-login", "ssl-secure"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_leetspeak_substitutions() -> HashMap<String, String> {
+    [("0", "o"), ("1", "li"), ("3", "e"), ("4", "a"), ("$", "s")]
+        .iter()
+        .map(|(from, to)| (from.to_string(), to.to_string()))
+        .collect()
+}
+
+fn default_dangerous_extensions() -> Vec<String> {
+    ["exe", "scr", "apk", "hta", "msi", "bat", "cmd", "vbs", "jar", "ps1", "dll", "lnk", "iso"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_redirect_allow_hosts() -> Vec<String> {
+    ["google.com", "safelinks.protection.outlook.com", "l.facebook.com", "lm.facebook.com", "urldefense.com", "web.archive.org"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+// Services that hand out subdomains for free, most of them on the PSL's private section.
+fn default_shared_hosting_domains() -> Vec<String> {
+    [
+        "000webhostapp.com", "blogspot.com", "ddns.net", "duckdns.org", "firebaseapp.com", "github.io", "glitch.me",
+        "herokuapp.com", "hopto.org", "netlify.app", "ngrok-free.app", "ngrok.io", "no-ip.org", "pages.dev", "repl.co",
+        "vercel.app", "web.app", "weebly.com", "wixsite.com", "workers.dev",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+// The original seven keep their 0.2; free and new gTLDs dominated by abuse weigh more.
+fn default_suspicious_tlds() -> HashMap<String, f32> {
+    [
+        ("xyz", 0.2), ("top", 0.2), ("club", 0.2), ("online", 0.2), ("site", 0.2), ("info", 0.2), ("biz", 0.2),
+        ("gq", 0.3), ("tk", 0.3), ("ml", 0.3), ("cf", 0.3), ("ga", 0.3), ("rest", 0.3), ("icu", 0.3),
+        ("cfd", 0.3), ("sbs", 0.3), ("cyou", 0.3), ("buzz", 0.3), ("monster", 0.3), ("bond", 0.3),
+        ("zip", 0.5), ("mov", 0.5),
+    ]
+    .into_iter()
+    .map(|(tld, weight)| (tld.to_string(), weight))
+    .collect()
+}
+
+// Pasted email bodies for `/analyze/text` run larger than JSON URL lists.
+fn default_max_body_bytes_by_route() -> HashMap<String, usize> {
+    HashMap::from([("/analyze/text".to_string(), 10 * 1024 * 1024)])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Score,
+    Enforce,
+}
+
+// Every bucket `severity_levels` must map.
+const SEVERITY_BUCKETS: [&str; 4] = ["low", "medium", "high", "unknown"];
+
+fn default_brand_domains() -> HashMap<String, Vec<String>> {
+    [
+        ("paypal", &["paypal.com", "paypal.me"][..]),
+        ("apple", &["apple.com", "icloud.com"]),
+        ("google", &["google.com", "youtube.com", "gmail.com"]),
+        ("microsoft", &["microsoft.com", "live.com", "office.com", "outlook.com", "microsoftonline.com"]),
+        ("amazon", &["amazon.com", "amazon.co.uk", "amazon.de", "amazonaws.com"]),
+        ("facebook", &["facebook.com", "fb.com", "messenger.com"]),
+    ]
+    .iter()
+    .map(|(brand, domains)| (brand.to_string(), domains.iter().map(|d| d.to_string()).collect()))
+    .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkFailurePolicy {
+    // Score from local signals and mark the result `degraded`.
+    Degrade,
+    // Answer 503 instead of a score.
+    Fail,
+    // Return a null score with assessment `unknown`.
+    Neutral,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PollutionScope {
+    Off,
+    // Only redirect-style parameters such as `url` or `next`.
+    Redirect,
+    // Any parameter; arrays like `?tag=a&tag=b` will fire too.
+    All,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum MinTlsVersion {
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub http1: bool,
+    pub http2: bool,
+    // HTTP/1.1 persistent connections.
+    pub keep_alive: bool,
+    // HTTP/2 PING interval; unset disables keep-alive pings.
+    pub http2_keep_alive_interval_secs: Option<u64>,
+    // How long to wait for a PING ack before closing the connection.
+    pub http2_keep_alive_timeout_secs: u64,
+    pub http2_max_concurrent_streams: u32,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            http1: true,
+            http2: true,
+            keep_alive: true,
+            http2_keep_alive_interval_secs: None,
+            http2_keep_alive_timeout_secs: 20,
+            http2_max_concurrent_streams: 256,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.http1 && !self.http2 {
+            return Err("server: at least one of http1 or http2 must be enabled".to_string());
+        }
+        if self.http2_max_concurrent_streams == 0 {
+            return Err("server: http2_max_concurrent_streams must be greater than 0".to_string());
+        }
+        if self.http2_keep_alive_interval_secs == Some(0) || self.http2_keep_alive_timeout_secs == 0 {
+            return Err("server: HTTP/2 keep-alive interval and timeout must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShortenerSeverity {
+    Benign,
+    Low,
+    Standard,
+    High,
+}
+
+impl ShortenerSeverity {
+    pub fn weight(&self) -> f32 {
+        match self {
+            ShortenerSeverity::Benign => 0.0,
+            ShortenerSeverity::Low => 0.1,
+            ShortenerSeverity::Standard => 0.3,
+            ShortenerSeverity::High => 0.5,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShortenerSeverity::Benign => "benign",
+            ShortenerSeverity::Low => "low",
+            ShortenerSeverity::Standard => "standard",
+            ShortenerSeverity::High => "high",
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            offline: false,
+            fuzzy_deny_match: false,
+            geoip_db_path: None,
+            top_sites_path: None,
+            top_sites_lookalike_limit: 10_000,
+            max_outbound_requests: 15,
+            fast_path_domains: Vec::new(),
+            fast_path_top_n: 0,
+            internal_domains: Vec::new(),
+            high_risk_countries: Vec::new(),
+            suspicious_tlds: default_suspicious_tlds(),
+            deny_urls: Vec::new(),
+            local_deny_optional: false,
+            connect_timeout_secs: 3,
+            request_timeout_secs: 10,
+            shortener_severity: ["youtu.be", "amzn.to", "bit.ly"]
+                .iter()
+                .map(|s| (s.to_string(), ShortenerSeverity::Low))
+                .collect(),
+            cache_ttl_secs: 300,
+            trusted_cache_secs: 0,
+            cache_max_entries: 10_000,
+            cache_sweep_interval_secs: 60,
+            cache_file: None,
+            canonicalize_paths: false,
+            strip_trailing_slash: false,
+            stats_window: 1000,
+            trending_min_count: 0,
+            server: ServerConfig::default(),
+            min_tls_version: MinTlsVersion::Tls12,
+            tls_probe: false,
+            content_peek: false,
+            content_peek_max_bytes: 64 * 1024,
+            favicon_check: false,
+            favicon_max_bytes: 100 * 1024,
+            redirect_check: true,
+            reachability_probe: false,
+            reachability_timeout_secs: 3,
+            known_phishing_favicons: Vec::new(),
+            favicon_cache_ttl_secs: 3600,
+            max_analysis_entries: 64,
+            max_analysis_value_len: 1024,
+            max_batch_size: 1000,
+            batch_concurrency: 16,
+            max_body_bytes: 5 * 1024 * 1024,
+            max_body_bytes_by_route: default_max_body_bytes_by_route(),
+            handler_timeout_secs: 30,
+            handler_timeout_secs_by_route: HashMap::new(),
+            mode: Mode::Score,
+            block_threshold: 0.7,
+            suppressed_signals: Vec::new(),
+            risk_profiles: HashMap::new(),
+            severity_levels: [("low", 1), ("medium", 5), ("high", 8), ("unknown", 0)]
+                .iter()
+                .map(|(bucket, level)| (bucket.to_string(), *level))
+                .collect(),
+            phishing_score_floor: 0.9,
+            phishing_score_ceiling: 1.0,
+            non_phishing_score_ceiling: 1.0,
+            redirect_allow_hosts: default_redirect_allow_hosts(),
+            shared_hosting_domains: default_shared_hosting_domains(),
+            parameter_pollution: PollutionScope::Redirect,
+            on_network_failure: NetworkFailurePolicy::Degrade,
+            max_path_depth: 6,
+            max_query_params: 30,
+            max_query_length: 2048,
+            dangerous_extensions: default_dangerous_extensions(),
+            numeric_host_check: true,
+            hex_label_min_len: 8,
+            ngram_check: true,
+            ngram_threshold: -3.3,
+            ngram_min_length: 6,
+            protocol_host_tokens: default_protocol_host_tokens(),
+            protected_brands: default_protected_brands(),
+            leetspeak_substitutions: default_leetspeak_substitutions(),
+            brand_domains: default_brand_domains(),
+            ct_check: false,
+            ct_timeout_secs: 5,
+            ct_recent_days: 3,
+            ct_cache_ttl_secs: 3600,
+            email_auth_check: false,
+            email_auth_timeout_secs: 3,
+            email_auth_cache_ttl_secs: 3600,
+            email_auth_resolver: None,
+            custom_features: Vec::new(),
+            composite_rules: Vec::new(),
+            detection_rules_file: None,
+            public_mode: false,
+            compact_responses: false,
+            explain_max_signals: 3,
+            api_keys: Vec::new(),
+            history_db_path: None,
+            reputation_providers: Vec::new(),
+            webhook_url: None,
+            webhook_threshold: 0.7,
+            webhook_cooldown_secs: 3600,
+            instance_name: None,
+            environment: None,
+            self_test: false,
+            feeds: default_feeds(),
+            feed_refresh_interval_secs: 3600,
+            max_feed_age_secs: None,
+            stale_feed_fails_readiness: false,
+            quiet_detection_window_secs: 0,
+            quiet_detection_min_samples: 500,
+            quiet_detection_fails_readiness: false,
+            feed_max_bytes: 64 * 1024 * 1024,
+            feed_min_entries: 1,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, Box<dyn std::error::Error + Send + Sync>> {
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                log::info!("No config file at {}, using defaults", path);
+                Ok(Config::default())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.server.validate()?;
+        if self.cache_ttl_secs > 0 && self.cache_sweep_interval_secs == 0 {
+            return Err("cache_sweep_interval_secs must be greater than 0".to_string());
+        }
+        if self.trusted_cache_secs > 0 && self.cache_ttl_secs == 0 {
+            return Err("trusted_cache_secs needs the cache enabled (cache_ttl_secs > 0)".to_string());
+        }
+        if self.cache_file.is_some() && self.cache_ttl_secs == 0 {
+            return Err("cache_file needs the cache enabled (cache_ttl_secs > 0)".to_string());
+        }
+        if self.quiet_detection_window_secs > 0 && !(1..=self.stats_window).contains(&self.quiet_detection_min_samples) {
+            return Err("quiet_detection_min_samples must be between 1 and stats_window".to_string());
+        }
+        if let Some((tld, _)) = self.suspicious_tlds.iter().find(|(_, weight)| !weight.is_finite()) {
+            return Err(format!("suspicious_tlds: weight for {} must be a number", tld));
+        }
+        if !self.ngram_threshold.is_finite() {
+            return Err("ngram_threshold must be a number".to_string());
+        }
+        let bounds = [self.phishing_score_floor, self.phishing_score_ceiling, self.non_phishing_score_ceiling];
+        if bounds.iter().any(|b| !(0.0..=1.0).contains(b)) {
+            return Err("score floor and ceilings must be between 0.0 and 1.0".to_string());
+        }
+        if self.phishing_score_floor > self.phishing_score_ceiling {
+            return Err("phishing_score_floor must not exceed phishing_score_ceiling".to_string());
+        }
+        for (name, profile) in &self.risk_profiles {
+            if !(0.0..=profile.high_threshold).contains(&profile.medium_threshold) || profile.high_threshold > 1.0 {
+                return Err(format!("risk profile {}: thresholds must satisfy 0.0 <= medium <= high <= 1.0", name));
+            }
+        }
+        if let Some(bucket) = self.severity_levels.keys().find(|b| !SEVERITY_BUCKETS.contains(&b.as_str())) {
+            return Err(format!("severity_levels: unknown bucket {} (expected {})", bucket, SEVERITY_BUCKETS.join(", ")));
+        }
+        if let Some(bucket) = SEVERITY_BUCKETS.iter().find(|b| !self.severity_levels.contains_key(**b)) {
+            return Err(format!("severity_levels: no level for {}", bucket));
+        }
+        Ok(())
+    }
+
+    pub fn severity_level(&self, bucket: &str) -> u8 {
+        self.severity_levels.get(bucket).copied().unwrap_or_default()
+    }
+
+    // Blank values count as unset so nothing empty is ever echoed.
+    pub fn instance_name(&self) -> Option<&str> {
+        self.instance_name.as_deref().map(str::trim).filter(|s| !s.is_empty())
+    }
+
+    pub fn environment(&self) -> Option<&str> {
+        self.environment.as_deref().map(str::trim).filter(|s| !s.is_empty())
+    }
+
+    // Accepts feature-style names too, so `has_dash_in_domain` suppresses `dash_in_domain`.
+    pub fn is_suppressed(&self, signal: &str) -> bool {
+        self.suppressed_signals.iter().any(|s| s.strip_prefix("has_").unwrap_or(s) == signal)
+    }
+
+    pub fn max_body_bytes(&self, route: &str) -> usize {
+        self.max_body_bytes_by_route.get(route).copied().unwrap_or(self.max_body_bytes)
+    }
+
+    // Configured checks that need the network, by name; all of them are unavailable offline.
+    pub fn network_checks(&self) -> Vec<&'static str> {
+        [
+            ("feeds", !self.feeds.is_empty()),
+            ("geoip", self.geoip_db_path.is_some()),
+            ("tls_probe", self.tls_probe),
+            ("ct", self.ct_check),
+            ("email_auth", self.email_auth_check),
+            ("favicon", self.favicon_check),
+            ("redirect", self.redirect_check),
+            ("reachability", self.reachability_probe),
+            ("reputation", !self.reputation_providers.is_empty()),
+            ("content_peek", self.content_peek),
+            ("webhook", self.webhook_url.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name)
+        .collect()
+    }
+
+    pub fn handler_timeout_secs(&self, route: &str) -> u64 {
+        self.handler_timeout_secs_by_route.get(route).copied().unwrap_or(self.handler_timeout_secs)
+    }
+
+    pub fn brand_domains(&self, brand: &str) -> Vec<String> {
+        match self.brand_domains.iter().find(|(name, _)| name.eq_ignore_ascii_case(brand)) {
+            Some((_, domains)) => domains.iter().map(|d| d.to_lowercase()).collect(),
+            None => vec![format!("{}.com", brand.to_lowercase())],
+        }
+    }
+
+    pub fn shortener_severity(&self, shortener: &str) -> ShortenerSeverity {
+        self.shortener_severity
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(shortener))
+            .map(|(_, severity)| *severity)
+            .unwrap_or(ShortenerSeverity::Standard)
+    }
+}
+
+pub fn init(config: Config) {
+    if CONFIG.set(config).is_err() {
+        log::warn!("Config already initialized, ignoring");
+    }
+}
+
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
@@ -0,0 +1,89 @@
+use chrono::NaiveDateTime;
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::outbound;
+
+const CRT_SH_URL: &str = "https://crt.sh/";
+const MAX_CACHED_DOMAINS: usize = 10_000;
+// Failed lookups are remembered briefly so an outage doesn't cost every request a timeout.
+const FAILURE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+struct CrtShEntry {
+    id: u64,
+    not_before: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CtSummary {
+    pub distinct_certs: usize,
+    // Validity start of the most recently issued certificate.
+    pub newest_issuance: Option<NaiveDateTime>,
+}
+
+// Certificate transparency lookups via crt.sh, memoized per registrable domain.
+pub struct CtLookup {
+    client: Client,
+    timeout: Duration,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (Instant, Option<CtSummary>)>>,
+}
+
+impl CtLookup {
+    pub fn new(client: Client, timeout: Duration, ttl: Duration) -> CtLookup {
+        CtLookup {
+            client,
+            timeout,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Certificates logged for `domain`; `None` when crt.sh is slow, down or returns garbage.
+    pub async fn summary(&self, domain: &str) -> Option<CtSummary> {
+        if let Some((expires_at, cached)) = self.cache.lock().unwrap().get(domain)
+            && *expires_at > Instant::now()
+        {
+            return cached.clone();
+        }
+        let (summary, ttl) = match self.fetch(domain).await {
+            Ok(summary) => (Some(summary), self.ttl),
+            Err(e) => {
+                log::debug!("CT lookup failed for {}: {}", domain, e);
+                (None, FAILURE_TTL)
+            }
+        };
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_DOMAINS {
+            cache.clear();
+        }
+        cache.insert(domain.to_string(), (Instant::now() + ttl, summary.clone()));
+        summary
+    }
+
+    async fn fetch(&self, domain: &str) -> Result<CtSummary, Box<dyn std::error::Error + Send + Sync>> {
+        let request = async {
+            self.client
+                .get(CRT_SH_URL)
+                .query(&[("q", domain), ("output", "json")])
+                .timeout(self.timeout)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Vec<CrtShEntry>>()
+                .await
+        };
+        let entries = outbound::track("ct", &outbound::host_of(CRT_SH_URL), request).await?;
+        // crt.sh lists a certificate once per matching identity, so count ids rather than rows.
+        let distinct_certs = entries.iter().map(|e| e.id).collect::<HashSet<_>>().len();
+        let newest_issuance = entries
+            .iter()
+            .filter_map(|e| NaiveDateTime::parse_from_str(&e.not_before, "%Y-%m-%dT%H:%M:%S").ok())
+            .max();
+        Ok(CtSummary { distinct_certs, newest_issuance })
+    }
+}
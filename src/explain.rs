@@ -0,0 +1,90 @@
+use crate::RiskBucket;
+use crate::config::config;
+
+// Signals in the order they are worth mentioning, each as a clause completing "This link ...".
+// Signals missing here (custom, composite and detection rules) come last, by name.
+const PHRASES: &[(&str, &str)] = &[
+    ("phishing_db", "is listed in a phishing database"),
+    ("redirect_target_phishing", "forwards to a site listed in a phishing database"),
+    ("fuzzy_phishing_match", "closely resembles a known phishing domain"),
+    ("known_phishing_favicon", "shows the icon of a known phishing kit"),
+    ("cross_origin_password_form", "asks for a password that is sent to another site"),
+    ("punycode_brand_spoof", "imitates a well-known brand with lookalike characters"),
+    ("rtl_in_host", "hides its real address with right-to-left characters"),
+    ("encoded_host", "disguises its address with percent-encoding"),
+    ("non_nfc_host", "spells its address with unusual Unicode sequences"),
+    ("leetspeak_brand", "spells a well-known brand with numbers or symbols"),
+    ("ip_host_with_brand_path", "points to a bare IP address while naming a brand"),
+    ("embedded_dangerous_uri", "carries an embedded script or data link"),
+    ("dangerous_download", "downloads a file that can run on your device"),
+    ("display_mismatch", "goes somewhere other than its text shows"),
+    ("top_site_lookalike", "looks like the address of a popular site"),
+    ("brand_context_mismatch", "names a brand on a site that does not belong to it"),
+    ("very_recent_cert_issuance", "got its security certificate only days ago"),
+    ("open_redirect_param", "forwards you to another site"),
+    ("embedded_url_in_path", "wraps another site's address in its path"),
+    ("parameter_pollution", "repeats a parameter with conflicting values"),
+    ("shortened", "uses a URL shortener that hides where it leads"),
+    ("shared_hosting", "is hosted on a free hosting or dynamic-DNS service"),
+    ("at_symbol", "contains an @ that can disguise the real address"),
+    ("ip_address", "uses a numeric IP address instead of a name"),
+    ("numeric_or_hex_host", "writes its address as numbers"),
+    ("protocol_token_in_host", "puts \"http\" or \"www\" inside its domain name"),
+    ("improbable_ngrams", "has a random-looking domain name"),
+    ("recently_trending_pattern", "resembles other risky links seen recently"),
+    ("suspicious_tld", "uses a domain ending often abused for phishing"),
+    ("weak_tls", "uses outdated encryption"),
+    ("weak_email_auth", "uses an email domain that is easy to impersonate"),
+    ("high_risk_geo", "is hosted in a high-risk region"),
+    ("deep_path_with_brand", "buries a brand name deep in a long address"),
+    ("deep_path", "has an unusually deep path"),
+    ("excessive_query", "carries an unusually long query string"),
+    ("double_slash", "contains an unexpected double slash"),
+    ("multiple_subdomains", "has many subdomains"),
+    ("dash_in_domain", "has dashes in its domain name"),
+    ("target_unreachable", "could not be reached"),
+    ("target_error_status", "answers with an error status"),
+    ("in_top_sites", "is on a popular, well-established site"),
+];
+
+fn lead(bucket: RiskBucket) -> &'static str {
+    match bucket {
+        RiskBucket::High => "High risk, likely phishing.",
+        RiskBucket::Medium => "Medium risk.",
+        RiskBucket::Low => "Low risk.",
+        RiskBucket::Unknown => "No verdict could be reached.",
+    }
+}
+
+/// A short paragraph for end users, e.g. "High risk, likely phishing. This link is listed in a phishing
+/// database and uses a URL shortener that hides where it leads." At most `explain_max_signals`
+/// signals are named.
+pub fn explain(signals: &[String], bucket: RiskBucket) -> String {
+    let mut clauses: Vec<String> = PHRASES
+        .iter()
+        .filter(|(signal, _)| signals.iter().any(|s| s == signal))
+        .map(|(_, phrase)| phrase.to_string())
+        .collect();
+    clauses.extend(
+        signals
+            .iter()
+            .filter(|s| !PHRASES.iter().any(|(signal, _)| signal == s))
+            .map(|s| format!("matches the {} rule", s)),
+    );
+    if clauses.is_empty() {
+        let none = if bucket == RiskBucket::Unknown { "" } else { " No warning signs were found." };
+        return format!("{}{}", lead(bucket), none);
+    }
+    let more = clauses.len().saturating_sub(config().explain_max_signals);
+    clauses.truncate(config().explain_max_signals);
+    let listed = match clauses.as_slice() {
+        [] => return lead(bucket).to_string(),
+        [only] => only.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    };
+    match more {
+        0 => format!("{} This link {}.", lead(bucket), listed),
+        1 => format!("{} This link {}, plus 1 other sign.", lead(bucket), listed),
+        _ => format!("{} This link {}, plus {} other signs.", lead(bucket), listed, more),
+    }
+}
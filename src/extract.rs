@@ -0,0 +1,93 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use url::Url;
+
+// Explicit http(s) URLs, or bare `host.tld[:port][/path]` candidates that are filtered further below.
+static URL_CANDIDATE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?i)https?://[^\s<>"'`]+|(?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.)+[a-z][a-z0-9-]{1,62}(?::\d{1,5})?(?:/[^\s<>"'`]*)?"#,
+    )
+    .unwrap()
+});
+
+/// URLs found in free text, in order of first appearance, with surrounding punctuation removed.
+/// Bare domains must end in a known public suffix, which skips version numbers; those whose TLD is
+/// also a common file extension (`install.sh`, `main.rs`) need a `www.` prefix or a path.
+pub fn extract_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for m in URL_CANDIDATE.find_iter(text) {
+        // Part of an email address, a file path or a longer token.
+        if let Some(prev) = text[..m.start()].chars().next_back()
+            && (prev.is_alphanumeric() || matches!(prev, '@' | '.' | '/' | '\\' | '-' | '_'))
+        {
+            continue;
+        }
+        let candidate = trim_trailing(m.as_str());
+        let has_scheme = candidate.to_ascii_lowercase().starts_with("http");
+        if !has_scheme && !is_plausible_bare_domain(candidate) {
+            continue;
+        }
+        if !urls.iter().any(|u| u == candidate) {
+            urls.push(candidate.to_string());
+        }
+    }
+    urls
+}
+
+// Strips sentence punctuation and closing brackets that have no opening partner in the URL.
+fn trim_trailing(candidate: &str) -> &str {
+    let mut end = candidate.len();
+    loop {
+        let current = &candidate[..end];
+        let Some(last) = current.chars().next_back() else {
+            break;
+        };
+        let unbalanced = |open: char, close: char| {
+            last == close && current.matches(open).count() < current.matches(close).count()
+        };
+        if matches!(last, '.' | ',' | ';' | ':' | '!' | '?' | '*') || unbalanced('(', ')') || unbalanced('[', ']') {
+            end -= last.len_utf8();
+        } else {
+            break;
+        }
+    }
+    &candidate[..end]
+}
+
+// Real TLDs that in pasted text are far more often file extensions.
+const FILE_EXTENSION_TLDS: &[&str] = &["java", "md", "mk", "ml", "mov", "pl", "pm", "ps", "py", "rs", "sh", "so", "zip"];
+
+fn is_plausible_bare_domain(candidate: &str) -> bool {
+    let Some(host) = Url::parse(&format!("https://{}", candidate)).ok().and_then(|u| u.host_str().map(str::to_string)) else {
+        return false;
+    };
+    let Some(tld) = host.rsplit('.').next() else {
+        return false;
+    };
+    if tld.chars().all(|c| c.is_ascii_digit()) || !psl::suffix(host.as_bytes()).is_some_and(|s| s.is_known()) {
+        return false;
+    }
+    !FILE_EXTENSION_TLDS.contains(&tld) || host.starts_with("www.") || candidate.contains('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_numbers_and_file_names_are_not_urls() {
+        assert!(extract_urls("upgraded to 1.2.3 (was v2.10.0)").is_empty());
+        assert!(extract_urls("run install.sh then edit config.py, main.rs, setup.py, README.md and archive.zip").is_empty());
+        assert_eq!(extract_urls("get www.install.sh or bun.sh/install"), ["www.install.sh", "bun.sh/install"]);
+    }
+
+    #[test]
+    fn parentheses_and_trailing_punctuation_are_trimmed() {
+        assert_eq!(extract_urls("(see https://en.wikipedia.org/wiki/Foo_(bar))."), ["https://en.wikipedia.org/wiki/Foo_(bar)"]);
+        assert_eq!(extract_urls("Login here (example.com)!"), ["example.com"]);
+        assert_eq!(
+            extract_urls("Visit example.com/login. Or https://a.example.org/x?y=1, then example.com/login;"),
+            ["example.com/login", "https://a.example.org/x?y=1"]
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use url::Url;
+
+use crate::peek::{self, Fetched};
+
+const MAX_CACHED_HOSTS: usize = 10_000;
+// Failed fetches are remembered briefly so a dead host doesn't cost every request a timeout.
+const FAILURE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Favicon {
+    // Shodan-style hash of the icon, as listed in `known_phishing_favicons`.
+    Hash(i32),
+    // The host resolved to a non-public address; nothing was fetched.
+    Blocked(IpAddr),
+}
+
+// `/favicon.ico` hashes, memoized per origin.
+pub struct FaviconLookup {
+    timeout: Duration,
+    max_bytes: usize,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (Instant, Option<Favicon>)>>,
+}
+
+impl FaviconLookup {
+    pub fn new(timeout: Duration, max_bytes: usize, ttl: Duration) -> FaviconLookup {
+        FaviconLookup {
+            timeout,
+            max_bytes,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Favicon of the URL's origin; `None` when there is none or it could not be fetched.
+    pub async fn lookup(&self, url: &Url) -> Option<Favicon> {
+        let mut icon_url = url.clone();
+        icon_url.set_path("/favicon.ico");
+        icon_url.set_query(None);
+        icon_url.set_fragment(None);
+        let _ = icon_url.set_username("");
+        let _ = icon_url.set_password(None);
+        let origin = icon_url.origin().ascii_serialization();
+        if let Some((expires_at, cached)) = self.cache.lock().unwrap().get(&origin)
+            && *expires_at > Instant::now()
+        {
+            return *cached;
+        }
+        let (favicon, ttl) = match peek::fetch_public(&icon_url, self.max_bytes, self.timeout, "favicon").await {
+            Ok(Fetched::Body(body)) if !body.is_empty() => (Some(Favicon::Hash(hash(&body))), self.ttl),
+            Ok(Fetched::Body(_)) => (None, self.ttl),
+            Ok(Fetched::Blocked(ip)) => (Some(Favicon::Blocked(ip)), self.ttl),
+            Err(e) => {
+                log::debug!("Favicon fetch failed for {}: {}", origin, e);
+                (None, FAILURE_TTL)
+            }
+        };
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_HOSTS {
+            cache.clear();
+        }
+        cache.insert(origin, (Instant::now() + ttl, favicon));
+        favicon
+    }
+}
+
+// The hash Shodan reports as `http.favicon.hash`: MurmurHash3 (x86, 32-bit, seed 0) of the
+// icon's base64 encoding with a newline after every 76 characters and at the end.
+pub fn hash(icon: &[u8]) -> i32 {
+    let encoded = STANDARD.encode(icon);
+    let mut wrapped = Vec::with_capacity(encoded.len() + encoded.len() / 76 + 1);
+    for line in encoded.as_bytes().chunks(76) {
+        wrapped.extend_from_slice(line);
+        wrapped.push(b'\n');
+    }
+    murmur3_32(&wrapped, 0) as i32
+}
+
+fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let mix = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    let mut h = seed;
+    let blocks = data.chunks_exact(4);
+    let tail = blocks.remainder();
+    for block in blocks {
+        h ^= mix(u32::from_le_bytes([block[0], block[1], block[2], block[3]]));
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0u32, |k, byte| (k << 8) | u32::from(*byte));
+        h ^= mix(k);
+    }
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use url::Url;
+
+use crate::{normalize_url, path_depth, query_size, rules};
+
+// Flags `extract_url_features` may leave out for a URL (e.g. no TLD check for an IP host);
+// exported as `false` so every vector has the same keys.
+const FLAGS: &[&str] = &[
+    "dangerous_download", "deep_path", "excessive_query", "has_at_symbol", "has_dash_in_domain",
+    "has_double_slash", "has_ip_address", "has_multiple_subdomains", "has_suspicious_tld",
+];
+
+// Numeric features `metric` can look up.
+pub const METRICS: &[&str] = &[
+    "digit_ratio", "host_digit_ratio", "host_entropy", "host_labels", "host_length", "path_depth",
+    "query_length", "query_param_count", "url_entropy", "url_length",
+];
+
+// Feature vector for `features=raw`, for collecting model training data. Field names are a
+// stable export format: add fields, but do not rename or remove them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawFeatures {
+    // The boolean features before network checks, keyed as `extract_url_features` names them,
+    // plus one entry per custom rule.
+    pub flags: BTreeMap<String, bool>,
+    pub url_length: usize,
+    pub host_length: usize,
+    // Dot-separated labels in the host; 0 for IP hosts.
+    pub host_labels: usize,
+    pub path_depth: usize,
+    pub query_param_count: usize,
+    pub query_length: usize,
+    // Share of ASCII digits in the normalized URL and in the host.
+    pub digit_ratio: f64,
+    pub host_digit_ratio: f64,
+    // Shannon entropy in bits per character.
+    pub url_entropy: f64,
+    pub host_entropy: f64,
+}
+
+impl RawFeatures {
+    pub fn new(url: &str, extracted: &HashMap<String, bool>) -> RawFeatures {
+        let normalized_url = normalize_url(url);
+        let parsed_url = Url::parse(&normalized_url).ok();
+        let host = parsed_url.as_ref().and_then(|u| u.host_str().map(str::to_string)).unwrap_or_default();
+        let mut flags: BTreeMap<String, bool> = FLAGS.iter().map(|flag| (flag.to_string(), false)).collect();
+        for feature in rules::custom_features() {
+            flags.insert(feature.name.clone(), false);
+        }
+        flags.extend(extracted.iter().map(|(name, value)| (name.clone(), *value)));
+        let (query_param_count, query_length) = parsed_url.as_ref().map_or((0, 0), query_size);
+        RawFeatures {
+            flags,
+            url_length: normalized_url.chars().count(),
+            host_length: host.chars().count(),
+            host_labels: parsed_url.as_ref().and_then(Url::domain).map_or(0, |domain| domain.split('.').count()),
+            path_depth: parsed_url.as_ref().map_or(0, path_depth),
+            query_param_count,
+            query_length,
+            digit_ratio: digit_ratio(&normalized_url),
+            host_digit_ratio: digit_ratio(&host),
+            url_entropy: entropy(&normalized_url),
+            host_entropy: entropy(&host),
+        }
+    }
+
+    /// Numeric feature by its export name, e.g. `url_entropy`, for detection rule thresholds.
+    pub fn metric(&self, name: &str) -> Option<f64> {
+        Some(match name {
+            "url_length" => self.url_length as f64,
+            "host_length" => self.host_length as f64,
+            "host_labels" => self.host_labels as f64,
+            "path_depth" => self.path_depth as f64,
+            "query_param_count" => self.query_param_count as f64,
+            "query_length" => self.query_length as f64,
+            "digit_ratio" => self.digit_ratio,
+            "host_digit_ratio" => self.host_digit_ratio,
+            "url_entropy" => self.url_entropy,
+            "host_entropy" => self.host_entropy,
+            _ => return None,
+        })
+    }
+}
+
+fn digit_ratio(text: &str) -> f64 {
+    let total = text.chars().count();
+    if total == 0 {
+        return 0.0;
+    }
+    text.chars().filter(char::is_ascii_digit).count() as f64 / total as f64
+}
+
+fn entropy(text: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in text.chars() {
+        *counts.entry(c).or_insert(0) += 1;
+    }
+    let total = text.chars().count() as f64;
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
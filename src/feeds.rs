@@ -0,0 +1,476 @@
+use reqwest::Client;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use url::Url;
+
+use crate::config::{FeedConfig, FeedFormat, config};
+use crate::{MatchTier, canonical_url_key, is_url_entry, normalize_url, outbound};
+
+// Merged deny data from every feed, split into whole-domain and exact-URL tiers.
+#[derive(Debug, Default)]
+pub struct DenyList {
+    pub hosts: HashSet<String>,
+    pub urls: HashSet<String>,
+    // Content fingerprint, set once all feeds are merged; identical lists share a generation.
+    pub generation: String,
+}
+
+impl DenyList {
+    pub fn insert(&mut self, entry: &str) {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            return;
+        }
+        if is_url_entry(entry) {
+            if let Some(key) = canonical_url_key(entry) {
+                self.urls.insert(key);
+            }
+        } else if let Some(host) = Url::parse(&normalize_url(entry)).ok().and_then(|u| u.host_str().map(str::to_lowercase)) {
+            self.hosts.insert(host);
+        }
+    }
+
+    fn fingerprint(&self) -> String {
+        let mut entries: Vec<&String> = self.hosts.iter().chain(&self.urls).collect();
+        entries.sort();
+        let mut hasher = Sha256::new();
+        for entry in entries {
+            hasher.update(entry.as_bytes());
+            hasher.update(b"\n");
+        }
+        hasher.finalize()[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hosts.len() + self.urls.len()
+    }
+
+    // Exact URL first, then the host and each of its parent domains.
+    pub fn check(&self, url: &str) -> Option<MatchTier> {
+        if canonical_url_key(url).is_some_and(|key| self.urls.contains(&key)) {
+            return Some(MatchTier::Url);
+        }
+        let host = Url::parse(&normalize_url(url)).ok()?.host_str()?.to_lowercase();
+        let mut domain = host.as_str();
+        loop {
+            if self.hosts.contains(domain) {
+                return Some(MatchTier::Domain);
+            }
+            domain = domain.split_once('.')?.1;
+        }
+    }
+}
+
+// Why a fetched feed was rejected. Rejected payloads never replace a feed's last good entries.
+#[derive(Debug)]
+pub enum FeedError {
+    // Network error or non-2xx status.
+    Fetch(String),
+    // Not the feed's format at all, e.g. an HTML outage page or truncated JSON.
+    Malformed(String),
+    // Well-formed but missing the expected structure, e.g. no `deny` array.
+    Shape(String),
+    // Body larger than `feed_max_bytes` or fewer entries than `feed_min_entries`.
+    Size(String),
+    // HTTP 429, with the upstream's `Retry-After` delay when it sent one.
+    RateLimited(Option<Duration>),
+}
+
+impl FeedError {
+    pub fn category(&self) -> &'static str {
+        match self {
+            FeedError::Fetch(_) => "fetch",
+            FeedError::Malformed(_) => "malformed",
+            FeedError::Shape(_) => "shape",
+            FeedError::Size(_) => "size",
+            FeedError::RateLimited(_) => "rate_limited",
+        }
+    }
+}
+
+impl fmt::Display for FeedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeedError::Fetch(detail) | FeedError::Malformed(detail) | FeedError::Shape(detail) | FeedError::Size(detail) => {
+                write!(f, "{}: {}", self.category(), detail)
+            }
+            FeedError::RateLimited(Some(delay)) => write!(f, "{}: HTTP 429, retry after {}s", self.category(), delay.as_secs()),
+            FeedError::RateLimited(None) => write!(f, "{}: HTTP 429", self.category()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for FeedError {
+    fn from(e: reqwest::Error) -> Self {
+        FeedError::Fetch(e.to_string())
+    }
+}
+
+pub trait PhishingFeed {
+    fn name(&self) -> &'static str;
+    // Raw deny entries (hosts or URLs) from a fetched feed body.
+    fn parse(&self, body: &str) -> Result<Vec<String>, FeedError>;
+}
+
+// polkadot-js/phishing `all.json`: `{ "allow": [...], "deny": [...] }`.
+pub struct PolkadotFeed;
+
+impl PhishingFeed for PolkadotFeed {
+    fn name(&self) -> &'static str {
+        "polkadot"
+    }
+
+    fn parse(&self, body: &str) -> Result<Vec<String>, FeedError> {
+        let json: Value = serde_json::from_str(body).map_err(|e| match e.is_eof() {
+            true => FeedError::Malformed(format!("truncated JSON: {}", e)),
+            false => FeedError::Malformed(format!("invalid JSON: {}", e)),
+        })?;
+        let deny = match json.get("deny") {
+            Some(Value::Array(deny)) => deny,
+            Some(other) => return Err(FeedError::Shape(format!("`deny` is {}, expected an array", json_kind(other)))),
+            None => return Err(FeedError::Shape("missing `deny` array".to_string())),
+        };
+        // Nested entries (e.g. `[{ "url": ... }]`) would otherwise parse as an empty list.
+        if let Some(other) = deny.iter().find(|v| !v.is_string()) {
+            return Err(FeedError::Shape(format!("`deny` contains {}, expected strings", json_kind(other))));
+        }
+        Ok(deny.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+    }
+}
+
+fn json_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+// URLhaus CSV dump: `#` comment lines, quoted fields, URL in the third column.
+pub struct UrlhausFeed;
+
+impl PhishingFeed for UrlhausFeed {
+    fn name(&self) -> &'static str {
+        "urlhaus"
+    }
+
+    fn parse(&self, body: &str) -> Result<Vec<String>, FeedError> {
+        Ok(body
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| split_csv_line(line).into_iter().nth(2))
+            .collect())
+    }
+}
+
+// OpenPhish plaintext feed: one URL per line.
+pub struct OpenPhishFeed;
+
+impl PhishingFeed for OpenPhishFeed {
+    fn name(&self) -> &'static str {
+        "openphish"
+    }
+
+    fn parse(&self, body: &str) -> Result<Vec<String>, FeedError> {
+        Ok(body
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+pub fn feed_for(format: FeedFormat) -> Box<dyn PhishingFeed + Send + Sync> {
+    match format {
+        FeedFormat::Polkadot => Box::new(PolkadotFeed),
+        FeedFormat::Urlhaus => Box::new(UrlhausFeed),
+        FeedFormat::Openphish => Box::new(OpenPhishFeed),
+    }
+}
+
+// Outage and login pages come back as 200 HTML; no supported format starts with a tag.
+fn looks_like_html(body: &str) -> bool {
+    let head = body.trim_start().get(..64).unwrap_or(body.trim_start()).to_ascii_lowercase();
+    head.starts_with("<!doctype html") || head.starts_with("<html") || head.starts_with("<head") || head.starts_with("<body")
+}
+
+// Wait before retrying a rate-limited feed that sent no `Retry-After`.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(300);
+// Longest `Retry-After` honored, so a bogus header cannot park a feed for good.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(24 * 3600);
+
+// `Retry-After` given as delay-seconds or as an HTTP date.
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+            (date.to_utc() - chrono::Utc::now()).to_std().unwrap_or(Duration::ZERO)
+        }
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+async fn fetch_feed(client: &Client, feed: &FeedConfig) -> Result<Vec<String>, FeedError> {
+    let request = async {
+        let response = client.get(&feed.url).send().await?;
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(FeedError::RateLimited(retry_after(&response)));
+        }
+        Ok(response.error_for_status()?)
+    };
+    let mut response = outbound::track("feed", &outbound::host_of(&feed.url), request).await?;
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > config().feed_max_bytes {
+            return Err(FeedError::Size(format!("body exceeds {} bytes", config().feed_max_bytes)));
+        }
+    }
+    let body = String::from_utf8_lossy(&body);
+    if looks_like_html(&body) {
+        return Err(FeedError::Malformed("received an HTML page instead of the feed".to_string()));
+    }
+    let entries = feed_for(feed.format).parse(&body)?;
+    if entries.len() < config().feed_min_entries {
+        return Err(FeedError::Size(format!("{} entries, expected at least {}", entries.len(), config().feed_min_entries)));
+    }
+    Ok(entries)
+}
+
+// The configured feeds with the last good entries of each, so a feed that starts serving
+// garbage keeps contributing what it served before until it recovers.
+pub struct FeedSet {
+    // `None` in offline mode.
+    client: Option<Client>,
+    last_good: Mutex<HashMap<String, Vec<String>>>,
+    // When each feed last loaded successfully.
+    loaded_at: Mutex<HashMap<String, Instant>>,
+    // Latest rejection per feed URL, cleared once the feed loads again.
+    errors: Mutex<BTreeMap<String, FeedError>>,
+    // Rate-limited feeds and when they may be fetched again; refreshes skip them until then.
+    retry_at: Mutex<HashMap<String, Instant>>,
+}
+
+impl FeedSet {
+    pub fn new(client: Option<Client>) -> FeedSet {
+        FeedSet {
+            client,
+            last_good: Mutex::new(HashMap::new()),
+            loaded_at: Mutex::new(HashMap::new()),
+            errors: Mutex::new(BTreeMap::new()),
+            retry_at: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Time since the least recently loaded feed last loaded; `None` while some feed never has.
+    pub fn oldest_age(&self) -> Option<Duration> {
+        let loaded_at = self.loaded_at.lock().unwrap();
+        config().feeds.iter().map(|feed| loaded_at.get(&feed.url).map(Instant::elapsed)).try_fold(Duration::ZERO, |oldest, age| {
+            age.map(|age| oldest.max(age))
+        })
+    }
+
+    /// Whether some feed has gone longer than `max_feed_age_secs` without a successful load.
+    pub fn is_stale(&self) -> bool {
+        match config().max_feed_age_secs {
+            Some(max_age) if !config().feeds.is_empty() => self.oldest_age().is_none_or(|age| age > Duration::from_secs(max_age)),
+            _ => false,
+        }
+    }
+
+    /// Entries currently contributed by each feed, keyed by feed URL.
+    pub fn entries(&self) -> BTreeMap<String, Vec<String>> {
+        self.last_good.lock().unwrap().iter().map(|(url, entries)| (url.clone(), entries.clone())).collect()
+    }
+
+    /// Rate-limited feeds, keyed by feed URL, with the seconds left until the next attempt.
+    pub fn rate_limited(&self) -> BTreeMap<String, u64> {
+        let errors = self.errors.lock().unwrap();
+        let retry_at = self.retry_at.lock().unwrap();
+        errors
+            .iter()
+            .filter(|(_, e)| matches!(e, FeedError::RateLimited(_)))
+            .map(|(url, _)| {
+                let wait = retry_at.get(url).map_or(Duration::ZERO, |at| at.saturating_duration_since(Instant::now()));
+                (url.clone(), wait.as_secs())
+            })
+            .collect()
+    }
+
+    /// Current `category: detail` rejection of each failing feed, keyed by feed URL.
+    pub fn errors(&self) -> BTreeMap<String, String> {
+        self.errors.lock().unwrap().iter().map(|(url, e)| (url.clone(), e.to_string())).collect()
+    }
+
+    /// Fetches every configured feed and merges them with the last good data of any that failed.
+    /// Fails only if no feed has ever loaded. Without a client nothing is fetched and the list is empty.
+    pub async fn refresh(&self) -> Result<DenyList, String> {
+        self.refresh_from(&config().feeds).await
+    }
+
+    async fn refresh_from(&self, feeds: &[FeedConfig]) -> Result<DenyList, String> {
+        if let Some(client) = &self.client {
+            for feed in feeds {
+                let name = feed_for(feed.format).name();
+                if self.retry_at.lock().unwrap().get(&feed.url).is_some_and(|at| *at > Instant::now()) {
+                    log::debug!("Skipping rate-limited {} feed {} until its retry time", name, feed.url);
+                    continue;
+                }
+                match fetch_feed(client, feed).await {
+                    Ok(entries) => {
+                        log::info!("Loaded {} entries from {} feed {}", entries.len(), name, feed.url);
+                        self.last_good.lock().unwrap().insert(feed.url.clone(), entries);
+                        self.loaded_at.lock().unwrap().insert(feed.url.clone(), Instant::now());
+                        self.errors.lock().unwrap().remove(&feed.url);
+                    }
+                    Err(e) => {
+                        let kept = self.last_good.lock().unwrap().get(&feed.url).map_or(0, Vec::len);
+                        match e {
+                            // Upstream changed its schema; this will not fix itself on the next refresh.
+                            FeedError::Shape(_) => log::error!("Schema drift in {} feed {} ({}); keeping {} previous entries", name, feed.url, e, kept),
+                            // Transient; the feed is skipped until the upstream's retry time.
+                            FeedError::RateLimited(delay) => {
+                                let retry_at = Instant::now() + delay.unwrap_or(RATE_LIMIT_BACKOFF);
+                                self.retry_at.lock().unwrap().insert(feed.url.clone(), retry_at);
+                                log::warn!("Rate-limited by {} feed {} ({}); keeping {} previous entries", name, feed.url, e, kept);
+                            }
+                            _ => log::warn!("Rejected {} feed {} ({}); keeping {} previous entries", name, feed.url, e, kept),
+                        }
+                        self.errors.lock().unwrap().insert(feed.url.clone(), e);
+                    }
+                }
+            }
+        }
+        let last_good = self.last_good.lock().unwrap();
+        if self.client.is_some() && !feeds.is_empty() && last_good.is_empty() {
+            return Err(self.errors().iter().map(|(url, e)| format!("{}: {}", url, e)).collect::<Vec<_>>().join("; "));
+        }
+        let mut list = DenyList::default();
+        for entries in last_good.values() {
+            entries.iter().for_each(|entry| list.insert(entry));
+        }
+        list.generation = list.fingerprint();
+        Ok(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Serves `responses` (raw HTTP) in order, one per connection, and returns the feed URL.
+    async fn feed_server(responses: Vec<String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/all.json", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let _ = stream.read(&mut [0; 4096]).await;
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        url
+    }
+
+    fn ok(body: &str) -> String {
+        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+    }
+
+    fn feed_set(url: &str) -> (FeedSet, Vec<FeedConfig>) {
+        let feeds = vec![FeedConfig { url: url.to_string(), format: FeedFormat::Polkadot }];
+        (FeedSet::new(Some(Client::new())), feeds)
+    }
+
+    const GOOD: &str = r#"{"allow": [], "deny": ["phish.example", "https://bad.example/login"]}"#;
+
+    #[test]
+    fn polkadot_rejects_html_and_truncated_bodies() {
+        let html = PolkadotFeed.parse("<!DOCTYPE html><html><body>Unicorn!</body></html>").unwrap_err();
+        assert_eq!(html.category(), "malformed");
+        let truncated = PolkadotFeed.parse(r#"{"allow": [], "deny": ["phish.example", "oth"#).unwrap_err();
+        assert!(matches!(&truncated, FeedError::Malformed(detail) if detail.starts_with("truncated JSON")), "{}", truncated);
+        assert_eq!(PolkadotFeed.parse(GOOD).unwrap(), ["phish.example", "https://bad.example/login"]);
+    }
+
+    #[tokio::test]
+    async fn refresh_keeps_last_good_list_on_html_and_truncated_feeds() {
+        let truncated = &GOOD[..GOOD.len() / 2];
+        let url = feed_server(vec![ok(GOOD), ok("<html><head><title>503</title></head></html>"), ok(truncated)]).await;
+        let (set, feeds) = feed_set(&url);
+        let first = set.refresh_from(&feeds).await.unwrap();
+        assert_eq!(first.len(), 2);
+        for expected in ["received an HTML page", "truncated JSON"] {
+            let list = set.refresh_from(&feeds).await.unwrap();
+            assert_eq!(list.generation, first.generation);
+            assert_eq!(list.check("https://phish.example/x"), Some(MatchTier::Domain));
+            let error = &set.errors()[&url];
+            assert!(error.starts_with("malformed: ") && error.contains(expected), "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn schema_drift_keeps_last_good_list() {
+        let drifted = [
+            r#"{"allow": [], "deny": {"phish.example": true}}"#,
+            r#"{"allow": [], "deny": [{"url": "phish.example"}]}"#,
+            r#"{"allow": [], "blocked": ["phish.example"]}"#,
+        ];
+        let mut responses = vec![ok(GOOD)];
+        responses.extend(drifted.iter().map(|body| ok(body)));
+        let url = feed_server(responses).await;
+        let (set, feeds) = feed_set(&url);
+        let first = set.refresh_from(&feeds).await.unwrap();
+        for expected in ["`deny` is an object", "`deny` contains an object", "missing `deny` array"] {
+            let list = set.refresh_from(&feeds).await.unwrap();
+            // Never an empty list: the drifted payload is rejected and the old entries stay.
+            assert_eq!((list.len(), &list.generation), (first.len(), &first.generation));
+            let error = &set.errors()[&url];
+            assert!(error.starts_with("shape: ") && error.contains(expected), "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limited_feed_waits_for_retry_after_or_the_default() {
+        let limited = |header: &str| format!("HTTP/1.1 429 Too Many Requests\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n", header);
+        for (header, expected) in [("Retry-After: 120\r\n", 120), ("", RATE_LIMIT_BACKOFF.as_secs())] {
+            let url = feed_server(vec![limited(header)]).await;
+            let (set, feeds) = feed_set(&url);
+            let _ = set.refresh_from(&feeds).await;
+            let wait = set.rate_limited()[&url];
+            assert!((expected - 1..=expected).contains(&wait), "waiting {}s, expected {}s", wait, expected);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatch {
+    pub entry: String,
+    pub distance: usize,
+}
+
+/// Levenshtein distance between `a` and `b`, or `None` once it exceeds `max`.
+pub fn levenshtein_within(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        let mut row_min = curr[0];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+            row_min = row_min.min(curr[j + 1]);
+        }
+        if row_min > max {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    let distance = prev[b.len()];
+    (distance <= max).then_some(distance)
+}
+
+/// Closest candidate within `max_distance` edits of `domain`, excluding exact matches.
+/// Candidates whose length differs by more than `max_distance` are skipped up front.
+pub fn closest_match<'a>(
+    domain: &str,
+    candidates: impl Iterator<Item = &'a str>,
+    max_distance: usize,
+) -> Option<FuzzyMatch> {
+    let domain = domain.to_lowercase();
+    let len = domain.chars().count();
+    let mut best: Option<FuzzyMatch> = None;
+
+    for candidate in candidates {
+        if candidate.chars().count().abs_diff(len) > max_distance {
+            continue;
+        }
+        let candidate = candidate.to_lowercase();
+        match levenshtein_within(&domain, &candidate, max_distance) {
+            Some(0) | None => {}
+            Some(distance) => {
+                if best.as_ref().is_none_or(|b| distance < b.distance) {
+                    best = Some(FuzzyMatch { entry: candidate, distance });
+                }
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn levenshtein_within_counts_edits() {
+        assert_eq!(levenshtein_within("paypal.com", "paypal.com", 2), Some(0));
+        assert_eq!(levenshtein_within("paypal.com", "paypa1.com", 2), Some(1));
+        assert_eq!(levenshtein_within("paypal.com", "paypall.co", 2), Some(2));
+        assert_eq!(levenshtein_within("paypal.com", "pyapal.com", 2), Some(2));
+    }
+
+    #[test]
+    fn levenshtein_within_gives_up_past_max() {
+        assert_eq!(levenshtein_within("paypal.com", "paypal.co.uk", 1), None);
+        // Same length, so only the row minimum can end it early.
+        assert_eq!(levenshtein_within("paypal.com", "xxxxxx.com", 2), None);
+        assert_eq!(levenshtein_within("", "abc", 2), None);
+    }
+
+    #[test]
+    fn closest_match_excludes_exact_matches() {
+        let list = ["paypal.com", "paypa1.com"];
+        let found = closest_match("PayPal.com", list.into_iter(), 1).unwrap();
+        assert_eq!(found, FuzzyMatch { entry: "paypa1.com".to_string(), distance: 1 });
+        assert_eq!(closest_match("paypal.com", ["paypal.com"].into_iter(), 1), None);
+    }
+
+    #[test]
+    fn closest_match_skips_candidates_by_length() {
+        let list = ["paypal.com.evil.example", "paypal.co", "paypal.comx"];
+        let found = closest_match("paypal.com", list.into_iter(), 1).unwrap();
+        assert_eq!(found.entry, "paypal.co");
+        assert_eq!(closest_match("paypal.com", ["paypal.com.evil.example"].into_iter(), 2), None);
+    }
+
+    #[test]
+    fn closest_match_prefers_the_smallest_distance() {
+        let list = ["paypall.co", "paypa1.com"];
+        assert_eq!(closest_match("paypal.com", list.into_iter(), 2).unwrap().entry, "paypa1.com");
+    }
+
+    // Stands in for a benchmark: a 10k-entry deny list must stay cheap to scan per analysis. Every
+    // entry has the query's length, so none is skipped; the bound leaves room for debug builds.
+    #[test]
+    fn closest_match_scans_10k_entries_quickly() {
+        let list: Vec<String> = (0..10_000).map(|i| format!("phish-{:05}-login.example", i)).collect();
+        let start = Instant::now();
+        for _ in 0..10 {
+            assert_eq!(closest_match("phish-04242-logins.example", list.iter().map(String::as_str), 1).unwrap().entry, "phish-04242-login.example");
+        }
+        let per_scan = start.elapsed() / 10;
+        println!("closest_match over 10k entries: {:?} per scan", per_scan);
+        assert!(per_scan < Duration::from_secs(1), "{:?} per scan", per_scan);
+    }
+}
@@ -0,0 +1,47 @@
+use maxminddb::{Reader, geoip2};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+const MAX_CACHED_IPS: usize = 10_000;
+
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+    cache: Mutex<HashMap<IpAddr, Option<String>>>,
+}
+
+impl GeoIp {
+    pub fn open(path: &str) -> Option<GeoIp> {
+        match Reader::open_readfile(path) {
+            Ok(reader) => Some(GeoIp {
+                reader,
+                cache: Mutex::new(HashMap::new()),
+            }),
+            Err(e) => {
+                log::warn!("Failed to open GeoIP database {}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// ISO 3166-1 alpha-2 country code for `ip`, memoized per address.
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        if let Some(cached) = self.cache.lock().unwrap().get(&ip) {
+            return cached.clone();
+        }
+        let country = match self.reader.lookup(ip).and_then(|r| r.decode::<geoip2::Country>()) {
+            Ok(Some(record)) => record.country.iso_code.map(str::to_string),
+            Ok(None) => None,
+            Err(e) => {
+                log::debug!("GeoIP lookup failed for {}: {}", ip, e);
+                None
+            }
+        };
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_IPS {
+            cache.clear();
+        }
+        cache.insert(ip, country.clone());
+        country
+    }
+}
@@ -0,0 +1,172 @@
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Error, ErrorExtensions, Object, Result, Schema, SimpleObject};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::Json;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use once_cell::sync::Lazy;
+
+use crate::config::config;
+use crate::{AnalysisOptions, AnalysisResponse, AppState, RiskBucket, analysis_response, analyze_in_input_order, redact_for, round_score};
+
+type AnalysisSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+static SCHEMA: Lazy<AnalysisSchema> = Lazy::new(|| Schema::build(Query, EmptyMutation, EmptySubscription).finish());
+
+// One analyzed URL. In public mode unauthenticated callers get `risk` and `severity` only, as
+// with REST; `details` stays null.
+#[derive(SimpleObject)]
+struct Analysis {
+    url: String,
+    // Absolute URL that was analyzed, when `url` was resolved against a base.
+    resolved_url: Option<String>,
+    // Gate decision, only set in enforce mode.
+    action: Option<String>,
+    // `low`, `medium`, `high` or `unknown`; null when the analysis failed.
+    risk: Option<String>,
+    severity: Option<u8>,
+    details: Option<AnalysisDetails>,
+    // Why this URL of a batch could not be analyzed; `analyze` reports it as a GraphQL error.
+    error: Option<String>,
+}
+
+#[derive(SimpleObject)]
+struct AnalysisDetails {
+    normalized_url: String,
+    is_phishing: bool,
+    is_shortened: bool,
+    risk_score: Option<f64>,
+    degraded: bool,
+    fast_path: bool,
+    offline: bool,
+    feed_stale: bool,
+    feed_generation: Option<String>,
+    cached: bool,
+    signals: Vec<String>,
+    // The REST `analysis` map as a list, ordered by name.
+    analysis: Vec<Finding>,
+}
+
+#[derive(SimpleObject)]
+struct Finding {
+    name: String,
+    detail: String,
+}
+
+impl From<AnalysisResponse> for Analysis {
+    fn from(response: AnalysisResponse) -> Analysis {
+        let (risk, severity) = match (&response.data, &response.verdict) {
+            (Some(data), _) => (Some(data.bucket().as_str().to_string()), Some(data.severity)),
+            (None, Some(verdict)) => (Some(RiskBucket::from_name(verdict.risk).as_str().to_string()), Some(verdict.severity)),
+            (None, None) => (None, None),
+        };
+        let details = response.data.map(|data| {
+            let mut analysis: Vec<Finding> = data.analysis.into_iter().map(|(name, detail)| Finding { name, detail }).collect();
+            analysis.sort_by(|a, b| a.name.cmp(&b.name));
+            AnalysisDetails {
+                normalized_url: data.normalized_url,
+                is_phishing: data.is_phishing,
+                is_shortened: data.is_shortened,
+                risk_score: data.risk_score.map(round_score),
+                degraded: data.degraded,
+                fast_path: data.fast_path,
+                offline: data.offline,
+                feed_stale: data.feed_stale,
+                feed_generation: data.feed_generation,
+                cached: data.cached,
+                signals: data.signals,
+                analysis,
+            }
+        });
+        Analysis {
+            url: response.url,
+            resolved_url: response.resolved_url,
+            action: response.action.map(str::to_string),
+            risk,
+            severity,
+            details,
+            error: response.error,
+        }
+    }
+}
+
+// The status REST would answer with, as `code` (e.g. `BAD_REQUEST`) and `status` extensions.
+fn status_error(status: StatusCode, message: String) -> Error {
+    let code = status.canonical_reason().unwrap_or("ERROR").to_uppercase().replace(' ', "_");
+    Error::new(message).extend_with(|_, extensions| {
+        extensions.set("code", code);
+        extensions.set("status", status.as_u16());
+    })
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn analyze(&self, ctx: &Context<'_>, url: String) -> Result<Analysis> {
+        let (state, options) = (ctx.data::<AppState>()?, ctx.data::<AnalysisOptions>()?);
+        let (status, Json(response)) = analysis_response(&url, state, options).await;
+        match response.error {
+            Some(message) => Err(status_error(status, message)),
+            None => Ok(response.into()),
+        }
+    }
+
+    // Same limits and deduplication as `POST /analyze/batch`; results are in input order.
+    async fn analyze_batch(&self, ctx: &Context<'_>, urls: Vec<String>) -> Result<Vec<Analysis>> {
+        let (state, options) = (ctx.data::<AppState>()?, ctx.data::<AnalysisOptions>()?);
+        let max_batch_size = config().max_batch_size;
+        if urls.len() > max_batch_size {
+            return Err(status_error(StatusCode::PAYLOAD_TOO_LARGE, format!("Batch exceeds {} URLs", max_batch_size)));
+        }
+        let (results, _) = analyze_in_input_order(&urls, config().batch_concurrency, |url| async move {
+            analysis_response(&url, state, options).await.1.0
+        })
+        .await;
+        Ok(results.into_iter().map(Analysis::from).collect())
+    }
+}
+
+async fn execute(state: AppState, options: AnalysisOptions, request: async_graphql::Request) -> async_graphql::Response {
+    SCHEMA.execute(request.data(state).data(options)).await
+}
+
+// `POST /graphql`; the caller's API key decides redaction as it does for `/analyze`.
+pub async fn handler(State(state): State<AppState>, headers: HeaderMap, request: GraphQLRequest) -> GraphQLResponse {
+    let options = AnalysisOptions { redact: redact_for(&headers), ..Default::default() };
+    execute(state, options, request.into_inner()).await.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_state;
+
+    async fn run(query: &str) -> serde_json::Value {
+        let response = execute(test_state(), AnalysisOptions::default(), async_graphql::Request::new(query)).await;
+        serde_json::to_value(&response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn errors_carry_the_rest_status_as_extension_code() {
+        let response = run(r#"{ analyze(url: "not a url") { risk } }"#).await;
+        let error = &response["errors"][0];
+        assert_eq!(error["message"], "Invalid URL");
+        assert_eq!(error["extensions"]["code"], "BAD_REQUEST");
+        assert_eq!(error["extensions"]["status"], 400);
+
+        let urls = vec!["\"https://example.com/\""; config().max_batch_size + 1].join(", ");
+        let response = run(&format!("{{ analyzeBatch(urls: [{}]) {{ risk }} }}", urls)).await;
+        let error = &response["errors"][0];
+        assert_eq!(error["extensions"]["code"], "PAYLOAD_TOO_LARGE");
+        assert_eq!(error["extensions"]["status"], 413);
+    }
+
+    #[tokio::test]
+    async fn batch_reports_item_errors_in_place_and_selects_only_requested_fields() {
+        let response = run(r#"{ analyzeBatch(urls: ["not a url", "http://127.0.0.1:9/"]) { url error details { normalizedUrl } } }"#).await;
+        assert!(response.get("errors").is_none(), "{}", response);
+        let results = response["data"]["analyzeBatch"].as_array().unwrap();
+        assert_eq!(results[0], serde_json::json!({"url": "not a url", "error": "Invalid URL", "details": null}));
+        assert_eq!(results[1], serde_json::json!({"url": "http://127.0.0.1:9/", "error": null, "details": {"normalizedUrl": "http://127.0.0.1:9/"}}));
+    }
+}
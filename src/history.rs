@@ -0,0 +1,129 @@
+use rusqlite::{Connection, params};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Changed verdicts kept in the rescan status; the total is still counted past this.
+const MAX_REPORTED_CHANGES: usize = 100;
+
+pub struct HistoryRecord {
+    pub input_url: String,
+    pub normalized_url: String,
+    pub risk_score: Option<f32>,
+    pub bucket: &'static str,
+    pub is_phishing: bool,
+}
+
+// Which previously seen URLs a rescan covers; every field is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RescanFilter {
+    // Only URLs last analyzed at or after this Unix timestamp.
+    pub since: Option<i64>,
+    // Only URLs whose last verdict was this bucket (`low`, `medium`, `high`, `unknown`).
+    pub bucket: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RescanChange {
+    pub url: String,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RescanStatus {
+    pub running: bool,
+    pub total: usize,
+    pub processed: usize,
+    pub changed_count: usize,
+    pub changed: Vec<RescanChange>,
+    pub started_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<i64>,
+}
+
+impl RescanStatus {
+    pub fn record_change(&mut self, change: RescanChange) {
+        self.changed_count += 1;
+        if self.changed.len() < MAX_REPORTED_CHANGES {
+            self.changed.push(change);
+        }
+    }
+}
+
+pub fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+// Every completed analysis, stored in SQLite for retroactive rescans.
+pub struct History {
+    conn: Mutex<Connection>,
+    // Latest rescan job; `None` until one has been started.
+    pub rescan: Mutex<Option<RescanStatus>>,
+}
+
+impl History {
+    pub fn open(path: &str) -> Option<History> {
+        let conn = match Connection::open(path) {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::warn!("Failed to open history database {}: {}", path, e);
+                return None;
+            }
+        };
+        let schema = "PRAGMA journal_mode = WAL;
+            CREATE TABLE IF NOT EXISTS analyses (
+                id INTEGER PRIMARY KEY,
+                input_url TEXT NOT NULL,
+                normalized_url TEXT NOT NULL,
+                risk_score REAL,
+                bucket TEXT NOT NULL,
+                is_phishing INTEGER NOT NULL,
+                analyzed_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS analyses_url ON analyses (normalized_url, analyzed_at);";
+        if let Err(e) = conn.execute_batch(schema) {
+            log::warn!("Failed to initialize history database {}: {}", path, e);
+            return None;
+        }
+        Some(History {
+            conn: Mutex::new(conn),
+            rescan: Mutex::new(None),
+        })
+    }
+
+    pub fn update_rescan(&self, f: impl FnOnce(&mut RescanStatus)) {
+        if let Some(status) = self.rescan.lock().unwrap().as_mut() {
+            f(status);
+        }
+    }
+
+    pub fn record(&self, record: &HistoryRecord) {
+        let result = self.conn.lock().unwrap().execute(
+            "INSERT INTO analyses (input_url, normalized_url, risk_score, bucket, is_phishing, analyzed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![record.input_url, record.normalized_url, record.risk_score, record.bucket, record.is_phishing, now_secs()],
+        );
+        if let Err(e) = result {
+            log::warn!("Failed to record history for {}: {}", record.normalized_url, e);
+        }
+    }
+
+    /// Latest verdict of each distinct URL matching `filter`, as (normalized URL, bucket).
+    pub fn latest(&self, filter: &RescanFilter) -> rusqlite::Result<Vec<(String, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT a.normalized_url, a.bucket FROM analyses a
+             JOIN (SELECT normalized_url, MAX(id) AS id FROM analyses GROUP BY normalized_url) latest ON a.id = latest.id
+             WHERE a.analyzed_at >= ?1 AND (?2 IS NULL OR a.bucket = ?2)
+             ORDER BY a.id LIMIT ?3",
+        )?;
+        let limit = filter.limit.map_or(-1, |l| l as i64);
+        let rows = statement.query_map(params![filter.since.unwrap_or(0), filter.bucket, limit], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        rows.collect()
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+use crate::outbound;
+
+const MAX_CACHED_DOMAINS: usize = 10_000;
+// Failed lookups are remembered briefly so a dead resolver doesn't cost every request a timeout.
+const FAILURE_TTL: Duration = Duration::from_secs(60);
+// Advertised via EDNS so long SPF records fit in one UDP answer.
+const UDP_PAYLOAD: u16 = 1232;
+const TYPE_TXT: u16 = 16;
+const TYPE_OPT: u16 = 41;
+const RCODE_NXDOMAIN: u8 = 3;
+
+// What a domain publishes against sender spoofing.
+#[derive(Debug, Clone)]
+pub enum EmailAuth {
+    // The domain does not exist, so there is nothing to spoof on its behalf.
+    NoDomain,
+    Policies { spf: Option<String>, dmarc: Option<String> },
+}
+
+impl EmailAuth {
+    // Why the domain is easy to spoof: DMARC missing or only monitoring (`p=none`).
+    pub fn weakness(&self) -> Option<&'static str> {
+        let EmailAuth::Policies { dmarc, .. } = self else {
+            return None;
+        };
+        match dmarc.as_deref().and_then(dmarc_policy) {
+            None => Some("no DMARC policy"),
+            Some(policy) if policy.eq_ignore_ascii_case("none") => Some("DMARC policy is p=none"),
+            Some(_) => None,
+        }
+    }
+}
+
+// The `p=` tag of a DMARC record, e.g. `reject` for `v=DMARC1; p=reject; rua=...`.
+fn dmarc_policy(record: &str) -> Option<&str> {
+    record.split(';').filter_map(|tag| tag.trim().split_once('=')).find(|(name, _)| name.trim() == "p").map(|(_, value)| value.trim())
+}
+
+// SPF and DMARC TXT lookups for email domains, memoized per domain.
+pub struct EmailAuthLookup {
+    resolver: SocketAddr,
+    timeout: Duration,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (Instant, Option<EmailAuth>)>>,
+}
+
+impl EmailAuthLookup {
+    pub fn new(resolver: SocketAddr, timeout: Duration, ttl: Duration) -> EmailAuthLookup {
+        EmailAuthLookup {
+            resolver,
+            timeout,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Policies published by `domain`; `None` when the resolver is slow, down or answers garbage.
+    pub async fn lookup(&self, domain: &str) -> Option<EmailAuth> {
+        if let Some((expires_at, cached)) = self.cache.lock().unwrap().get(domain)
+            && *expires_at > Instant::now()
+        {
+            return cached.clone();
+        }
+        let (auth, ttl) = match self.fetch(domain).await {
+            Ok(auth) => (Some(auth), self.ttl),
+            Err(e) => {
+                log::debug!("SPF/DMARC lookup failed for {}: {}", domain, e);
+                (None, FAILURE_TTL)
+            }
+        };
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_DOMAINS {
+            cache.clear();
+        }
+        cache.insert(domain.to_string(), (Instant::now() + ttl, auth.clone()));
+        auth
+    }
+
+    async fn fetch(&self, domain: &str) -> Result<EmailAuth, String> {
+        let dmarc_name = format!("_dmarc.{}", domain);
+        let (spf, dmarc) = tokio::join!(self.txt(domain), self.txt(&dmarc_name));
+        let Some(spf) = spf? else {
+            return Ok(EmailAuth::NoDomain);
+        };
+        // A missing `_dmarc` name just means no DMARC record.
+        let dmarc = dmarc?.unwrap_or_default();
+        Ok(EmailAuth::Policies {
+            spf: spf.into_iter().find(|record| record.to_ascii_lowercase().starts_with("v=spf1")),
+            dmarc: dmarc.into_iter().find(|record| record.to_ascii_lowercase().starts_with("v=dmarc1")),
+        })
+    }
+
+    // TXT records at `name`, or `None` for NXDOMAIN.
+    async fn txt(&self, name: &str) -> Result<Option<Vec<String>>, String> {
+        let id: u16 = rand::random();
+        let query = encode_query(id, name)?;
+        let exchange = async {
+            let socket = UdpSocket::bind(if self.resolver.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).await.map_err(|e| e.to_string())?;
+            socket.connect(self.resolver).await.map_err(|e| e.to_string())?;
+            socket.send(&query).await.map_err(|e| e.to_string())?;
+            let mut buf = vec![0; UDP_PAYLOAD as usize];
+            // Stray datagrams (late answers to an earlier query) are skipped, not fatal.
+            loop {
+                let len = socket.recv(&mut buf).await.map_err(|e| e.to_string())?;
+                if len >= 2 && u16::from_be_bytes([buf[0], buf[1]]) == id {
+                    return decode_txt(&buf[..len]);
+                }
+            }
+        };
+        let timed = async {
+            tokio::time::timeout(self.timeout, exchange).await.map_err(|_| format!("timed out after {}s", self.timeout.as_secs()))?
+        };
+        outbound::track("dns", &self.resolver.ip().to_string(), timed).await
+    }
+}
+
+/// The resolver to query: `configured` (`ip` or `ip:port`), else the first `nameserver` in
+/// `/etc/resolv.conf`.
+pub fn resolver(configured: Option<&str>) -> Option<SocketAddr> {
+    let parse = |addr: &str| addr.parse().ok().or_else(|| addr.parse().ok().map(|ip| SocketAddr::new(ip, 53)));
+    match configured {
+        Some(addr) => parse(addr),
+        None => fs::read_to_string("/etc/resolv.conf")
+            .ok()?
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("nameserver"))
+            .find_map(|addr| parse(addr.trim())),
+    }
+}
+
+fn encode_query(id: u16, name: &str) -> Result<Vec<u8>, String> {
+    let mut packet = Vec::with_capacity(512);
+    packet.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired; one question, one additional (the EDNS OPT record).
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 1]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("invalid DNS name {}", name));
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_TXT.to_be_bytes());
+    packet.extend_from_slice(&1u16.to_be_bytes());
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_OPT.to_be_bytes());
+    packet.extend_from_slice(&UDP_PAYLOAD.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    Ok(packet)
+}
+
+fn decode_txt(packet: &[u8]) -> Result<Option<Vec<String>>, String> {
+    let malformed = || "malformed DNS answer".to_string();
+    let word = |at: usize| packet.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or_else(malformed);
+    let flags = word(2)?;
+    if flags & 0x0200 != 0 {
+        return Err("DNS answer truncated".to_string());
+    }
+    match (flags & 0x000f) as u8 {
+        0 => {}
+        RCODE_NXDOMAIN => return Ok(None),
+        rcode => return Err(format!("DNS error rcode {}", rcode)),
+    }
+    let (questions, answers) = (word(4)?, word(6)?);
+    let mut at = 12;
+    for _ in 0..questions {
+        at = skip_name(packet, at).ok_or_else(malformed)? + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        at = skip_name(packet, at).ok_or_else(malformed)?;
+        let (kind, len) = (word(at)?, word(at + 8)? as usize);
+        let data = packet.get(at + 10..at + 10 + len).ok_or_else(malformed)?;
+        at += 10 + len;
+        // Answers can lead with the CNAME chain to the TXT records.
+        if kind != TYPE_TXT {
+            continue;
+        }
+        // One record may be split into several character-strings, which are concatenated.
+        let mut text = Vec::new();
+        let mut rest = data;
+        while let Some((&n, tail)) = rest.split_first() {
+            let chunk = tail.get(..n as usize).ok_or_else(malformed)?;
+            text.extend_from_slice(chunk);
+            rest = &tail[n as usize..];
+        }
+        records.push(String::from_utf8_lossy(&text).into_owned());
+    }
+    Ok(Some(records))
+}
+
+// Offset just past the (possibly compressed) name starting at `at`.
+fn skip_name(packet: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(at)?;
+        match len {
+            0 => return Some(at + 1),
+            // A compression pointer ends the name.
+            len if len & 0xc0 == 0xc0 => return Some(at + 2),
+            len => at += 1 + len as usize,
+        }
+    }
+}
@@ -1,175 +1,1014 @@
-use axum::extract::State;
+use axum::extract::{DefaultBodyLimit, Path, Request, State, rejection::QueryRejection};
+use axum::middleware::Next;
 use axum::{
     Router,
     extract::Query,
-    routing::get,
+    http::HeaderMap,
+    http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+    http::{HeaderName, HeaderValue, Method, Uri},
+    response::sse::{Event, KeepAlive, Sse},
+    response::{IntoResponse, Response},
+    routing::{get, post},
     http::StatusCode,
     Json,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::net::SocketAddr;
-use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use futures::stream::{self, StreamExt};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use reqwest::Client;
-use std::time::Duration;
-use url::Url;
-use std::sync::Arc;
+use std::time::{Duration, Instant};
+use unicode_security::confusable_detection;
+use tower_http::limit::RequestBodyLimitLayer;
+use url::{Host, Url};
+use std::sync::{Arc, Mutex, RwLock};
+
+mod cache;
+mod cef;
+mod config;
+mod ct;
+mod explain;
+mod extract;
+mod favicon;
+mod features;
+mod feeds;
+mod fuzzy;
+mod geoip;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod history;
+mod mailauth;
+mod ngram;
+mod outbound;
+mod peek;
+mod reputation;
+mod rules;
+mod scan;
+mod stats;
+mod tls;
+mod topsites;
+mod webhook;
+
+use config::{RiskProfile, ShortenerSeverity, config};
+
+#[derive(Clone)]
+struct AppState {
+    // Swapped wholesale on each feed refresh; analyses hold on to the list they started with.
+    phishing_list: Arc<RwLock<Arc<Result<feeds::DenyList, String>>>>,
+    feeds: Arc<feeds::FeedSet>,
+    geoip: Option<Arc<geoip::GeoIp>>,
+    stats: Arc<stats::Stats>,
+    // `None` when `cache_ttl_secs` is 0.
+    cache: Option<Arc<cache::AnalysisCache>>,
+    warm_jobs: Arc<cache::WarmJobs>,
+    // `None` unless `ct_check` is enabled.
+    ct: Option<Arc<ct::CtLookup>>,
+    // `None` unless `email_auth_check` is enabled and a resolver is known.
+    email_auth: Option<Arc<mailauth::EmailAuthLookup>>,
+    // `None` unless `favicon_check` is enabled.
+    favicon: Option<Arc<favicon::FaviconLookup>>,
+    // `None` unless `top_sites_path` points at a readable list.
+    top_sites: Option<Arc<topsites::TopSites>>,
+    // `None` unless `history_db_path` is set and the database opened.
+    history: Option<Arc<history::History>>,
+    // External scorers from `reputation_providers`; empty by default.
+    reputation: Arc<Vec<Box<dyn reputation::ReputationProvider>>>,
+    // `None` unless `webhook_url` is set.
+    webhook: Option<Arc<webhook::Webhook>>,
+    // Operator exceptions from `filters/allow.json`; reloaded on SIGHUP along with the deny file.
+    allow_list: Arc<RwLock<Vec<String>>>,
+}
+
+impl AppState {
+    fn deny_list(&self) -> Arc<Result<feeds::DenyList, String>> {
+        self.phishing_list.read().unwrap().clone()
+    }
+
+    // `None` while no deny list has loaded.
+    fn feed_generation(&self) -> Option<String> {
+        self.deny_list().as_ref().as_ref().ok().map(|list| list.generation.clone())
+    }
+}
+
+// Every `/analyze` query option, parsed and checked in one place; see `AnalyzeOptions::from_query`.
+#[derive(Deserialize)]
+struct AnalyzeOptions {
+    url: String,
+    #[serde(default)]
+    timings: bool,
+    #[serde(default)]
+    scope: Scope,
+    // Comma-separated names from `risk_profiles`.
+    profiles: Option<String>,
+    // Absolute URL that a relative `url` is resolved against.
+    base: Option<String>,
+    // Response format; `Accept: text/x-cef` selects CEF as well.
+    #[serde(default)]
+    format: ResponseFormat,
+    // Omit null, false and empty fields; defaults to `compact_responses`.
+    compact: Option<bool>,
+    // `raw` adds the extracted feature vector under `data.features`.
+    features: Option<FeatureExport>,
+    // `text` adds a plain-language summary of the verdict under `data.explanation`.
+    explain: Option<Explain>,
+}
+
+impl AnalyzeOptions {
+    // A malformed option, an unknown profile or options that cannot be combined; the error is
+    // the input URL (when it parsed) and a message naming the option.
+    fn parse(query: Result<Query<AnalyzeOptions>, QueryRejection>) -> Result<AnalyzeOptions, (String, String)> {
+        let Query(options) = query.map_err(|rejection| {
+            // axum names the offending option first, e.g. "scope: unknown variant `host`, ...".
+            let text = rejection.body_text();
+            let detail = text.strip_prefix("Failed to deserialize query string: ").unwrap_or(&text);
+            (String::new(), format!("Invalid query: {}", detail))
+        })?;
+        match options.validate() {
+            Ok(()) => Ok(options),
+            Err(e) => Err((options.url, e)),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(unknown) = self.profile_names().iter().find(|p| !config().risk_profiles.contains_key(*p)) {
+            return Err(format!("Unknown risk profile: {}", unknown));
+        }
+        // A CEF line has no room for any of these, so asking for them alongside it is a mistake.
+        if self.format == ResponseFormat::Cef {
+            let json_only = [
+                ("compact", self.compact == Some(true)),
+                ("explain", self.explain.is_some()),
+                ("features", self.features.is_some()),
+                ("profiles", !self.profile_names().is_empty()),
+                ("timings", self.timings),
+            ];
+            if let Some((option, _)) = json_only.iter().find(|(_, set)| *set) {
+                return Err(format!("Conflicting options: {} cannot be combined with format=cef", option));
+            }
+        }
+        Ok(())
+    }
+
+    fn profile_names(&self) -> Vec<String> {
+        self.profiles
+            .iter()
+            .flat_map(|p| p.split(','))
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FeatureExport {
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Explain {
+    Text,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ResponseFormat {
+    #[default]
+    Json,
+    // One ArcSight Common Event Format line, for SIEM ingestion.
+    Cef,
+}
+
+impl ResponseFormat {
+    fn negotiate(requested: ResponseFormat, headers: &HeaderMap) -> ResponseFormat {
+        let accepts_cef = headers
+            .get(ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|accept| accept.split(',').any(|t| t.trim().starts_with(CEF_CONTENT_TYPE)));
+        if accepts_cef { ResponseFormat::Cef } else { requested }
+    }
+}
+
+const CEF_CONTENT_TYPE: &str = "text/x-cef";
+
+// What part of the URL is scored: the full URL, or only its host (for DNS-layer callers).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Scope {
+    #[default]
+    Url,
+    Domain,
+}
 
 #[derive(Deserialize)]
-struct UrlQuery {
+struct LinkQuery {
+    display: String,
     url: String,
+    base: Option<String>,
+}
+
+// Per-request context that can influence analysis beyond the URL itself.
+#[derive(Debug, Default)]
+struct AnalysisOptions {
+    display_text: Option<String>,
+    timings: bool,
+    // Replace detailed signals with a bucket-level verdict (public mode, unauthenticated caller).
+    redact: bool,
+    // Skip checks that contact the network (DNS, TLS probe).
+    local_only: bool,
+    scope: Scope,
+    // Risk profiles to report alongside the default verdict.
+    profiles: Vec<String>,
+    // Page URL that relative inputs (`/login`, `../verify`) are resolved against.
+    base: Option<String>,
+    // Keep `features` in the response.
+    raw_features: bool,
+    // Add `explanation` to the response.
+    explain: bool,
+}
+
+// Wall-clock duration of each sub-check in milliseconds; collects nothing unless enabled.
+#[derive(Debug, Default)]
+struct Timings(Option<BTreeMap<&'static str, f64>>);
+
+impl Timings {
+    fn new(enabled: bool) -> Timings {
+        Timings(enabled.then(BTreeMap::new))
+    }
+
+    fn start(&self) -> Option<Instant> {
+        self.0.as_ref().map(|_| Instant::now())
+    }
+
+    fn record(&mut self, check: &'static str, start: Option<Instant>) {
+        if let (Some(timings), Some(start)) = (self.0.as_mut(), start) {
+            *timings.entry(check).or_default() += start.elapsed().as_secs_f64() * 1000.0;
+        }
+    }
+}
+
+// Outcome of the network-dependent checks (feeds, DNS, TLS probe, CT) run for one analysis.
+#[derive(Debug, Default)]
+struct NetworkChecks {
+    attempted: u32,
+    failed: u32,
+    // Outbound requests made so far, and checks skipped once `max_outbound_requests` was reached.
+    requests: usize,
+    skipped: usize,
+}
+
+impl NetworkChecks {
+    // Claims one outbound request from the analysis budget; false means skip the check.
+    fn spend(&mut self) -> bool {
+        if self.requests >= config().max_outbound_requests {
+            self.skipped += 1;
+            return false;
+        }
+        self.requests += 1;
+        true
+    }
+
+    // Requests left in the analysis budget.
+    fn remaining(&self) -> usize {
+        config().max_outbound_requests.saturating_sub(self.requests)
+    }
+
+    fn record(&mut self, ok: bool) {
+        self.attempted += 1;
+        if !ok {
+            self.failed += 1;
+        }
+    }
+
+    fn all_failed(&self) -> bool {
+        self.attempted > 0 && self.failed == self.attempted
+    }
+}
+
+// Returned by `analyze_url` under `on_network_failure = "fail"`; answered with 503.
+#[derive(Debug)]
+struct NetworkFailure;
+
+impl std::fmt::Display for NetworkFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "all network-dependent checks failed")
+    }
+}
+
+impl std::error::Error for NetworkFailure {}
+
+#[derive(Serialize)]
+struct NormalizeResponse {
+    input: String,
+    normalized: String,
+    valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BatchQuery {
+    urls: Vec<String>,
+    // Applied to every relative entry in `urls`.
+    base: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BatchResponse {
+    results: Vec<AnalysisResponse>,
+    summary: BatchSummary,
+}
+
+#[derive(Serialize)]
+struct BatchSummary {
+    total: usize,
+    unique: usize,
+    // Share of inputs answered from another input's analysis.
+    dedup_ratio: f32,
+}
+
+impl BatchSummary {
+    fn new(total: usize, unique: usize) -> BatchSummary {
+        BatchSummary { total, unique, dedup_ratio: if total == 0 { 0.0 } else { 1.0 - unique as f32 / total as f32 } }
+    }
+}
+
+// Payload of the final `done` event of `/analyze/stream`.
+#[derive(Serialize)]
+struct StreamSummary {
+    #[serde(flatten)]
+    summary: BatchSummary,
+    // Results per risk bucket, plus `error` for URLs that could not be analyzed.
+    counts: BTreeMap<&'static str, usize>,
+}
+
+#[derive(Deserialize)]
+struct TextQuery {
+    text: String,
 }
 
 #[derive(Serialize)]
+struct TextResponse {
+    urls: Vec<AnalysisResponse>,
+    // Highest risk among the extracted URLs; null when none were found or analyzed.
+    #[serde(serialize_with = "serialize_optional_score")]
+    max_risk_score: Option<f32>,
+    verdict: String,
+}
+
+#[derive(Clone, Serialize)]
 struct AnalysisResponse {
     url: String,
     status: String,
     data: Option<UrlAnalysis>,
     error: Option<String>,
+    // Absolute URL that was analyzed, when `url` was resolved against a `base`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved_url: Option<String>,
+    // Gate decision, only present in enforce mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    action: Option<&'static str>,
+    // Stands in for `data` when detail is withheld in public mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verdict: Option<PublicVerdict>,
+}
+
+#[derive(Clone, Serialize)]
+struct PublicVerdict {
+    risk: &'static str,
+    severity: u8,
+    message: &'static str,
+}
+
+impl AnalysisResponse {
+    fn error(url: &str, message: String) -> AnalysisResponse {
+        AnalysisResponse {
+            url: url.to_string(),
+            status: "error".to_string(),
+            data: None,
+            error: Some(message),
+            resolved_url: None,
+            action: None,
+            verdict: None,
+        }
+    }
+
+    // Swaps the detailed analysis for a bucket-level verdict.
+    fn redact(&mut self) {
+        if let Some(analysis) = self.data.take() {
+            let bucket = analysis.bucket();
+            self.verdict = Some(PublicVerdict {
+                risk: bucket.as_str(),
+                severity: config().severity_level(bucket.as_str()),
+                message: bucket.public_message(),
+            });
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct UrlAnalysis {
+    // Deprecated: alias of `normalized_url`, kept for existing clients.
     url: String,
+    input_url: String,
+    normalized_url: String,
     is_shortened: bool,
     is_phishing: bool,
-    risk_score: f32,
+    // Null when network checks failed under `on_network_failure = "neutral"`.
+    #[serde(serialize_with = "serialize_optional_score")]
+    risk_score: Option<f32>,
+    // The bucket's level from `severity_levels`, for systems that sort by numeric severity.
+    severity: u8,
+    // Every network-dependent check failed, or some were skipped by the outbound request
+    // budget, so the score rests partly or wholly on local signals.
+    degraded: bool,
+    // High-reputation domain: network checks were skipped on purpose.
+    fast_path: bool,
+    // Running with `offline`: no network-dependent check ran.
+    offline: bool,
+    // The deny-list feeds are older than `max_feed_age_secs`.
+    feed_stale: bool,
+    // Content hash of the deny list this analysis was checked against; see `/health`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    feed_generation: Option<String>,
+    // Served from the analysis cache rather than analyzed for this request.
+    cached: bool,
+    // Machine-readable identifiers of the signals that fired; `analysis` holds the prose.
+    signals: Vec<String>,
     analysis: HashMap<String, String>,
+    // Verdicts under the risk profiles requested with `profiles=`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, ProfileVerdict>,
+    // Never cached, so never read back from a `cache_file`.
+    #[serde(skip_serializing_if = "Option::is_none", skip_deserializing)]
+    timings: Option<BTreeMap<&'static str, f64>>,
+    // Extracted feature vector, reported with `features=raw`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    features: Option<features::RawFeatures>,
+    // End-user summary of the verdict, with `explain=text`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explanation: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ProfileVerdict {
+    #[serde(serialize_with = "serialize_optional_score")]
+    risk_score: Option<f32>,
+    risk_assessment: &'static str,
+}
+
+// A `ProfileVerdict` read back from a `cache_file`.
+#[derive(Deserialize)]
+struct StoredVerdict {
+    risk_score: Option<f32>,
+    risk_assessment: String,
+}
+
+// Assessments are fixed texts, so a stored one maps back to its bucket's.
+impl TryFrom<StoredVerdict> for ProfileVerdict {
+    type Error = String;
+
+    fn try_from(stored: StoredVerdict) -> Result<ProfileVerdict, String> {
+        let risk_assessment = [RiskBucket::High, RiskBucket::Medium, RiskBucket::Low, RiskBucket::Unknown]
+            .into_iter()
+            .map(|bucket| bucket.assessment())
+            .find(|assessment| *assessment == stored.risk_assessment)
+            .ok_or_else(|| format!("unknown risk assessment {:?}", stored.risk_assessment))?;
+        Ok(ProfileVerdict { risk_score: stored.risk_score, risk_assessment })
+    }
+}
+
+// Derived, the `&'static str` field would only deserialize from `'static` input.
+impl<'de> Deserialize<'de> for ProfileVerdict {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<ProfileVerdict, D::Error> {
+        StoredVerdict::deserialize(deserializer)?.try_into().map_err(serde::de::Error::custom)
+    }
 }
 
 #[derive(Debug)]
 pub struct UrlCheckResult {
     pub is_phishing: bool,
+    // A shortener host with a short code, or any link that redirects to another domain; the
+    // service's homepage alone does not count.
     pub is_shortened: bool,
+    pub shortener: Option<ShortenerMatch>,
+    pub short_code: Option<String>,
+    pub phishing_tier: Option<MatchTier>,
+    // Where the link's redirects led, when they were followed and could be.
+    pub redirect: Option<peek::RedirectOutcome>,
+}
+
+// Which shortener list entry a host matched, and how.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShortenerMatch {
+    pub domain: &'static str,
+    pub kind: ShortenerMatchKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShortenerMatchKind {
+    // The host is the list entry itself, e.g. `bit.ly`.
+    Exact,
+    // The host is a subdomain of the entry, e.g. `foo.bit.ly`.
+    Subdomain,
+}
+
+impl ShortenerMatchKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ShortenerMatchKind::Exact => "exact",
+            ShortenerMatchKind::Subdomain => "subdomain",
+        }
+    }
+}
+
+// Which kind of deny rule matched: an exact full URL or a whole domain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchTier {
+    Url,
+    Domain,
+}
+
+impl MatchTier {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MatchTier::Url => "url",
+            MatchTier::Domain => "domain",
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PhishingList {
-    pub flagged_sites: Vec<String>,
+    pub flagged_sites: Vec<ListEntry>,
+}
+
+// A local deny or allow entry, written as a plain string or as `{"site": ..., "expires_at": ...}`
+// with an RFC 3339 time after which it no longer applies.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawListEntry")]
+pub struct ListEntry {
+    pub site: String,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawListEntry {
+    Plain(String),
+    Timed { site: String, expires_at: String },
+}
+
+impl TryFrom<RawListEntry> for ListEntry {
+    type Error = String;
+
+    fn try_from(raw: RawListEntry) -> Result<Self, String> {
+        match raw {
+            RawListEntry::Plain(site) => Ok(ListEntry { site, expires_at: None }),
+            RawListEntry::Timed { site, expires_at } => {
+                let expires_at = chrono::DateTime::parse_from_rfc3339(&expires_at)
+                    .map_err(|e| format!("{}: invalid expires_at {:?}: {}", site, expires_at, e))?;
+                Ok(ListEntry { site, expires_at: Some(expires_at.to_utc()) })
+            }
+        }
+    }
+}
+
+impl ListEntry {
+    fn is_live(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+// Entries that have not expired, as plain strings.
+fn live_sites(entries: &[ListEntry]) -> Vec<String> {
+    let now = chrono::Utc::now();
+    entries.iter().filter(|e| e.is_live(now)).map(|e| e.site.clone()).collect()
 }
 
 
+// `url` joined onto `base` as a browser resolves an href; `None` when there is no base.
+// Absolute URLs are unaffected by a base, and relative input without one is rejected.
+fn resolve_against_base(url: &str, base: Option<&str>) -> Result<Option<String>, String> {
+    let Some(base) = base else {
+        let url = url.trim();
+        if !url.starts_with("//") && url.starts_with(['/', '.', '?', '#']) {
+            return Err(format!("Relative URL {} requires a base", url));
+        }
+        return Ok(None);
+    };
+    let base = Url::parse(base.trim())
+        .ok()
+        .filter(|base| matches!(base.scheme(), "http" | "https"))
+        .ok_or_else(|| format!("Invalid base URL: {}", base))?;
+    base.join(url.trim()).map(|resolved| Some(resolved.to_string())).map_err(|e| format!("Cannot resolve {} against {}: {}", url, base, e))
+}
+
 fn is_valid_url(url: &str) -> bool {
+    validate_url(url).is_ok()
+}
+
+// Why a URL is rejected for analysis, if it is.
+fn validate_url(url: &str) -> Result<(), String> {
     if url.len() > 2048 {
         log::warn!("URL exceeds 2048 characters: {}", url);
-        return false;
+        return Err("URL exceeds 2048 characters".to_string());
     }
     let normalized_url = normalize_url(url);
     match Url::parse(&normalized_url) {  // Use normalized_url here
         Ok(parsed_url) => {
-            let valid = parsed_url.scheme() == "http" || parsed_url.scheme() == "https";
-            if !valid {
-                log::debug!("Invalid scheme for URL: {}", normalized_url);            }
-            valid
+            if parsed_url.scheme() == "http" || parsed_url.scheme() == "https" {
+                Ok(())
+            } else if parsed_url.scheme() == "mailto" {
+                // Only the recipient's domain is analyzed, so it has to be there.
+                mailto_domain(&parsed_url).map(|_| ()).ok_or_else(|| "mailto: link has no valid recipient domain".to_string())
+            } else {
+                log::debug!("Invalid scheme for URL: {}", normalized_url);
+                Err(format!("Unsupported scheme: {}", parsed_url.scheme()))
+            }
         }
         Err(e) => {
-            log::debug!("Failed to parse URL '{}': {}", normalized_url, e);            false
+            log::debug!("Failed to parse URL '{}': {}", normalized_url, e);
+            Err(format!("Failed to parse URL: {}", e))
         }
     }
 }
 
 fn normalize_url(url: &str) -> String {
     // If the URL parses as-is, return it; otherwise, prepend "https://"
-    match Url::parse(url) {
+    let normalized_url = match Url::parse(url) {
         Ok(_) => url.to_string(),
         Err(_) => format!("https://{}", url),
+    };
+    if config().canonicalize_paths {
+        canonicalize_path(&normalized_url, config().strip_trailing_slash).unwrap_or(normalized_url)
+    } else {
+        normalized_url
     }
 }
 
-fn is_known_shortener(url: &str) -> bool {
-    let normalized_url = normalize_url(url);
-    let shorteners: HashSet<&str> = [
-        "bit.ly", "tinyurl.com", "t.co", "goo.gl", "is.gd", "cli.gs", "pic.gd",
-        "DwarfURL.com", "ow.ly", "snipurl.com", "short.to", "BudURL.com",
-        "ping.fm", "post.ly", "Just.as", "bkite.com", "snipr.com", "fic.kr",
-        "loopt.us", "doiop.com", "twitthis.com", "htxt.it", "AltURL.com",
-        "RedirX.com", "DigBig.com", "tiny.cc", "u.nu", "u.to", "ln-s.net",
-        "twurl.nl", "zi.ma", "urlx.ie", "adjix.com", "cutt.ly", "tr.im",
-        "tiny.pl", "url4.eu", "fave.co", "hurl.ws", "ur1.ca", "x.co",
-        "prettylinkpro.com", "scrnch.me", "filoops.info", "vzturl.com",
-        "qr.net", "1url.com", "tweez.me", "v.gd", "tr.im", "link.zip.net",
-        "tinyarrows.com", "shrinkster.com", "go2.me", "go2l.ink", "youtu.be",
-        "amzn.to",
-    ].iter().copied().collect();
-
-    let parsed_url = match Url::parse(&normalized_url) {
-        Ok(url) => url,
-        Err(_e) => {
-            return false;
+// Empty path becomes `/`, runs of slashes in the path collapse to one, and with
+// `strip_trailing_slash` a trailing `/` is dropped. Path case is left untouched.
+fn canonicalize_path(url: &str, strip_trailing_slash: bool) -> Option<String> {
+    let mut parsed_url = Url::parse(url).ok()?;
+    if parsed_url.cannot_be_a_base() {
+        return None;
+    }
+    let mut path = String::with_capacity(parsed_url.path().len());
+    for c in parsed_url.path().chars() {
+        if !(c == '/' && path.ends_with('/')) {
+            path.push(c);
         }
+    }
+    if strip_trailing_slash && path.len() > 1 && path.ends_with('/') {
+        path.pop();
+    }
+    parsed_url.set_path(&path);
+    Some(parsed_url.to_string())
+}
+
+const SHORTENERS: &[&str] = &[
+    "bit.ly", "tinyurl.com", "t.co", "goo.gl", "is.gd", "cli.gs", "pic.gd",
+    "DwarfURL.com", "ow.ly", "snipurl.com", "short.to", "BudURL.com",
+    "ping.fm", "post.ly", "Just.as", "bkite.com", "snipr.com", "fic.kr",
+    "loopt.us", "doiop.com", "twitthis.com", "htxt.it", "AltURL.com",
+    "RedirX.com", "DigBig.com", "tiny.cc", "u.nu", "u.to", "ln-s.net",
+    "twurl.nl", "zi.ma", "urlx.ie", "adjix.com", "cutt.ly", "tr.im",
+    "tiny.pl", "url4.eu", "fave.co", "hurl.ws", "ur1.ca", "x.co",
+    "prettylinkpro.com", "scrnch.me", "filoops.info", "vzturl.com",
+    "qr.net", "1url.com", "tweez.me", "v.gd", "link.zip.net",
+    "tinyarrows.com", "shrinkster.com", "go2.me", "go2l.ink", "youtu.be",
+    "amzn.to",
+];
+
+// The shortener list entry the URL's host matches: an exact match first, else the most
+// specific entry the host is a subdomain of, so the result never depends on list order.
+fn known_shortener(url: &str) -> Option<ShortenerMatch> {
+    let parsed_url = Url::parse(&normalize_url(url)).ok()?;
+    let host = parsed_url.host_str()?.to_lowercase();
+    if let Some(domain) = SHORTENERS.iter().find(|s| s.eq_ignore_ascii_case(&host)) {
+        return Some(ShortenerMatch { domain, kind: ShortenerMatchKind::Exact });
+    }
+    SHORTENERS
+        .iter()
+        .filter(|s| host.ends_with(&format!(".{}", s.to_lowercase())))
+        .max_by_key(|s| s.len())
+        .map(|domain| ShortenerMatch { domain, kind: ShortenerMatchKind::Subdomain })
+}
+
+// The path of a short link without its slashes, e.g. `3xYz` for `https://bit.ly/3xYz`.
+fn short_code(url: &str) -> Option<String> {
+    let parsed_url = Url::parse(url).ok()?;
+    let code = parsed_url.path().trim_matches('/');
+    (!code.is_empty()).then(|| code.to_string())
+}
+
+// Settings every outbound client shares: rustls with the configured minimum TLS version and the
+// connect and request timeouts.
+fn http_client_builder(config: &config::Config) -> reqwest::ClientBuilder {
+    let min_tls_version = match config.min_tls_version {
+        config::MinTlsVersion::Tls12 => reqwest::tls::Version::TLS_1_2,
+        config::MinTlsVersion::Tls13 => reqwest::tls::Version::TLS_1_3,
     };
+    Client::builder()
+        .use_rustls_tls()
+        .min_tls_version(min_tls_version)
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .timeout(Duration::from_secs(config.request_timeout_secs))
+}
 
-    if let Some(host) = parsed_url.host_str() {
-        shorteners.contains(host) || shorteners.iter().any(|s| host.ends_with(&format!(".{}", s)))
-    } else {
-        false
+// The shared client for feeds, CT, reputation and webhooks; offline, none is built at all.
+fn outbound_client(config: &config::Config) -> Result<Option<Client>, reqwest::Error> {
+    (!config.offline).then(|| http_client_builder(config).build()).transpose()
+}
+
+// Host (lowercased), port, path and query of a URL, ignoring scheme and fragment.
+// Paths stay case-sensitive since servers may treat them that way.
+fn canonical_url_key(url: &str) -> Option<String> {
+    let parsed_url = Url::parse(&normalize_url(url)).ok()?;
+    let host = parsed_url.host_str()?.to_lowercase();
+    let mut key = match parsed_url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host,
+    };
+    key.push_str(parsed_url.path());
+    if let Some(query) = parsed_url.query() {
+        key.push('?');
+        key.push_str(query);
+    }
+    Some(key)
+}
+
+// Deny entries carrying a path or query are full-URL rules; bare hosts are domain rules.
+fn is_url_entry(entry: &str) -> bool {
+    match Url::parse(&normalize_url(entry)) {
+        Ok(parsed_url) => parsed_url.path() != "/" || parsed_url.query().is_some(),
+        Err(_) => false,
+    }
+}
+
+fn matches_url_entry(url: &str, entry: &str) -> bool {
+    match (canonical_url_key(url), canonical_url_key(entry)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
     }
 }
 
-async fn fetch_phishing_list() -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
-    const URL: &str = "https://raw.githubusercontent.com/polkadot-js/phishing/master/all.json";
-    let client = Client::builder()
-        .timeout(Duration::from_secs(10))
-        .build()?;
-    let response = client.get(URL).send().await?;
-    let json: Value = response.json().await?;
-    Ok(json)
+fn matches_domain_entry(url: &str, entry: &str) -> bool {
+    let Some(host) = Url::parse(&normalize_url(url)).ok().and_then(|u| u.host_str().map(str::to_lowercase)) else {
+        return false;
+    };
+    // Unicode entries compare in the parser's NFC, punycoded form, however they were typed.
+    let entry = entry.trim().to_lowercase();
+    let entry = idna::domain_to_ascii(&entry).unwrap_or(entry);
+    host == entry || host.ends_with(&format!(".{}", entry))
+}
+
+// Signals derived from the host's shape, which internal naming schemes trip without being suspicious.
+const STRUCTURAL_SIGNALS: &[&str] = &[
+    "brand_context_mismatch", "dash_in_domain", "encoded_host", "improbable_ngrams", "leetspeak_brand",
+    "multiple_subdomains", "non_nfc_host", "numeric_or_hex_host", "protocol_token_in_host", "punycode_brand_spoof",
+    "rtl_in_host", "suspicious_tld", "top_site_lookalike",
+];
+
+fn internal_domain<'a>(url: &str, internal_domains: &'a [String]) -> Option<&'a str> {
+    internal_domains.iter().find(|entry| matches_domain_entry(url, entry)).map(String::as_str)
+}
+
+// Removes the structural signals from the features and analysis, returning those that had fired.
+fn drop_structural_signals(url_features: &mut HashMap<String, bool>, analysis: &mut HashMap<String, String>) -> Vec<&'static str> {
+    let dropped: Vec<&str> = STRUCTURAL_SIGNALS
+        .iter()
+        .copied()
+        .filter(|signal| {
+            let plain = url_features.remove(*signal).unwrap_or(false);
+            let prefixed = url_features.remove(&format!("has_{}", signal)).unwrap_or(false);
+            plain || prefixed
+        })
+        .collect();
+    for signal in &dropped {
+        analysis.remove(*signal);
+        analysis.remove(&format!("has_{}", signal));
+    }
+    dropped
 }
 
-async fn check_online_phishing_db(url: &str, phishing_list: &Result<Value, String>) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-    let json = match phishing_list {
-        Ok(json) => json,
+// Host shape for campaign correlation: public suffix, registrable label length and the structural
+// signals that fired, e.g. `zip:9:dash_in_domain+multiple_subdomains`. `None` when none fired, since
+// plain hosts share too little to correlate on.
+fn structural_fingerprint(url: &str, fired: impl Fn(&str) -> bool) -> Option<String> {
+    let signals: Vec<&str> = STRUCTURAL_SIGNALS.iter().copied().filter(|signal| fired(signal)).collect();
+    if signals.is_empty() {
+        return None;
+    }
+    let domain = registrable_domain(url)?;
+    let (label, suffix) = domain.split_once('.').unwrap_or((&domain, ""));
+    Some(format!("{}:{}:{}", suffix, label.chars().count(), signals.join("+")))
+}
+
+async fn check_online_phishing_db(url: &str, phishing_list: &Result<feeds::DenyList, String>) -> Result<Option<MatchTier>, Box<dyn std::error::Error + Send + Sync>> {
+    match phishing_list {
+        Ok(list) => Ok(list.check(url)),
         Err(e) => {
             log::warn!("Failed to load phishing list: {}", e);
-            return Ok(false);
+            Ok(None)
         }
-    };
-    if let Some(Value::Array(blacklist)) = json.get("deny") {
-        let url_lower = url.to_lowercase();
-        Ok(blacklist.iter().any(|site| {
-            site.as_str().map_or(false, |s| url_lower.contains(&s.to_lowercase()))
-        }))
-    } else {
-        Ok(false)
     }
 }
 
-fn check_local_phishing_db(url: &str) -> bool {
-    let content = match fs::read_to_string("filters/caught.json") {
+const LOCAL_DENY_FILE: &str = "filters/caught.json";
+
+// Last successfully parsed deny file, loaded at startup and on SIGHUP; kept while the file
+// is malformed or has gone missing.
+#[derive(Default)]
+struct LocalDenyList {
+    // Expired entries included; they are filtered out as the clock passes them.
+    entries: Vec<ListEntry>,
+    // Logged once per distinct problem rather than on every reload.
+    last_error: Option<String>,
+}
+
+impl LocalDenyList {
+    // Replaces the entries with those in `path`, or keeps the current ones if it cannot be read or parsed.
+    fn reload(&mut self, path: &str) -> Result<usize, String> {
+        let result = fs::read_to_string(path).map_err(|e| format!("Failed to read local phishing DB {}: {}", path, e)).and_then(|content| {
+            // serde_json's message carries the line and column of the problem.
+            serde_json::from_str::<PhishingList>(&content).map_err(|e| format!("Malformed local phishing DB {}: {}", path, e))
+        });
+        match result {
+            Ok(json) => {
+                self.entries = json.flagged_sites;
+                self.last_error = None;
+                Ok(self.entries.len())
+            }
+            Err(e) => {
+                if self.last_error.as_ref() != Some(&e) {
+                    log::error!("{}; keeping {} previously loaded entries", e, self.entries.len());
+                    self.last_error = Some(e.clone());
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn live(&self, now: chrono::DateTime<chrono::Utc>) -> Vec<ListEntry> {
+        self.entries.iter().filter(|e| e.is_live(now)).cloned().collect()
+    }
+}
+
+static LOCAL_DENY_LIST: Mutex<Option<LocalDenyList>> = Mutex::new(None);
+
+// The entries still in effect; the file itself is only read by `reload_local_deny_list`.
+fn load_local_deny_list() -> Vec<ListEntry> {
+    LOCAL_DENY_LIST.lock().unwrap().as_ref().map_or_else(Vec::new, |list| list.live(chrono::Utc::now()))
+}
+
+fn reload_local_deny_list() -> Result<usize, String> {
+    LOCAL_DENY_LIST.lock().unwrap().get_or_insert_with(LocalDenyList::default).reload(LOCAL_DENY_FILE)
+}
+
+// A missing deny file is a deployment mistake unless `local_deny_optional` says otherwise;
+// a malformed one only loses its entries until it is fixed.
+fn check_local_deny_file() -> Result<(), String> {
+    let mut list = LocalDenyList::default();
+    open_local_deny_file(&mut list, LOCAL_DENY_FILE, config().local_deny_optional)?;
+    *LOCAL_DENY_LIST.lock().unwrap() = Some(list);
+    Ok(())
+}
+
+fn open_local_deny_file(list: &mut LocalDenyList, path: &str, optional: bool) -> Result<(), String> {
+    if !std::path::Path::new(path).exists() {
+        if optional {
+            log::info!("No local phishing DB at {}; running without one", path);
+            return Ok(());
+        }
+        return Err(format!("Local phishing DB {} not found; create it or set local_deny_optional", path));
+    }
+    if let Ok(count) = list.reload(path) {
+        log::info!("Loaded {} local deny entries from {}", count, path);
+    }
+    Ok(())
+}
+
+fn check_local_phishing_db(url: &str) -> Option<MatchTier> {
+    let flagged_sites = load_local_deny_list();
+    check_deny_entries(url, flagged_sites.iter().map(|e| &e.site).chain(&config().deny_urls))
+}
+
+// When `url` is denied locally only by time-bounded entries, the latest time one of them expires.
+fn local_deny_expiry(url: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    deny_expiry(url, load_local_deny_list())
+}
+
+fn deny_expiry(url: &str, live: Vec<ListEntry>) -> Option<chrono::DateTime<chrono::Utc>> {
+    let (timed, permanent): (Vec<ListEntry>, Vec<ListEntry>) = live.into_iter().partition(|e| e.expires_at.is_some());
+    if check_deny_entries(url, permanent.iter().map(|e| &e.site).chain(&config().deny_urls)).is_some() {
+        return None;
+    }
+    timed.iter().filter(|e| check_deny_entries(url, std::iter::once(&e.site)).is_some()).filter_map(|e| e.expires_at).max()
+}
+
+const LOCAL_ALLOW_FILE: &str = "filters/allow.json";
+
+// Same schema as the deny file; a missing file means no exceptions.
+fn load_local_allow_list() -> Vec<String> {
+    let content = match fs::read_to_string(LOCAL_ALLOW_FILE) {
         Ok(content) => content,
         Err(e) => {
-            log::warn!("Failed to read local phishing DB: {}", e);
-            return false;
+            log::info!("No local allow list loaded from {}: {}", LOCAL_ALLOW_FILE, e);
+            return Vec::new();
         }
     };
-    let json: PhishingList = match serde_json::from_str(&content) {
-        Ok(json) => json,
+    match serde_json::from_str::<PhishingList>(&content) {
+        Ok(list) => live_sites(&list.flagged_sites),
         Err(e) => {
-            log::warn!("Failed to parse local phishing DB: {}", e);
-            return false;
+            log::warn!("Failed to parse local allow list: {}", e);
+            Vec::new()
         }
+    }
+}
+
+fn check_deny_entries<'a>(url: &str, entries: impl Iterator<Item = &'a String>) -> Option<MatchTier> {
+    let (url_entries, domain_entries): (Vec<&String>, Vec<&String>) = entries.partition(|e| is_url_entry(e));
+    if url_entries.iter().any(|e| matches_url_entry(url, e)) {
+        Some(MatchTier::Url)
+    } else if domain_entries.iter().any(|e| matches_domain_entry(url, e)) {
+        Some(MatchTier::Domain)
+    } else {
+        None
+    }
+}
+
+fn registrable_domain(url: &str) -> Option<String> {
+    let parsed_url = Url::parse(&normalize_url(url)).ok()?;
+    let host = parsed_url.host_str()?.to_lowercase();
+    Some(psl::domain_str(&host).unwrap_or(&host).to_string())
+}
+
+fn check_fuzzy_phishing_db(url: &str, phishing_list: &Result<feeds::DenyList, String>) -> Option<fuzzy::FuzzyMatch> {
+    let domain = registrable_domain(url)?;
+    let Ok(list) = phishing_list else {
+        return None;
     };
-    json.flagged_sites.iter().any(|s| url == s)
+    fuzzy::closest_match(&domain, list.hosts.iter().map(String::as_str), 1)
 }
 
-async fn checking_url_enhanced(url: &str, phishing_list: &Result<Value, String>) -> UrlCheckResult {
+const MAX_REDIRECTS: usize = 5;
+
+async fn checking_url_enhanced(
+    url: &str,
+    phishing_list: &Result<feeds::DenyList, String>,
+    // Outbound requests the redirect check may make; `None` skips it.
+    redirect_budget: Option<usize>,
+    timings: &mut Timings,
+) -> UrlCheckResult {
     let normalized_url = normalize_url(url);
-    let is_phishing_local = check_local_phishing_db(&normalized_url);
-    let is_phishing_online = check_online_phishing_db(&normalized_url, phishing_list).await.unwrap_or_else(|e| {
+    let start = timings.start();
+    let local_tier = check_local_phishing_db(&normalized_url);
+    timings.record("local_db", start);
+    let start = timings.start();
+    let online_tier = check_online_phishing_db(&normalized_url, phishing_list).await.unwrap_or_else(|e| {
         log::warn!("Online phishing check failed: {}", e);
-        false
+        None
     });
-    let is_shortened = is_known_shortener(&normalized_url);
+    timings.record("online_db", start);
+    // A full-URL rule is the more specific match, so it wins when both tiers hit.
+    let phishing_tier = if local_tier == Some(MatchTier::Url) || online_tier == Some(MatchTier::Url) {
+        Some(MatchTier::Url)
+    } else {
+        local_tier.or(online_tier)
+    };
+    let shortener = known_shortener(&normalized_url);
+    let short_code = shortener.and_then(|_| short_code(&normalized_url));
+    // A dead or slow link is treated as not redirecting rather than failing the analysis.
+    let mut redirect = None;
+    if let Some(budget) = redirect_budget
+        && let Ok(parsed_url) = Url::parse(&normalized_url)
+    {
+        let start = timings.start();
+        let timeout = Duration::from_secs(config().request_timeout_secs);
+        match peek::follow_redirects(&parsed_url, MAX_REDIRECTS, budget, timeout).await {
+            Ok(outcome) => redirect = Some(outcome),
+            Err(e) => log::warn!("Redirect check failed for {}: {}", normalized_url, e),
+        }
+        timings.record("redirect", start);
+    }
+    let cross_domain = matches!(redirect, Some(peek::RedirectOutcome::Followed { cross_domain: true, .. }));
     UrlCheckResult {
-        is_phishing: is_phishing_local || is_phishing_online,
-        is_shortened,
+        is_phishing: phishing_tier.is_some(),
+        is_shortened: short_code.is_some() || cross_domain,
+        shortener,
+        short_code,
+        phishing_tier,
+        redirect,
     }
 }
 
 
 struct RiskWeights {
-    shortened: f32,
     // redirects: f32,
     phishing: f32,
     suspicious_tld: f32,
@@ -178,10 +1017,41 @@ struct RiskWeights {
     double_slash: f32,
     dash_in_domain: f32,
     multiple_subdomains: f32,
+    high_risk_geo: f32,
+    display_mismatch: f32,
+    weak_tls: f32,
+    very_recent_cert_issuance: f32,
+    weak_email_auth: f32,
+    ip_host_with_brand_path: f32,
+    embedded_dangerous_uri: f32,
+    dangerous_download: f32,
+    brand_context_mismatch: f32,
+    protocol_token_in_host: f32,
+    numeric_or_hex_host: f32,
+    leetspeak_brand: f32,
+    deep_path: f32,
+    deep_path_with_brand: f32,
+    excessive_query: f32,
+    punycode_brand_spoof: f32,
+    rtl_in_host: f32,
+    encoded_host: f32,
+    non_nfc_host: f32,
+    improbable_ngrams: f32,
+    recently_trending_pattern: f32,
+    shared_hosting: f32,
+    in_top_sites: f32,
+    top_site_lookalike: f32,
+    open_redirect_param: f32,
+    embedded_url_in_path: f32,
+    parameter_pollution: f32,
+    redirect_target_phishing: f32,
+    cross_origin_password_form: f32,
+    known_phishing_favicon: f32,
+    target_unreachable: f32,
+    target_error_status: f32,
 }
 
 const WEIGHTS: RiskWeights = RiskWeights {
-    shortened: 0.3,
     // redirects: 0.2,
     phishing: 0.9,
     suspicious_tld: 0.2,
@@ -190,192 +1060,3207 @@ const WEIGHTS: RiskWeights = RiskWeights {
     double_slash: 0.2,
     dash_in_domain: 0.1,
     multiple_subdomains: 0.1,
+    high_risk_geo: 0.2,
+    display_mismatch: 0.5,
+    weak_tls: 0.2,
+    very_recent_cert_issuance: 0.2,
+    // Says the sender domain is spoofable, not that this one is malicious.
+    weak_email_auth: 0.2,
+    ip_host_with_brand_path: 0.6,
+    embedded_dangerous_uri: 0.7,
+    dangerous_download: 0.5,
+    brand_context_mismatch: 0.4,
+    protocol_token_in_host: 0.3,
+    numeric_or_hex_host: 0.4,
+    leetspeak_brand: 0.6,
+    deep_path: 0.2,
+    // Padding that pushes the real domain off-screen while the path names a brand.
+    deep_path_with_brand: 0.2,
+    // Kept small: long analytics query strings are common on legitimate links.
+    excessive_query: 0.1,
+    punycode_brand_spoof: 0.7,
+    // Bidi marks and RTL letters let a host display differently from where it resolves.
+    rtl_in_host: 0.6,
+    // Browsers never need an encoded host, so this is obfuscation.
+    encoded_host: 0.5,
+    // Same host, different code points: only useful for slipping past exact-match filters.
+    non_nfc_host: 0.5,
+    // Kept small: some real names are unusual letter salad too.
+    improbable_ngrams: 0.3,
+    // Only as strong as the verdicts it echoes, so it nudges rather than decides.
+    recently_trending_pattern: 0.2,
+    // Plenty of legitimate projects live on these too; it is the free, anonymous sign-up that counts.
+    shared_hosting: 0.3,
+    // Popular domains get a discount; deny-list hits are still held at `phishing_score_floor`.
+    in_top_sites: -0.5,
+    top_site_lookalike: 0.5,
+    open_redirect_param: 0.2,
+    embedded_url_in_path: 0.2,
+    parameter_pollution: 0.3,
+    redirect_target_phishing: 0.6,
+    cross_origin_password_form: 0.6,
+    // Kits reuse their icons, so a listed hash is strong evidence even on a brand-new domain.
+    known_phishing_favicon: 0.7,
+    // Mostly triage context: dead or erroring targets are common among taken-down phish and broken links alike.
+    target_unreachable: 0.1,
+    target_error_status: 0.1,
 };
 
 fn calculate_risk_score(
-    is_shortened: bool,
+    shortener_severity: Option<ShortenerSeverity>,
     // redirects: bool,
     in_phishing_db: bool,
     url_features: &HashMap<String, bool>,
+    // Weight of the matched entry in `suspicious_tlds`.
+    suspicious_tld_weight: Option<f32>,
+    reputation: &[reputation::Contribution],
+    profile: Option<&RiskProfile>,
 ) -> f32 {
+    // Profile overrides are keyed by signal identifier, e.g. `suspicious_tld` or `phishing_db`.
+    let weight = |signal: &str, default: f32| match config().is_suppressed(signal) {
+        true => 0.0,
+        false => profile.and_then(|p| p.weights.get(signal)).copied().unwrap_or(default),
+    };
     let mut score = 0.0;
-    if is_shortened { score += WEIGHTS.shortened; }
+    if let Some(severity) = shortener_severity { score += weight("shortened", severity.weight()); }
     // if redirects { score += WEIGHTS.redirects; }
-    if in_phishing_db { score += WEIGHTS.phishing; }
-    if *url_features.get("has_suspicious_tld").unwrap_or(&false) { score += WEIGHTS.suspicious_tld; }
-    if *url_features.get("has_ip_address").unwrap_or(&false) { score += WEIGHTS.ip_address; }
-    if *url_features.get("has_at_symbol").unwrap_or(&false) { score += WEIGHTS.at_symbol; }
-    if *url_features.get("has_double_slash").unwrap_or(&false) { score += WEIGHTS.double_slash; }
-    if *url_features.get("has_dash_in_domain").unwrap_or(&false) { score += WEIGHTS.dash_in_domain; }
-    if *url_features.get("has_multiple_subdomains").unwrap_or(&false) { score += WEIGHTS.multiple_subdomains; }
-    score.min(1.0)
+    if in_phishing_db { score += weight("phishing_db", WEIGHTS.phishing); }
+    if *url_features.get("has_suspicious_tld").unwrap_or(&false) {
+        score += weight("suspicious_tld", suspicious_tld_weight.unwrap_or(WEIGHTS.suspicious_tld));
+    }
+    if *url_features.get("has_ip_address").unwrap_or(&false) { score += weight("ip_address", WEIGHTS.ip_address); }
+    if *url_features.get("has_at_symbol").unwrap_or(&false) { score += weight("at_symbol", WEIGHTS.at_symbol); }
+    if *url_features.get("has_double_slash").unwrap_or(&false) { score += weight("double_slash", WEIGHTS.double_slash); }
+    if *url_features.get("has_dash_in_domain").unwrap_or(&false) { score += weight("dash_in_domain", WEIGHTS.dash_in_domain); }
+    if *url_features.get("has_multiple_subdomains").unwrap_or(&false) { score += weight("multiple_subdomains", WEIGHTS.multiple_subdomains); }
+    if *url_features.get("high_risk_geo").unwrap_or(&false) { score += weight("high_risk_geo", WEIGHTS.high_risk_geo); }
+    if *url_features.get("display_mismatch").unwrap_or(&false) { score += weight("display_mismatch", WEIGHTS.display_mismatch); }
+    if *url_features.get("weak_tls").unwrap_or(&false) { score += weight("weak_tls", WEIGHTS.weak_tls); }
+    if *url_features.get("very_recent_cert_issuance").unwrap_or(&false) { score += weight("very_recent_cert_issuance", WEIGHTS.very_recent_cert_issuance); }
+    if *url_features.get("weak_email_auth").unwrap_or(&false) { score += weight("weak_email_auth", WEIGHTS.weak_email_auth); }
+    if *url_features.get("ip_host_with_brand_path").unwrap_or(&false) { score += weight("ip_host_with_brand_path", WEIGHTS.ip_host_with_brand_path); }
+    if *url_features.get("in_top_sites").unwrap_or(&false) { score += weight("in_top_sites", WEIGHTS.in_top_sites); }
+    if *url_features.get("top_site_lookalike").unwrap_or(&false) { score += weight("top_site_lookalike", WEIGHTS.top_site_lookalike); }
+    if *url_features.get("deep_path").unwrap_or(&false) { score += weight("deep_path", WEIGHTS.deep_path); }
+    if *url_features.get("deep_path_with_brand").unwrap_or(&false) { score += weight("deep_path_with_brand", WEIGHTS.deep_path_with_brand); }
+    if *url_features.get("excessive_query").unwrap_or(&false) { score += weight("excessive_query", WEIGHTS.excessive_query); }
+    if *url_features.get("brand_context_mismatch").unwrap_or(&false) { score += weight("brand_context_mismatch", WEIGHTS.brand_context_mismatch); }
+    if *url_features.get("protocol_token_in_host").unwrap_or(&false) { score += weight("protocol_token_in_host", WEIGHTS.protocol_token_in_host); }
+    if *url_features.get("numeric_or_hex_host").unwrap_or(&false) { score += weight("numeric_or_hex_host", WEIGHTS.numeric_or_hex_host); }
+    if *url_features.get("leetspeak_brand").unwrap_or(&false) { score += weight("leetspeak_brand", WEIGHTS.leetspeak_brand); }
+    if *url_features.get("dangerous_download").unwrap_or(&false) { score += weight("dangerous_download", WEIGHTS.dangerous_download); }
+    if *url_features.get("embedded_dangerous_uri").unwrap_or(&false) { score += weight("embedded_dangerous_uri", WEIGHTS.embedded_dangerous_uri); }
+    if *url_features.get("punycode_brand_spoof").unwrap_or(&false) { score += weight("punycode_brand_spoof", WEIGHTS.punycode_brand_spoof); }
+    if *url_features.get("rtl_in_host").unwrap_or(&false) { score += weight("rtl_in_host", WEIGHTS.rtl_in_host); }
+    if *url_features.get("encoded_host").unwrap_or(&false) { score += weight("encoded_host", WEIGHTS.encoded_host); }
+    if *url_features.get("non_nfc_host").unwrap_or(&false) { score += weight("non_nfc_host", WEIGHTS.non_nfc_host); }
+    if *url_features.get("improbable_ngrams").unwrap_or(&false) { score += weight("improbable_ngrams", WEIGHTS.improbable_ngrams); }
+    if *url_features.get("recently_trending_pattern").unwrap_or(&false) { score += weight("recently_trending_pattern", WEIGHTS.recently_trending_pattern); }
+    if *url_features.get("shared_hosting").unwrap_or(&false) { score += weight("shared_hosting", WEIGHTS.shared_hosting); }
+    if *url_features.get("open_redirect_param").unwrap_or(&false) { score += weight("open_redirect_param", WEIGHTS.open_redirect_param); }
+    if *url_features.get("embedded_url_in_path").unwrap_or(&false) { score += weight("embedded_url_in_path", WEIGHTS.embedded_url_in_path); }
+    if *url_features.get("parameter_pollution").unwrap_or(&false) { score += weight("parameter_pollution", WEIGHTS.parameter_pollution); }
+    if *url_features.get("redirect_target_phishing").unwrap_or(&false) { score += weight("redirect_target_phishing", WEIGHTS.redirect_target_phishing); }
+    if *url_features.get("cross_origin_password_form").unwrap_or(&false) { score += weight("cross_origin_password_form", WEIGHTS.cross_origin_password_form); }
+    if *url_features.get("known_phishing_favicon").unwrap_or(&false) { score += weight("known_phishing_favicon", WEIGHTS.known_phishing_favicon); }
+    if *url_features.get("target_unreachable").unwrap_or(&false) { score += weight("target_unreachable", WEIGHTS.target_unreachable); }
+    if *url_features.get("target_error_status").unwrap_or(&false) { score += weight("target_error_status", WEIGHTS.target_error_status); }
+    for feature in rules::custom_features() {
+        if *url_features.get(&feature.name).unwrap_or(&false) { score += weight(&feature.name, feature.weight); }
+    }
+    for rule in &config().composite_rules {
+        if *url_features.get(&rule.name).unwrap_or(&false) { score += weight(&rule.name, rule.weight); }
+    }
+    let default_profile = RiskProfile::default();
+    let thresholds = profile.unwrap_or(&default_profile);
+    let mut verdict_floor: f32 = 0.0;
+    for rule in rules::detection_rules() {
+        if *url_features.get(&rule.name).unwrap_or(&false) {
+            score += weight(&rule.name, rule.score);
+            let floor = match rule.verdict {
+                _ if config().is_suppressed(&rule.name) => 0.0,
+                Some(rules::Verdict::High) => thresholds.high_threshold,
+                Some(rules::Verdict::Medium) => thresholds.medium_threshold,
+                None => 0.0,
+            };
+            verdict_floor = verdict_floor.max(floor);
+        }
+    }
+    for contribution in reputation {
+        score += weight(&format!("reputation_{}", contribution.provider), contribution.weight) * contribution.score;
+    }
+    clamp_score(score.max(verdict_floor), in_phishing_db)
 }
 
-fn extract_url_features(url: &str) -> HashMap<String, bool> {
-    let normalized_url = normalize_url(url);
-    let mut features = HashMap::new();
-    let suspicious_tlds = ["xyz", "top", "club", "online", "site", "info", "biz"];
-    let parsed_url = match Url::parse(&normalized_url) {
-        Ok(url) => url,
-        Err(_) => return features,
+// Keeps the score consistent with the verdict, so a deny-list hit is never diluted into a lower bucket.
+fn clamp_score(score: f32, in_phishing_db: bool) -> f32 {
+    let (floor, ceiling) = if in_phishing_db {
+        (config().phishing_score_floor, config().phishing_score_ceiling)
+    } else {
+        (0.0, config().non_phishing_score_ceiling)
     };
+    score.max(floor).min(ceiling).clamp(0.0, 1.0)
+}
 
-    if let Some(domain) = parsed_url.domain() {
-        let parts: Vec<&str> = domain.split('.').collect();
-        if parts.len() > 1 {
-            let tld = parts[parts.len() - 1];
-            features.insert("has_suspicious_tld".to_string(), suspicious_tlds.contains(&tld));
-        }
-        features.insert("has_dash_in_domain".to_string(), domain.contains('-'));
-        features.insert("has_multiple_subdomains".to_string(), domain.matches('.').count() > 2);
-    }
+const SCORE_DECIMALS: i32 = 3;
 
-    features.insert("has_ip_address".to_string(), normalized_url.parse::<std::net::IpAddr>().is_ok());
+fn round_score(score: f32) -> f64 {
+    let factor = 10f64.powi(SCORE_DECIMALS);
+    (f64::from(score) * factor).round() / factor
+}
+
+// f32 scores widen to values like 0.6000000238 in JSON; emit them at fixed precision instead.
+fn serialize_optional_score<S: serde::Serializer>(score: &Option<f32>, serializer: S) -> Result<S::Ok, S::Error> {
+    match score {
+        Some(score) => serializer.serialize_f64(round_score(*score)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RiskBucket {
+    Low,
+    Medium,
+    High,
+    // No score: network checks failed under `on_network_failure = "neutral"`.
+    Unknown,
+}
+
+impl RiskBucket {
+    fn from_score(risk_score: f32) -> RiskBucket {
+        RiskBucket::from_score_in(risk_score, &RiskProfile::default())
+    }
+
+    // Bucket under a profile's thresholds.
+    fn from_score_in(risk_score: f32, profile: &RiskProfile) -> RiskBucket {
+        if risk_score >= profile.high_threshold {
+            RiskBucket::High
+        } else if risk_score >= profile.medium_threshold {
+            RiskBucket::Medium
+        } else {
+            RiskBucket::Low
+        }
+    }
+
+    fn from_name(name: &str) -> RiskBucket {
+        [RiskBucket::Low, RiskBucket::Medium, RiskBucket::High]
+            .into_iter()
+            .find(|bucket| bucket.as_str() == name)
+            .unwrap_or(RiskBucket::Unknown)
+    }
+
+    // One-word decision for header-only clients.
+    fn verdict(&self) -> &'static str {
+        match self {
+            RiskBucket::Low => "allow",
+            RiskBucket::Medium => "warn",
+            RiskBucket::High => "block",
+            RiskBucket::Unknown => "review",
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            RiskBucket::Low => "low",
+            RiskBucket::Medium => "medium",
+            RiskBucket::High => "high",
+            RiskBucket::Unknown => "unknown",
+        }
+    }
+
+    // Deliberately vague wording for public mode.
+    fn public_message(&self) -> &'static str {
+        match self {
+            RiskBucket::High => "This link is likely unsafe",
+            RiskBucket::Medium => "This link may be unsafe",
+            RiskBucket::Low => "No known risks found for this link",
+            RiskBucket::Unknown => "This link could not be checked",
+        }
+    }
+
+    fn assessment(&self) -> &'static str {
+        match self {
+            RiskBucket::High => "High risk - Likely phishing",
+            RiskBucket::Medium => "Medium risk - Suspicious",
+            RiskBucket::Low => "Low risk - Likely safe",
+            RiskBucket::Unknown => "unknown",
+        }
+    }
+}
+
+fn extract_url_features(url: &str) -> HashMap<String, bool> {
+    let normalized_url = normalize_url(url);
+    let mut features = HashMap::new();
+    let parsed_url = match Url::parse(&normalized_url) {
+        Ok(url) => url,
+        Err(_) => return features,
+    };
+
+    if let Some(domain) = parsed_url.domain() {
+        if domain.contains('.') {
+            features.insert("has_suspicious_tld".to_string(), suspicious_tld(&parsed_url).is_some());
+        }
+        // Judged on the Unicode form: punycode's `xn--` prefix and delimiter are not dashes anyone typed.
+        let (unicode, _) = idna::domain_to_unicode(domain);
+        features.insert("has_dash_in_domain".to_string(), unicode.contains('-'));
+        features.insert("has_multiple_subdomains".to_string(), domain.matches('.').count() > 2);
+    }
+
+    features.insert("has_ip_address".to_string(), matches!(parsed_url.host(), Some(Host::Ipv4(_) | Host::Ipv6(_))));
     features.insert("has_at_symbol".to_string(), normalized_url.contains('@'));
-    features.insert("has_double_slash".to_string(), normalized_url[8..].contains("//"));
+    // Checked on the input, since path canonicalization collapses these.
+    let after_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    features.insert("has_double_slash".to_string(), after_scheme.contains("//"));
+    features.insert("dangerous_download".to_string(), dangerous_download(&parsed_url).is_some());
+    features.insert("deep_path".to_string(), path_depth(&parsed_url) > config().max_path_depth);
+    let (param_count, query_length) = query_size(&parsed_url);
+    features.insert(
+        "excessive_query".to_string(),
+        param_count > config().max_query_params || query_length > config().max_query_length,
+    );
+    for feature in rules::custom_features() {
+        features.insert(feature.name.clone(), feature.matches(&parsed_url));
+    }
     features
 }
 
+// Last host label and its `suspicious_tlds` weight, when listed there.
+fn suspicious_tld(parsed_url: &Url) -> Option<(String, f32)> {
+    let tld = parsed_url.domain()?.trim_end_matches('.').rsplit('.').next()?.to_lowercase();
+    let weight = config()
+        .suspicious_tlds
+        .iter()
+        .find(|(listed, _)| listed.trim_start_matches('.').eq_ignore_ascii_case(&tld))
+        .map(|(_, weight)| *weight)?;
+    Some((tld, weight))
+}
+
+// Number of non-empty path segments, e.g. 8 for `/a/b/c/d/e/f/secure/login`.
+fn path_depth(parsed_url: &Url) -> usize {
+    parsed_url.path_segments().map_or(0, |segments| segments.filter(|s| !s.is_empty()).count())
+}
+
+// Number of query parameters and length of the raw query string.
+fn query_size(parsed_url: &Url) -> (usize, usize) {
+    (parsed_url.query_pairs().count(), parsed_url.query().map_or(0, str::len))
+}
+
+// Dangerous extension of the path's last segment, percent-decoded, e.g. `exe` for `/invoice.pdf%2Eexe`.
+// Returns the extension and the disguising one for double extensions like `.pdf.exe`.
+fn dangerous_download(parsed_url: &Url) -> Option<(String, Option<String>)> {
+    let segment = parsed_url.path_segments()?.next_back()?;
+    let file_name = percent_encoding::percent_decode_str(segment).decode_utf8_lossy().to_lowercase();
+    let mut parts = file_name.trim_end_matches(['.', ' ']).rsplit('.');
+    let extension = parts.next()?.to_string();
+    // The file name itself; `rsplit` yields the whole name when there is no dot.
+    let disguise = parts.next()?;
+    if !config().dangerous_extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&extension)) {
+        return None;
+    }
+    let disguise = parts.next().is_some().then(|| disguise.to_string()).filter(|d| d.len() <= 4 && !d.is_empty());
+    Some((extension, disguise))
+}
+
+// Host (and port) as typed, before the URL parser decodes or normalizes it.
+fn raw_host(url: &str) -> Option<&str> {
+    let after_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = after_scheme.split(['/', '?', '#']).next()?;
+    Some(authority.rsplit_once('@').map_or(authority, |(_, host)| host))
+}
+
+// A host written as numbers or hex rather than a name, as typed in the input: IPv4 in
+// hex, octal or integer form (`0x1f2e3d4c`, `3232235777`, which URL parsing silently decodes)
+// or a domain whose labels below the TLD are all numeric or hex-like (`12345.67890.com`,
+// `deadbeef1234.net`). Plain dotted-decimal IPs are left to `ip_address`. Returns the host as written.
+fn numeric_or_hex_host(url: &str) -> Option<String> {
+    let host = raw_host(url)?.split(':').next()?.trim_end_matches('.').to_lowercase();
+    let parsed_url = Url::parse(&normalize_url(url)).ok()?;
+    let labels: Vec<&str> = host.split('.').collect();
+    let suspicious = match parsed_url.host()? {
+        Host::Ipv4(ip) => ip.to_string() != host,
+        Host::Ipv6(_) => false,
+        // The TLD is exempt: `cafe` or `bad` are real TLDs and hex-like by accident.
+        Host::Domain(_) => labels.len() > 1 && labels[..labels.len() - 1].iter().all(|label| is_numeric_or_hex_label(label)),
+    };
+    suspicious.then_some(host)
+}
+
+fn is_numeric_or_hex_label(label: &str) -> bool {
+    if let Some(hex) = label.strip_prefix("0x") {
+        return !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    if !label.is_empty() && label.chars().all(|c| c.is_ascii_digit()) {
+        return true;
+    }
+    // Short hex-only words (`cafe`, `face`, `bead`) are ordinary names; long digit-bearing runs are not.
+    label.len() >= config().hex_label_min_len
+        && label.chars().all(|c| c.is_ascii_hexdigit())
+        && label.chars().any(|c| c.is_ascii_digit())
+}
+
+// An IP-literal host whose path names a protected brand or looks like a domain,
+// e.g. `http://192.0.2.10/paypal.com/login`. Returns the IP and the masquerading token.
+fn ip_host_brand_path(url: &str) -> Option<(String, String)> {
+    let parsed_url = Url::parse(&normalize_url(url)).ok()?;
+    let ip = match parsed_url.host()? {
+        Host::Ipv4(ip) => ip.to_string(),
+        Host::Ipv6(ip) => ip.to_string(),
+        Host::Domain(_) => return None,
+    };
+    let path = parsed_url.path().to_lowercase();
+    let token = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .find_map(|segment| {
+            domains_in_text(segment).into_iter().next().or_else(|| {
+                config().protected_brands.iter().find(|b| segment.contains(&b.to_lowercase())).cloned()
+            })
+        })?;
+    Some((ip, token))
+}
+
+// A protected brand named as a whole token in the path or query of a URL on an unrelated
+// domain, e.g. `https://totally-not-paypal.xyz/paypal/signin`. Returns the brand and the actual domain.
+fn brand_context_mismatch(url: &str) -> Option<(String, String)> {
+    let parsed_url = Url::parse(&normalize_url(url)).ok()?;
+    // IP-literal hosts are covered by `ip_host_with_brand_path`.
+    if !matches!(parsed_url.host()?, Host::Domain(_)) {
+        return None;
+    }
+    let domain = registrable_domain(parsed_url.as_str())?;
+    let context = format!("{} {}", parsed_url.path(), parsed_url.query().unwrap_or("")).to_lowercase();
+    let tokens: HashSet<&str> = context.split(|c: char| !c.is_alphanumeric()).filter(|t| !t.is_empty()).collect();
+    let brand = config().protected_brands.iter().find(|brand| {
+        tokens.contains(brand.to_lowercase().as_str()) && !config().brand_domains(brand).contains(&domain)
+    })?;
+    Some((brand.clone(), domain))
+}
+
+// A host label that fakes a protocol or security cue, e.g. `http-secure-login.evil.com` or
+// `https.paypal.evil.com`. Tokens match whole dash-separated runs of a label; a token ending
+// in `-` must start the label. Returns the token and the label it was found in.
+fn protocol_token_in_host(url: &str) -> Option<(String, String)> {
+    let parsed_url = Url::parse(&normalize_url(url)).ok()?;
+    let host = parsed_url.domain()?.to_lowercase();
+    host.split('.').find_map(|label| {
+        let dashed = format!("-{}-", label);
+        let token = config().protocol_host_tokens.iter().find(|token| {
+            let token = token.to_lowercase();
+            if token.ends_with('-') {
+                label.len() > token.len() && label.starts_with(&token)
+            } else {
+                dashed.contains(&format!("-{}-", token))
+            }
+        })?;
+        Some((token.clone(), label.to_string()))
+    })
+}
+
+// A registrable label (or dash-separated part of it) that spells a protected brand with digit or
+// symbol substitutions from `leetspeak_substitutions`, e.g. `g00gl3.com` for google. Compared position
+// by position, so several substitutions don't add up to an edit distance. Returns the label as
+// written, its de-leeted form and the brand.
+fn leetspeak_brand(url: &str) -> Option<(String, String, String)> {
+    let domain = registrable_domain(url)?;
+    let label = domain.split('.').next()?;
+    let substitutions = &config().leetspeak_substitutions;
+    let reads_as = |c: char, target: char| c == target || substitutions.get(&c.to_string()).is_some_and(|s| s.contains(target));
+    label.split('-').find_map(|part| {
+        let brand = config().protected_brands.iter().find(|brand| {
+            let brand = brand.to_lowercase();
+            part.chars().count() == brand.chars().count()
+                && part != brand
+                && part.chars().zip(brand.chars()).all(|(c, b)| reads_as(c, b))
+                && !config().brand_domains(&brand).contains(&domain)
+        })?;
+        Some((part.to_string(), brand.to_lowercase(), brand.clone()))
+    })
+}
+
+// A host typed with percent-encoding, e.g. `%65%76%69%6c.com`. Returns the raw and decoded host;
+// the URL parser decodes it, so every other check already sees the decoded form.
+fn encoded_host(url: &str) -> Option<(String, String)> {
+    let host = raw_host(url)?;
+    let host = host.rsplit_once(':').filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit())).map_or(host, |(host, _)| host);
+    let bytes = host.as_bytes();
+    let encoded = bytes.windows(3).any(|w| w[0] == b'%' && w[1].is_ascii_hexdigit() && w[2].is_ascii_hexdigit());
+    let decoded = percent_encoding::percent_decode_str(host).decode_utf8_lossy().to_lowercase();
+    encoded.then(|| (host.to_string(), decoded))
+}
+
+// A registrable label whose letter pairs are rare in natural language, as DGA-generated names
+// are, e.g. `xkqzpwj.com`. Protected brands and their domains are never flagged. Returns the label
+// and its score.
+fn improbable_ngrams(url: &str) -> Option<(String, f32)> {
+    let domain = registrable_domain(url)?;
+    let label = domain.split('.').next()?;
+    if label.starts_with("xn--") {
+        return None;
+    }
+    let brand = config().protected_brands.iter().any(|brand| {
+        brand.eq_ignore_ascii_case(label) || config().brand_domains(brand).contains(&domain)
+    });
+    let score = ngram::label_score(label)?;
+    (!brand && score < config().ngram_threshold).then(|| (label.to_string(), score))
+}
+
+// A host typed in a form that Unicode NFC normalization changes, e.g. `cafe\u{301}.com` (e plus a
+// combining accent) for `café.com`, or the Kelvin sign for K. The URL parser normalizes it, so deny
+// and brand checks already see the NFC form. Returns the raw and normalized host and the code
+// points that normalization replaced.
+fn non_nfc_host(url: &str) -> Option<(String, String, Vec<String>)> {
+    use unicode_normalization::UnicodeNormalization;
+    let host = percent_encoding::percent_decode_str(raw_host(url)?).decode_utf8_lossy().into_owned();
+    let normalized: String = host.nfc().collect();
+    if normalized == host {
+        return None;
+    }
+    let mut replaced: Vec<String> = host
+        .chars()
+        .filter(|c| !c.is_ascii() && !normalized.contains(*c))
+        .map(|c| format!("U+{:04X}", c as u32))
+        .collect();
+    replaced.dedup();
+    Some((host, normalized, replaced))
+}
+
+// Bidi control marks, which reorder how the rest of the host is displayed.
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{200E}' | '\u{200F}' | '\u{061C}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+// Letters of right-to-left scripts (Hebrew, Arabic, Syriac, Thaana, N'Ko, ...) and their presentation forms.
+fn is_rtl_char(c: char) -> bool {
+    matches!(c, '\u{0590}'..='\u{08FF}' | '\u{FB1D}'..='\u{FDFF}' | '\u{FE70}'..='\u{FEFF}' | '\u{10800}'..='\u{10FFF}' | '\u{1E800}'..='\u{1EFFF}')
+}
+
+// Unicode form of the input host and the RTL or bidi control characters in it, as `U+202E`.
+// Read from the raw input, since the URL parser rejects some of these characters outright.
+fn rtl_in_host(url: &str) -> Option<(String, Vec<String>)> {
+    let host = raw_host(url)?;
+    let host = host.rsplit_once(':').filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit())).map_or(host, |(host, _)| host);
+    let host = percent_encoding::percent_decode_str(host).decode_utf8_lossy();
+    // `xn--` labels are decoded; IDNA mapping replaces disallowed bidi controls, so a label
+    // it cannot decode cleanly is kept as typed.
+    let decoded: Vec<String> = host
+        .split('.')
+        .map(|label| {
+            let (unicode, _) = idna::domain_to_unicode(label);
+            if unicode.contains('\u{FFFD}') { label.to_string() } else { unicode }
+        })
+        .collect();
+    let decoded = decoded.join(".");
+    let mut found: Vec<String> = Vec::new();
+    for c in decoded.chars().filter(|c| is_bidi_control(*c) || is_rtl_char(*c)) {
+        let code = format!("U+{:04X}", c as u32);
+        if !found.contains(&code) {
+            found.push(code);
+        }
+    }
+    (!found.is_empty()).then_some((decoded, found))
+}
+
+// ASCII and Unicode forms of an internationalized host, e.g. `xn--80ak6aa92e.com` and `аррӏе.com`,
+// however it was typed. Deny lists compare the ASCII form, which the URL parser produces from either.
+fn idn_host(url: &str) -> Option<(String, String)> {
+    let parsed_url = Url::parse(&normalize_url(url)).ok()?;
+    let host = parsed_url.domain()?.to_lowercase();
+    if !host.split('.').any(|label| label.starts_with("xn--")) {
+        return None;
+    }
+    let (unicode, result) = idna::domain_to_unicode(&host);
+    result.ok()?;
+    Some((host, unicode))
+}
+
+// The `shared_hosting_domains` entry a tenant host sits under, e.g. `duckdns.org` for
+// `paypal-login.duckdns.org`. The provider's own site does not count.
+fn shared_hosting(url: &str) -> Option<&'static str> {
+    let host = Url::parse(&normalize_url(url)).ok()?.host_str()?.to_lowercase();
+    config()
+        .shared_hosting_domains
+        .iter()
+        .find(|entry| host.ends_with(&format!(".{}", entry.trim().to_lowercase())))
+        .map(String::as_str)
+}
+
+// UTS #39 skeleton, lowercased, with `i` folded into `l`: the table maps Cyrillic `ӏ` to `i`, yet
+// it renders as `l`, as in `аррӏе.com` for `apple.com`.
+fn lookalike_skeleton(text: &str) -> String {
+    confusable_detection::skeleton(text).collect::<String>().to_lowercase().replace('i', "l")
+}
+
+// A punycode host whose decoded form is a confusable of a protected brand, e.g. `xn--pypal-4ve.com`
+// (Cyrillic `а`). Returns the decoded host and the brand. Plain IDN hosts that resemble no brand are ignored.
+fn punycode_brand_spoof(url: &str) -> Option<(String, String)> {
+    let parsed_url = Url::parse(&normalize_url(url)).ok()?;
+    let host = parsed_url.domain()?.to_lowercase();
+    if !host.split('.').any(|label| label.starts_with("xn--")) {
+        return None;
+    }
+    let (decoded, result) = idna::domain_to_unicode(&host);
+    result.ok()?;
+    let skeleton = lookalike_skeleton(&decoded);
+    let brand = config().protected_brands.iter().find(|brand| {
+        let brand_skeleton = lookalike_skeleton(&brand.to_lowercase());
+        skeleton.split('.').any(|label| label.contains(&brand_skeleton))
+    })?;
+    Some((decoded, brand.clone()))
+}
+
+// Decoded bytes inspected per embedded data URI; payloads are truncated beyond this.
+const MAX_DATA_URI_DECODED: usize = 64 * 1024;
+
+// A `data:`, `javascript:` or `blob:` URI hidden in a query value or the fragment,
+// e.g. `https://example.com/#data:text/html;base64,...`. Returns the scheme and a description.
+fn embedded_dangerous_uri(url: &str) -> Option<(&'static str, String)> {
+    let parsed_url = Url::parse(&normalize_url(url)).ok()?;
+    let fragment = parsed_url.fragment().map(|f| percent_encoding::percent_decode_str(f).decode_utf8_lossy().into_owned());
+    let values = parsed_url.query_pairs().map(|(_, value)| value.into_owned()).chain(fragment);
+    for value in values {
+        let lower = value.to_ascii_lowercase();
+        for (prefix, scheme) in [("data:", "data"), ("javascript:", "javascript"), ("blob:", "blob")] {
+            // The scheme must start a token, so `metadata:` is not `data:`.
+            let Some(pos) = lower
+                .match_indices(prefix)
+                .map(|(pos, _)| pos)
+                .find(|&pos| !lower[..pos].chars().next_back().is_some_and(|c| c.is_ascii_alphanumeric()))
+            else {
+                continue;
+            };
+            let detail = match scheme {
+                "data" => describe_data_uri(&value[pos + prefix.len()..]),
+                _ => format!("Embedded {} URI", prefix),
+            };
+            return Some((scheme, detail));
+        }
+    }
+    None
+}
+
+// Classifies a data URI body (`<mediatype>[;base64],<payload>`), decoding at most `MAX_DATA_URI_DECODED` bytes.
+fn describe_data_uri(body: &str) -> String {
+    use base64::Engine;
+    let Some((header, payload)) = body.split_once(',') else {
+        return "Embedded data: URI".to_string();
+    };
+    let media_type = header.split(';').next().filter(|m| !m.is_empty()).unwrap_or("text/plain").to_ascii_lowercase();
+    let content = if header.to_ascii_lowercase().ends_with(";base64") {
+        // 4 base64 characters decode to 3 bytes; a truncated tail is dropped rather than failing.
+        let limit = (MAX_DATA_URI_DECODED / 3 * 4).min(payload.len());
+        let encoded: String = payload[..payload.floor_char_boundary(limit)].chars().filter(|c| !c.is_whitespace()).collect();
+        let encoded = &encoded[..encoded.len() / 4 * 4];
+        match base64::engine::general_purpose::STANDARD.decode(encoded) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).to_lowercase(),
+            Err(_) => return format!("Embedded data: URI ({}, undecodable base64)", media_type),
+        }
+    } else {
+        let limit = payload.floor_char_boundary(MAX_DATA_URI_DECODED);
+        percent_encoding::percent_decode_str(&payload[..limit]).decode_utf8_lossy().to_lowercase()
+    };
+    let traits: Vec<&str> = [("<form", "form"), ("<script", "script"), ("password", "password field")]
+        .iter()
+        .filter(|(needle, _)| content.contains(needle))
+        .map(|(_, name)| *name)
+        .collect();
+    if traits.is_empty() {
+        format!("Embedded data: URI ({})", media_type)
+    } else {
+        format!("Embedded data: URI ({} with {})", media_type, traits.join(", "))
+    }
+}
+
+const REDIRECT_PARAMS: &[&str] = &[
+    "url", "u", "q", "redirect", "redirect_url", "redirect_uri", "next", "target", "dest", "destination",
+    "continue", "return", "returnurl", "return_to", "goto", "link",
+];
+
+fn is_redirect_param(name: &str) -> bool {
+    REDIRECT_PARAMS.iter().any(|p| name.eq_ignore_ascii_case(p))
+}
+
+// Redirect-style query parameters carrying an absolute URL on another site,
+// e.g. `https://example.com/out?url=https://evil.com`, as (parameter, embedded URL) pairs.
+// Every distinct value of a repeated parameter is returned, since servers differ in which one they use.
+fn redirect_param_targets(url: &str) -> Vec<(String, String)> {
+    let Ok(parsed_url) = Url::parse(&normalize_url(url)) else {
+        return Vec::new();
+    };
+    let own_domain = registrable_domain(parsed_url.as_str());
+    parsed_url.query_pairs().filter_map(|(name, value)| {
+        if !is_redirect_param(&name) {
+            return None;
+        }
+        let value = value.trim();
+        let lower = value.to_ascii_lowercase();
+        if !(lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("//")) {
+            return None;
+        }
+        let target = Url::parse(&normalize_url(value.trim_start_matches('/'))).ok()?;
+        target.host_str()?;
+        (registrable_domain(target.as_str()) != own_domain).then(|| (name.into_owned(), target.to_string()))
+    }).fold(Vec::new(), |mut targets, pair| {
+        if !targets.contains(&pair) {
+            targets.push(pair);
+        }
+        targets
+    })
+}
+
+// Levels of `proxy/https://next/https://...` nesting followed by `path_embedded_urls`.
+const MAX_PATH_EMBED_DEPTH: usize = 3;
+
+// Absolute URLs embedded in the path on another site, plain or percent-encoded, e.g.
+// `https://proxy.com/https://evil.com/login`. The outer query is passed on to the embedded URL, as
+// proxies do, and each target's own path is scanned in turn up to `MAX_PATH_EMBED_DEPTH` levels.
+fn path_embedded_urls(url: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    let mut current = normalize_url(url);
+    for _ in 0..MAX_PATH_EMBED_DEPTH {
+        let Ok(parsed_url) = Url::parse(&current) else {
+            break;
+        };
+        let path = percent_encoding::percent_decode_str(parsed_url.path()).decode_utf8_lossy().into_owned();
+        let lower = path.to_ascii_lowercase();
+        let Some(start) = ["http://", "https://"].iter().filter_map(|scheme| lower.find(scheme)).min() else {
+            break;
+        };
+        let mut embedded = path[start..].to_string();
+        if let Some(query) = parsed_url.query() {
+            embedded = format!("{}?{}", embedded, query);
+        }
+        let Some(target) = Url::parse(&embedded).ok().filter(|target| target.host_str().is_some()) else {
+            break;
+        };
+        if registrable_domain(target.as_str()) != registrable_domain(parsed_url.as_str()) {
+            targets.push(target.to_string());
+        }
+        current = target.to_string();
+    }
+    targets
+}
+
+// Query parameters given more than once with different values (`?url=a&url=b`), in order of
+// first appearance. Which value a server acts on varies, so checks reading one can be bypassed.
+fn polluted_params(url: &str) -> Vec<String> {
+    let Ok(parsed_url) = Url::parse(&normalize_url(url)) else {
+        return Vec::new();
+    };
+    let scope = config().parameter_pollution;
+    let mut values: Vec<(String, Vec<String>)> = Vec::new();
+    for (name, value) in parsed_url.query_pairs() {
+        if scope == config::PollutionScope::Redirect && !is_redirect_param(&name) {
+            continue;
+        }
+        match values.iter_mut().find(|(seen, _)| *seen == name) {
+            Some((_, seen)) => seen.push(value.into_owned()),
+            None => values.push((name.into_owned(), vec![value.into_owned()])),
+        }
+    }
+    values
+        .into_iter()
+        .filter(|(_, values)| values.iter().any(|v| *v != values[0]))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+// Hosts whose redirect parameters are expected, e.g. search result and mail link wrappers.
+fn is_redirect_allowed(url: &str) -> bool {
+    config().redirect_allow_hosts.iter().any(|host| matches_domain_entry(url, host))
+}
+
+// Hosts of domain-looking tokens in free text, e.g. the visible text of a link.
+fn domains_in_text(text: &str) -> Vec<String> {
+    text.split(|c: char| c.is_whitespace() || "<>()[]{}\"',;".contains(c))
+        .filter_map(|token| {
+            let token = token.trim_end_matches(['.', ':', '!', '?']);
+            let host = Url::parse(&normalize_url(token)).ok()?.host_str()?.to_lowercase();
+            let known_suffix = psl::suffix(host.as_bytes()).is_some_and(|s| s.is_known());
+            (host.contains('.') && known_suffix).then_some(host)
+        })
+        .collect()
+}
+
+// First domain shown in `display_text` whose registrable domain differs from the target's.
+fn find_display_mismatch(display_text: &str, url: &str) -> Option<String> {
+    let target = registrable_domain(url)?;
+    domains_in_text(display_text)
+        .into_iter()
+        .find(|shown| registrable_domain(shown).is_some_and(|d| d != target))
+}
+
+async fn resolve_host(url: &str) -> Option<IpAddr> {
+    let parsed_url = Url::parse(&normalize_url(url)).ok()?;
+    match parsed_url.host()? {
+        Host::Ipv4(ip) => Some(IpAddr::V4(ip)),
+        Host::Ipv6(ip) => Some(IpAddr::V6(ip)),
+        Host::Domain(domain) => {
+            let port = parsed_url.port_or_known_default().unwrap_or(443);
+            let lookup = outbound::track("dns", domain, tokio::net::lookup_host((domain, port)));
+            match tokio::time::timeout(Duration::from_secs(config().connect_timeout_secs), lookup).await {
+                Ok(Ok(mut addrs)) => addrs.next().map(|addr| addr.ip()),
+                Ok(Err(e)) => {
+                    log::debug!("DNS resolution failed for {}: {}", domain, e);
+                    None
+                }
+                Err(_) => {
+                    log::debug!("DNS resolution timed out for {}", domain);
+                    None
+                }
+            }
+        }
+    }
+}
+
+// ASCII domain of the first recipient of a `mailto:` link, e.g. `paypa1.com` for
+// `mailto:admin@paypa1.com?subject=Verify`. IDN domains come back as punycode.
+fn mailto_domain(parsed_url: &Url) -> Option<String> {
+    if parsed_url.scheme() != "mailto" {
+        return None;
+    }
+    let recipient = parsed_url.path().split(',').next()?;
+    let recipient = percent_encoding::percent_decode_str(recipient).decode_utf8().ok()?;
+    let (_, domain) = recipient.trim().rsplit_once('@')?;
+    match Host::parse(domain).ok()? {
+        Host::Domain(domain) if domain.contains('.') => Some(domain),
+        _ => None,
+    }
+}
+
+// `scheme://host[:port]/` of a URL: path, query, fragment and credentials removed.
+fn domain_scope_url(url: &str) -> String {
+    let normalized_url = normalize_url(url);
+    let Ok(mut parsed_url) = Url::parse(&normalized_url) else {
+        return normalized_url;
+    };
+    if parsed_url.cannot_be_a_base() {
+        return normalized_url;
+    }
+    parsed_url.set_path("/");
+    parsed_url.set_query(None);
+    parsed_url.set_fragment(None);
+    let _ = parsed_url.set_username("");
+    let _ = parsed_url.set_password(None);
+    parsed_url.to_string()
+}
+
+// Registrable domain of a URL whose network checks can be skipped: listed in `fast_path_domains`
+// or ranked within the top `fast_path_top_n` of the top sites list.
+fn fast_path_domain(url: &str, state: &AppState) -> Option<String> {
+    let domain = registrable_domain(url)?;
+    let listed = config().fast_path_domains.iter().any(|d| d.eq_ignore_ascii_case(&domain));
+    let top = state.top_sites.as_ref().and_then(|top_sites| top_sites.rank(&domain)).is_some_and(|rank| rank <= config().fast_path_top_n);
+    (listed || top).then_some(domain)
+}
+
+async fn analyze_url(url: &str, state: &AppState, options: &AnalysisOptions) -> Result<UrlAnalysis, Box<dyn std::error::Error + Send + Sync>> {
+    // In domain scope only host-level signals can fire, since the path and query are gone.
+    let scoped_url = (options.scope == Scope::Domain).then(|| domain_scope_url(url));
+    // A mailto: link is analyzed as its recipient's domain; only domain-level checks apply.
+    let mail_domain = Url::parse(url).ok().and_then(|parsed_url| mailto_domain(&parsed_url));
+    let scoped_url = mail_domain.as_ref().map(|domain| format!("https://{}/", domain)).or(scoped_url);
+    let input_url = url;
+    let url = scoped_url.as_deref().unwrap_or(url);
+    let normalized_url = normalize_url(url);
+    let mut analysis = HashMap::new();
+    let phishing_list = &state.deny_list();
+    let mut timings = Timings::new(options.timings);
+    let fast_path = fast_path_domain(&normalized_url, state);
+    let skip_network = options.local_only || fast_path.is_some() || config().offline;
+    let mut network = NetworkChecks::default();
+    if !config().feeds.is_empty() && !skip_network {
+        network.record(phishing_list.is_ok());
+    }
+    // Scoped analyses start at a bare host, whose redirects say nothing about the link itself.
+    let follow_redirects = config().redirect_check
+        && !skip_network
+        && scoped_url.is_none()
+        && Url::parse(&normalized_url).is_ok_and(|parsed_url| matches!(parsed_url.scheme(), "http" | "https"))
+        && network.spend();
+    // The first request is spent above; each further hop takes one more from the budget.
+    let redirect_budget = follow_redirects.then(|| network.remaining() + 1);
+    let mut check_result = checking_url_enhanced(&normalized_url, phishing_list, redirect_budget, &mut timings).await;
+    // Not being able to follow the link is not a failed check, as for `reachability_probe`.
+    if follow_redirects {
+        network.record(true);
+    }
+    match check_result.redirect {
+        Some(peek::RedirectOutcome::Followed { hops, .. }) => network.requests += hops,
+        Some(peek::RedirectOutcome::OutOfBudget { hops }) => {
+            network.requests += hops - 1;
+            network.skipped += 1;
+        }
+        _ => {}
+    }
+    // Domain scope has no path to hold a short code, so the shortener host itself counts.
+    if options.scope == Scope::Domain && mail_domain.is_none() {
+        check_result.is_shortened = check_result.shortener.is_some();
+    }
+    // Allow entries win over any deny match.
+    let allow_tier = check_deny_entries(&normalized_url, state.allow_list.read().unwrap().iter());
+    if let Some(tier) = allow_tier.filter(|_| check_result.is_phishing) {
+        analysis.insert(
+            "allowlisted".to_string(),
+            format!("Local allow entry ({} match) overrides the phishing database", tier.as_str()),
+        );
+        check_result.is_phishing = false;
+        check_result.phishing_tier = None;
+    }
+    if let Some(domain) = &mail_domain {
+        analysis.insert("mailto_domain".to_string(), domain.clone());
+    }
+    if let Some(domain) = &fast_path {
+        analysis.insert("fast_path".to_string(), format!("{} is a high-reputation domain; network checks skipped", domain));
+    }
+    if config().offline && !config().network_checks().is_empty() {
+        analysis.insert("offline".to_string(), format!("Offline mode; unavailable checks: {}", config().network_checks().join(", ")));
+    }
+    match &check_result.redirect {
+        Some(peek::RedirectOutcome::Followed { final_url, hops, cross_domain }) if *hops > 0 => {
+            let scope = if *cross_domain { "another domain" } else { "the same domain" };
+            analysis.insert("redirect".to_string(), format!("URL redirects to {} ({}, redirects followed: {})", final_url, scope, hops));
+        }
+        Some(peek::RedirectOutcome::Followed { .. }) => {
+            analysis.insert("redirect".to_string(), "No redirection".to_string());
+        }
+        Some(peek::RedirectOutcome::Blocked(ip)) => {
+            analysis.insert("redirect_blocked".to_string(), format!("Redirect resolves to non-public address {}", ip));
+        }
+        Some(peek::RedirectOutcome::OutOfBudget { hops }) => {
+            analysis.insert("redirect".to_string(), format!("Redirect chain not followed past {} redirects: outbound request budget spent", hops));
+        }
+        None => {}
+    }
+
+    if check_result.is_phishing {
+        analysis.insert("phishing_detected".to_string(), "URL found in phishing database".to_string());
+        if let Some(tier) = check_result.phishing_tier {
+            analysis.insert("phishing_match_tier".to_string(), tier.as_str().to_string());
+        }
+        if let Some(expires_at) = local_deny_expiry(&normalized_url) {
+            analysis.insert(
+                "phishing_entry_expires".to_string(),
+                format!("Matched a temporary local deny entry that expires at {}", expires_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+            );
+        }
+    } else if config().fuzzy_deny_match && allow_tier.is_none() {
+        let start = timings.start();
+        if let Some(m) = check_fuzzy_phishing_db(&normalized_url, phishing_list) {
+            analysis.insert(
+                "fuzzy_phishing_match".to_string(),
+                format!("Close to phishing database entry {} (edit distance {})", m.entry, m.distance),
+            );
+        }
+        timings.record("fuzzy_db", start);
+    }
+
+    let start = timings.start();
+    let mut url_features = extract_url_features(url);
+    let raw_features = features::RawFeatures::new(url, &url_features);
+    let ip_brand_path = ip_host_brand_path(&normalized_url);
+    let brand_spoof = punycode_brand_spoof(&normalized_url);
+    let idn = idn_host(&normalized_url);
+    let rtl_host = rtl_in_host(url);
+    let encoded = encoded_host(url);
+    let non_nfc = non_nfc_host(url);
+    let embedded_uri = embedded_dangerous_uri(&normalized_url);
+    let brand_mismatch = brand_context_mismatch(&normalized_url);
+    let protocol_token = protocol_token_in_host(&normalized_url);
+    let leetspeak = leetspeak_brand(&normalized_url);
+    let numeric_host = config().numeric_host_check.then(|| numeric_or_hex_host(url)).flatten();
+    let ngrams = config().ngram_check.then(|| improbable_ngrams(&normalized_url)).flatten();
+    let hosting = shared_hosting(&normalized_url);
+    timings.record("feature_extraction", start);
+    if let Some((ip, token)) = &ip_brand_path {
+        analysis.insert(
+            "ip_host_with_brand_path".to_string(),
+            format!("IP host {} with path masquerading as {}", ip, token),
+        );
+    }
+    url_features.insert("ip_host_with_brand_path".to_string(), ip_brand_path.is_some());
+    if let Some(host) = &numeric_host {
+        analysis.insert("numeric_or_hex_host".to_string(), format!("Host {} is written as numbers or hex", host));
+    }
+    url_features.insert("numeric_or_hex_host".to_string(), numeric_host.is_some());
+    if let Some((label, normalized, brand)) = &leetspeak {
+        analysis.insert(
+            "leetspeak_brand".to_string(),
+            format!("Label {} normalizes to {}, spelling the brand {}", label, normalized, brand),
+        );
+    }
+    url_features.insert("leetspeak_brand".to_string(), leetspeak.is_some());
+    if let Some((ascii, unicode)) = &idn {
+        analysis.insert("idn_host".to_string(), format!("{} (ASCII form {})", unicode, ascii));
+    }
+    if let Some((decoded, brand)) = &brand_spoof {
+        analysis.insert(
+            "punycode_brand_spoof".to_string(),
+            format!("Punycode host decodes to {}, a lookalike of {}", decoded, brand),
+        );
+    }
+    url_features.insert("punycode_brand_spoof".to_string(), brand_spoof.is_some());
+    if let Some((decoded, chars)) = &rtl_host {
+        analysis.insert(
+            "rtl_in_host".to_string(),
+            format!("Host {} contains right-to-left or bidi control characters: {}", decoded, chars.join(", ")),
+        );
+    }
+    url_features.insert("rtl_in_host".to_string(), rtl_host.is_some());
+    if let Some((raw, decoded)) = &encoded {
+        analysis.insert("encoded_host".to_string(), format!("Host {} is percent-encoded; decodes to {}", raw, decoded));
+    }
+    url_features.insert("encoded_host".to_string(), encoded.is_some());
+    if let Some((raw, normalized, replaced)) = &non_nfc {
+        analysis.insert(
+            "non_nfc_host".to_string(),
+            format!("Host {} is not NFC-normalized ({}); normalizes to {}", raw, replaced.join(", "), normalized),
+        );
+    }
+    url_features.insert("non_nfc_host".to_string(), non_nfc.is_some());
+    if let Some((label, score)) = &ngrams {
+        analysis.insert(
+            "improbable_ngrams".to_string(),
+            format!("Label {} has improbable letter pairs (score {:.2}, threshold {:.2})", label, score, config().ngram_threshold),
+        );
+    }
+    url_features.insert("improbable_ngrams".to_string(), ngrams.is_some());
+    if let Some(provider) = hosting {
+        analysis.insert(
+            "shared_hosting".to_string(),
+            format!("Tenant {} of free hosting or dynamic-DNS provider {}", registrable_domain(&normalized_url).unwrap_or_default(), provider),
+        );
+    }
+    url_features.insert("shared_hosting".to_string(), hosting.is_some());
+    if let Some((brand, domain)) = &brand_mismatch {
+        analysis.insert(
+            "brand_context_mismatch".to_string(),
+            format!("URL references {} but is hosted on {}", brand, domain),
+        );
+    }
+    url_features.insert("brand_context_mismatch".to_string(), brand_mismatch.is_some());
+    if let Some((token, label)) = &protocol_token {
+        analysis.insert(
+            "protocol_token_in_host".to_string(),
+            format!("Host label {} contains the token {}", label, token),
+        );
+    }
+    url_features.insert("protocol_token_in_host".to_string(), protocol_token.is_some());
+    let tld_weight = match Url::parse(&normalized_url).ok().as_ref().and_then(suspicious_tld) {
+        Some((tld, weight)) => {
+            analysis.insert("has_suspicious_tld".to_string(), format!("TLD .{} (weight {:.2})", tld, weight));
+            Some(weight)
+        }
+        None => None,
+    };
+    if *url_features.get("deep_path").unwrap_or(&false) {
+        let depth = Url::parse(&normalized_url).map_or(0, |u| path_depth(&u));
+        analysis.insert("deep_path".to_string(), format!("Path is {} segments deep", depth));
+        let brand_in_path = brand_mismatch.is_some() || ip_brand_path.is_some();
+        url_features.insert("deep_path_with_brand".to_string(), brand_in_path);
+        if brand_in_path {
+            analysis.insert("deep_path_with_brand".to_string(), "Deep path padding combined with a brand in the path".to_string());
+        }
+    }
+    if *url_features.get("excessive_query").unwrap_or(&false) {
+        let (param_count, query_length) = Url::parse(&normalized_url).map_or((0, 0), |u| query_size(&u));
+        analysis.insert("query_param_count".to_string(), param_count.to_string());
+        analysis.insert("query_length".to_string(), query_length.to_string());
+        analysis.insert(
+            "excessive_query".to_string(),
+            format!("Query has {} parameters and {} characters", param_count, query_length),
+        );
+    }
+    if *url_features.get("dangerous_download").unwrap_or(&false)
+        && let Some((extension, disguise)) = Url::parse(&normalized_url).ok().as_ref().and_then(dangerous_download)
+    {
+        let detail = match disguise {
+            Some(disguise) => format!("Path ends in .{}.{}, a .{} file disguised as .{}", disguise, extension, extension, disguise),
+            None => format!("Path ends in a .{} file", extension),
+        };
+        analysis.insert("dangerous_download".to_string(), detail);
+    }
+    if let Some(top_sites) = &state.top_sites
+        && let Some(domain) = registrable_domain(&normalized_url)
+    {
+        let start = timings.start();
+        match top_sites.rank(&domain) {
+            Some(rank) => {
+                analysis.insert("in_top_sites".to_string(), format!("{} is ranked #{} in the top sites list", domain, rank));
+                url_features.insert("in_top_sites".to_string(), true);
+            }
+            None => {
+                if let Some(m) = top_sites.lookalike(&domain, config().top_sites_lookalike_limit) {
+                    analysis.insert(
+                        "top_site_lookalike".to_string(),
+                        format!("{} is not a top site but is close to {} (edit distance {})", domain, m.entry, m.distance),
+                    );
+                    url_features.insert("top_site_lookalike".to_string(), true);
+                }
+            }
+        }
+        timings.record("top_sites", start);
+    }
+    if let Some((scheme, detail)) = &embedded_uri {
+        analysis.insert("embedded_uri_scheme".to_string(), scheme.to_string());
+        analysis.insert("embedded_dangerous_uri".to_string(), detail.clone());
+    }
+    url_features.insert("embedded_dangerous_uri".to_string(), embedded_uri.is_some());
+    let redirect_targets = redirect_param_targets(&normalized_url);
+    if !redirect_targets.is_empty() {
+        let redirects: Vec<String> = redirect_targets.iter().map(|(param, target)| format!("{} redirects to {}", param, target)).collect();
+        if is_redirect_allowed(&normalized_url) {
+            let mut params: Vec<&str> = redirect_targets.iter().map(|(param, _)| param.as_str()).collect();
+            params.dedup();
+            analysis.insert(
+                "open_redirect_suppressed".to_string(),
+                format!("Redirect parameter {} is expected for this host", params.join(", ")),
+            );
+        } else {
+            analysis.insert("open_redirect_param".to_string(), format!("Query parameter {}", redirects.join("; ")));
+            url_features.insert("open_redirect_param".to_string(), true);
+        }
+        // The embedded targets are checked either way; an allowed wrapper can still lead somewhere bad.
+        let targets: Vec<&str> = redirect_targets.iter().map(|(_, target)| target.as_str()).collect();
+        analysis.insert("redirect_target".to_string(), targets.join(", "));
+    }
+    // Allowed wrappers (e.g. web.archive.org) embed targets in the path by design.
+    let path_targets = path_embedded_urls(&normalized_url);
+    let path_embeds = !path_targets.is_empty() && !is_redirect_allowed(&normalized_url);
+    if path_embeds {
+        analysis.insert("embedded_url_in_path".to_string(), format!("Path embeds {}", path_targets.join(", then ")));
+    }
+    url_features.insert("embedded_url_in_path".to_string(), path_embeds);
+    if !redirect_targets.is_empty() || !path_targets.is_empty() {
+        let start = timings.start();
+        let listed = |target: &String| check_local_phishing_db(target).or_else(|| (**phishing_list).as_ref().ok()?.check(target)).is_some();
+        let flagged_path: Vec<&String> = path_targets.iter().filter(|target| listed(target)).collect();
+        let flagged = redirect_targets.iter().map(|(_, target)| target).find(|target| listed(target)).or(flagged_path.first().copied());
+        timings.record("redirect_target", start);
+        if !path_targets.is_empty() {
+            let verdicts: Vec<String> = path_targets
+                .iter()
+                .map(|target| format!("{} ({})", target, if flagged_path.contains(&target) { "in phishing database" } else { "not listed" }))
+                .collect();
+            analysis.insert("path_target".to_string(), verdicts.join(", "));
+        }
+        if let Some(target) = flagged {
+            analysis.insert(
+                "redirect_target_phishing".to_string(),
+                format!("Redirect target {} found in phishing database", target),
+            );
+        }
+        url_features.insert("redirect_target_phishing".to_string(), flagged.is_some());
+    }
+    if config().parameter_pollution != config::PollutionScope::Off {
+        let polluted = polluted_params(&normalized_url);
+        if !polluted.is_empty() {
+            analysis.insert(
+                "parameter_pollution".to_string(),
+                format!("Query parameter {} repeated with different values", polluted.join(", ")),
+            );
+        }
+        url_features.insert("parameter_pollution".to_string(), !polluted.is_empty());
+    }
+    if let Some(geoip) = state.geoip.as_ref().filter(|_| !skip_network && network.spend()) {
+        let start = timings.start();
+        let resolved = resolve_host(&normalized_url).await;
+        timings.record("dns", start);
+        network.record(resolved.is_some());
+        let start = timings.start();
+        if let Some(ip) = resolved
+            && let Some(country) = geoip.country(ip)
+        {
+            let high_risk = config().high_risk_countries.iter().any(|c| c.eq_ignore_ascii_case(&country));
+            analysis.insert("geo_country".to_string(), format!("Host {} is located in {}", ip, country));
+            url_features.insert("high_risk_geo".to_string(), high_risk);
+        }
+        timings.record("geoip", start);
+    }
+    if config().tls_probe
+        && !skip_network
+        && let Ok(parsed_url) = Url::parse(&normalized_url)
+        && parsed_url.scheme() == "https"
+        && let Some(host) = parsed_url.host_str()
+        && network.spend()
+    {
+        let timeout = Duration::from_secs(config().connect_timeout_secs);
+        let start = timings.start();
+        let host = host.trim_matches(['[', ']']);
+        let probe = outbound::track("tls_probe", host, tls::check_tls(&parsed_url, timeout)).await;
+        timings.record("tls_probe", start);
+        network.record(probe.is_ok());
+        match probe {
+            Ok(tls::TlsCheck::Blocked(ip)) => {
+                analysis.insert("tls_probe_blocked".to_string(), format!("Host resolves to non-public address {}", ip));
+            }
+            Ok(tls::TlsCheck::Probed(probe)) => {
+                let detail = match probe.version {
+                    Some(version) => format!("Negotiated {}", version),
+                    None => "Server only supports TLS 1.1 or older".to_string(),
+                };
+                analysis.insert("tls_version".to_string(), detail);
+                url_features.insert("weak_tls".to_string(), probe.weak);
+            }
+            Err(e) => log::debug!("TLS probe failed for {}: {}", host, e),
+        }
+    }
+    if let Some(ct) = state.ct.as_ref().filter(|_| !skip_network)
+        && let Some(domain) = registrable_domain(&normalized_url)
+        && network.spend()
+    {
+        let start = timings.start();
+        let summary = ct.summary(&domain).await;
+        timings.record("ct_log", start);
+        network.record(summary.is_some());
+        if let Some(summary) = summary {
+            let newest = summary.newest_issuance.map(|t| t.format("%Y-%m-%d").to_string());
+            analysis.insert(
+                "ct_certificates".to_string(),
+                format!("{} distinct certificates logged, newest issued {}", summary.distinct_certs, newest.as_deref().unwrap_or("never")),
+            );
+            let recent_cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(config().ct_recent_days);
+            let very_recent = summary.newest_issuance.is_some_and(|t| t >= recent_cutoff);
+            if very_recent {
+                analysis.insert(
+                    "very_recent_cert_issuance".to_string(),
+                    format!("Certificate issued within the last {} days", config().ct_recent_days),
+                );
+            }
+            url_features.insert("very_recent_cert_issuance".to_string(), very_recent);
+        }
+    }
+    if let Some(lookup) = state.email_auth.as_ref().filter(|_| !skip_network)
+        && let Some(domain) = &mail_domain
+        && network.spend()
+    {
+        let start = timings.start();
+        let auth = lookup.lookup(domain).await;
+        timings.record("email_auth", start);
+        network.record(auth.is_some());
+        match &auth {
+            Some(mailauth::EmailAuth::NoDomain) => {
+                analysis.insert("email_auth".to_string(), format!("{} does not exist in DNS", domain));
+            }
+            Some(mailauth::EmailAuth::Policies { spf, dmarc }) => {
+                analysis.insert("spf_policy".to_string(), spf.clone().unwrap_or_else(|| "none".to_string()));
+                analysis.insert("dmarc_policy".to_string(), dmarc.clone().unwrap_or_else(|| "none".to_string()));
+            }
+            None => {}
+        }
+        let weakness = auth.as_ref().and_then(mailauth::EmailAuth::weakness);
+        if let Some(weakness) = weakness {
+            analysis.insert("weak_email_auth".to_string(), format!("{} can be spoofed as a sender: {}", domain, weakness));
+        }
+        url_features.insert("weak_email_auth".to_string(), weakness.is_some());
+    }
+    if let Some(favicon) = state.favicon.as_ref().filter(|_| !skip_network)
+        && let Ok(parsed_url) = Url::parse(&normalized_url)
+        && matches!(parsed_url.scheme(), "http" | "https")
+        && network.spend()
+    {
+        let start = timings.start();
+        let icon = favicon.lookup(&parsed_url).await;
+        timings.record("favicon", start);
+        network.record(icon.is_some());
+        match icon {
+            Some(favicon::Favicon::Hash(hash)) => {
+                analysis.insert("favicon_hash".to_string(), hash.to_string());
+                let known = config().known_phishing_favicons.contains(&hash);
+                if known {
+                    analysis.insert(
+                        "known_phishing_favicon".to_string(),
+                        format!("Favicon hash {} matches a known phishing kit", hash),
+                    );
+                }
+                url_features.insert("known_phishing_favicon".to_string(), known);
+            }
+            Some(favicon::Favicon::Blocked(ip)) => {
+                analysis.insert("favicon_blocked".to_string(), format!("Host resolves to non-public address {}", ip));
+            }
+            None => {}
+        }
+    }
+    if config().reachability_probe
+        && !skip_network
+        && let Ok(parsed_url) = Url::parse(&normalized_url)
+        && matches!(parsed_url.scheme(), "http" | "https")
+        && network.spend()
+    {
+        let start = timings.start();
+        let probe = peek::probe(&parsed_url, Duration::from_secs(config().reachability_timeout_secs)).await;
+        timings.record("reachability", start);
+        // Not reaching the target is the finding here, not a failed check.
+        network.record(true);
+        match probe {
+            Ok(peek::Probe::Reached(reached)) => {
+                analysis.insert("target_status".to_string(), reached.status.to_string());
+                if let Some(location) = &reached.location {
+                    analysis.insert("target_location".to_string(), location.clone());
+                }
+                if let Some(content_type) = &reached.content_type {
+                    analysis.insert("target_content_type".to_string(), content_type.clone());
+                }
+                let error_status = reached.status >= 400;
+                if error_status {
+                    analysis.insert("target_error_status".to_string(), format!("Target answered HTTP {}", reached.status));
+                }
+                url_features.insert("target_error_status".to_string(), error_status);
+            }
+            Ok(peek::Probe::Blocked(ip)) => {
+                analysis.insert("reachability_blocked".to_string(), format!("Host resolves to non-public address {}", ip));
+            }
+            Err(e) => {
+                analysis.insert("target_unreachable".to_string(), format!("Target could not be reached: {}", e));
+                url_features.insert("target_unreachable".to_string(), true);
+            }
+        }
+    }
+    let mut reputation = Vec::new();
+    if !skip_network
+        && !state.reputation.is_empty()
+        && let Ok(parsed_url) = Url::parse(&normalized_url)
+    {
+        let providers: Vec<_> = state.reputation.iter().filter(|_| network.spend()).collect();
+        let start = timings.start();
+        let scores = futures::future::join_all(providers.iter().map(|provider| provider.score(&parsed_url))).await;
+        timings.record("reputation", start);
+        for (provider, score) in providers.iter().zip(scores) {
+            network.record(score.is_some());
+            let Some(score) = score else {
+                continue;
+            };
+            let contribution = reputation::Contribution {
+                provider: provider.name().to_string(),
+                weight: provider.weight(),
+                score: score.score,
+            };
+            let detail = score.detail.map(|d| format!(" ({})", d)).unwrap_or_default();
+            analysis.insert(
+                format!("reputation_{}", contribution.provider),
+                format!("Score {:.2} x weight {:.2} = {:+.3}{}", contribution.score, contribution.weight, contribution.score * contribution.weight, detail),
+            );
+            reputation.push(contribution);
+        }
+    }
+    if let Some(display_text) = &options.display_text {
+        let mismatch = find_display_mismatch(display_text, &normalized_url);
+        if let Some(shown) = &mismatch {
+            let target = registrable_domain(&normalized_url).unwrap_or_default();
+            analysis.insert(
+                "display_mismatch".to_string(),
+                format!("Link text shows {} but points to {}", shown, target),
+            );
+        }
+        url_features.insert("display_mismatch".to_string(), mismatch.is_some());
+    }
+    for feature in rules::custom_features() {
+        if *url_features.get(&feature.name).unwrap_or(&false) {
+            analysis.insert(feature.name.clone(), format!("Matched custom rule /{}/", feature.regex));
+        }
+    }
+    for (feature, value) in &url_features {
+        if *value {
+            analysis.entry(feature.clone()).or_insert_with(|| "Suspicious feature detected".to_string());
+        }
+    }
+    let shortener = check_result.shortener.filter(|_| check_result.is_shortened);
+    // Links that only redirected to another domain score as an unlisted shortener would.
+    let shortener_severity = match shortener {
+        Some(shortener) => Some(config().shortener_severity(shortener.domain)),
+        None => check_result.is_shortened.then_some(ShortenerSeverity::Standard),
+    };
+    if let Some(code) = check_result.short_code.as_ref().filter(|_| check_result.is_shortened) {
+        analysis.insert("short_code".to_string(), code.clone());
+    }
+    if let (Some(shortener), Some(severity)) = (shortener, shortener_severity) {
+        analysis.insert(
+            "shortener".to_string(),
+            format!("Known URL shortener {} (severity: {})", shortener.domain, severity.as_str()),
+        );
+        analysis.insert(
+            "shortener_match".to_string(),
+            format!("{} ({} match)", shortener.domain, shortener.kind.as_str()),
+        );
+    }
+    // Internal hosts keep deny-list matches but not the structural signals their naming trips.
+    if let Some(entry) = internal_domain(&normalized_url, &config().internal_domains) {
+        let dropped = drop_structural_signals(&mut url_features, &mut analysis);
+        let note = match dropped.is_empty() {
+            true => format!("Host is under internal domain {}", entry),
+            false => format!("Host is under internal domain {}; not scored: {}", entry, dropped.join(", ")),
+        };
+        analysis.insert("internal_domain".to_string(), note);
+    }
+    // Campaigns hit many similar URLs at once; earlier high-risk ones in the stats window vouch for this one.
+    if config().trending_min_count > 0 {
+        let domain = registrable_domain(&normalized_url);
+        let fingerprint = structural_fingerprint(&normalized_url, |signal| {
+            url_features.get(signal).or_else(|| url_features.get(&format!("has_{}", signal))).copied().unwrap_or(false)
+        });
+        let correlation = state.stats.correlated(&normalized_url, domain.as_deref(), fingerprint.as_deref());
+        let mut matches = Vec::new();
+        if let Some(domain) = domain.filter(|_| correlation.domain >= config().trending_min_count) {
+            matches.push(format!("{} on {}", correlation.domain, domain));
+        }
+        if let Some(fingerprint) = fingerprint.filter(|_| correlation.fingerprint >= config().trending_min_count) {
+            matches.push(format!("{} shaped {}", correlation.fingerprint, fingerprint));
+        }
+        if !matches.is_empty() {
+            analysis.insert(
+                "recently_trending_pattern".to_string(),
+                format!("Resembles recent high-risk URLs: {}", matches.join(", ")),
+            );
+        }
+        url_features.insert("recently_trending_pattern".to_string(), !matches.is_empty());
+    }
+    // Only pages already in the medium band are fetched; the form check decides which way they tip.
+    let preliminary_score = calculate_risk_score(shortener_severity, check_result.is_phishing, &url_features, tld_weight, &reputation, None);
+    if config().content_peek
+        && !skip_network
+        && RiskBucket::from_score(preliminary_score) == RiskBucket::Medium
+        && let Ok(parsed_url) = Url::parse(&normalized_url)
+        && matches!(parsed_url.scheme(), "http" | "https")
+        && network.spend()
+    {
+        let timeout = Duration::from_secs(config().request_timeout_secs);
+        let start = timings.start();
+        let peek = peek::password_form(&parsed_url, config().content_peek_max_bytes, timeout).await;
+        timings.record("content_peek", start);
+        network.record(peek.is_ok());
+        match peek {
+            Ok(peek::PeekOutcome::CrossOrigin(action)) => {
+                analysis.insert("password_form_action".to_string(), action.clone());
+                analysis.insert(
+                    "cross_origin_password_form".to_string(),
+                    format!("Page has a password form posting to {}", action),
+                );
+                url_features.insert("cross_origin_password_form".to_string(), true);
+            }
+            Ok(peek::PeekOutcome::Blocked(ip)) => {
+                analysis.insert("content_peek_blocked".to_string(), format!("Host resolves to non-public address {}", ip));
+            }
+            Ok(peek::PeekOutcome::Clean) => {}
+            Err(e) => log::debug!("Content peek failed for {}: {}", normalized_url, e),
+        }
+    }
+    // Composites see every signal that fired and still counts, i.e. is not suppressed.
+    if !config().composite_rules.is_empty() {
+        let fired = fired_signals(&check_result, &analysis, &url_features);
+        let counts = |signal: &String| {
+            let signal = signal.strip_prefix("has_").unwrap_or(signal);
+            fired.iter().any(|s| s == signal) && !config().is_suppressed(signal)
+        };
+        for rule in config().composite_rules.iter().filter(|rule| rule.signals.iter().all(counts)) {
+            analysis.insert(
+                rule.name.clone(),
+                format!("Composite rule: {} together (+{:.2})", rule.signals.join(" + "), rule.weight),
+            );
+            url_features.insert(rule.name.clone(), true);
+        }
+    }
+    // Detection rules run last, so their `signal` conditions see every other signal, composites included.
+    if !rules::detection_rules().is_empty()
+        && let Ok(parsed_url) = Url::parse(&normalized_url)
+    {
+        let signals = fired_signals(&check_result, &analysis, &url_features);
+        let input = rules::RuleInput { parsed_url: &parsed_url, signals: &signals, features: &raw_features };
+        for rule in rules::detection_rules().iter().filter(|rule| rule.condition.matches(&input)) {
+            let verdict = match rule.verdict {
+                Some(rules::Verdict::High) => ", verdict high",
+                Some(rules::Verdict::Medium) => ", verdict medium",
+                None => "",
+            };
+            analysis.insert(rule.name.clone(), format!("Detection rule matched (+{:.2}{})", rule.score, verdict));
+            url_features.insert(rule.name.clone(), true);
+        }
+    }
+    // Self-test runs are local-only by design and never count as degraded.
+    let network_failed = !skip_network && network.all_failed();
+    if network_failed {
+        if config().on_network_failure == config::NetworkFailurePolicy::Fail {
+            return Err(NetworkFailure.into());
+        }
+        analysis.insert(
+            "degraded".to_string(),
+            format!("All {} network-dependent checks failed", network.attempted),
+        );
+    }
+    if network.skipped > 0 {
+        analysis.insert(
+            "budget_exhausted".to_string(),
+            format!("{} network checks skipped after {} outbound requests", network.skipped, network.requests),
+        );
+    }
+    let degraded = network_failed || network.skipped > 0;
+    let score_with = |profile| calculate_risk_score(shortener_severity, check_result.is_phishing, &url_features, tld_weight, &reputation, profile);
+    let risk_score = match config().on_network_failure {
+        config::NetworkFailurePolicy::Neutral if network_failed => None,
+        _ => Some(score_with(None)),
+    };
+    // Every configured profile is scored (it is only arithmetic) so cached analyses can answer any `profiles=`.
+    let profiles = config()
+        .risk_profiles
+        .iter()
+        .map(|(name, profile)| {
+            let risk_score = risk_score.map(|_| score_with(Some(profile)));
+            let bucket = risk_score.map_or(RiskBucket::Unknown, |score| RiskBucket::from_score_in(score, profile));
+            (name.clone(), ProfileVerdict { risk_score, risk_assessment: bucket.assessment() })
+        })
+        .collect();
+    let risk_assessment = risk_score.map_or(RiskBucket::Unknown, RiskBucket::from_score).assessment().to_string();
+    analysis.insert("risk_assessment".to_string(), risk_assessment);
+    let signals = drop_suppressed(fired_signals(&check_result, &analysis, &url_features), &mut analysis);
+    limit_analysis(&mut analysis, &normalized_url);
+
+    Ok(UrlAnalysis {
+        url: normalized_url.clone(),
+        input_url: input_url.to_string(),
+        normalized_url,
+        is_shortened: check_result.is_shortened,
+        is_phishing: check_result.is_phishing,
+        risk_score,
+        severity: config().severity_level(risk_score.map_or(RiskBucket::Unknown, RiskBucket::from_score).as_str()),
+        degraded,
+        fast_path: fast_path.is_some(),
+        offline: config().offline,
+        cached: false,
+        feed_stale: state.feeds.is_stale(),
+        feed_generation: phishing_list.as_ref().as_ref().ok().map(|list| list.generation.clone()),
+        signals,
+        analysis,
+        profiles,
+        timings: timings.0,
+        features: Some(raw_features),
+        explanation: None,
+    })
+}
+
+// Caps the number of `analysis` entries and the length of each value. Entries are kept in key
+// order (`risk_assessment` always survives) and the rest are summarized under `truncated`.
+fn limit_analysis(analysis: &mut HashMap<String, String>, url: &str) {
+    let max_len = config().max_analysis_value_len;
+    let mut shortened = 0;
+    for value in analysis.values_mut() {
+        if value.chars().count() > max_len {
+            *value = value.chars().take(max_len).collect::<String>() + "...";
+            shortened += 1;
+        }
+    }
+    let max_entries = config().max_analysis_entries.max(2);
+    let mut dropped = 0;
+    if analysis.len() > max_entries {
+        let mut keys: Vec<String> = analysis.keys().filter(|k| *k != "risk_assessment").cloned().collect();
+        keys.sort();
+        // One slot for `risk_assessment`, one for the marker.
+        for key in keys.iter().skip(max_entries.saturating_sub(2)) {
+            analysis.remove(key);
+            dropped += 1;
+        }
+        analysis.insert("truncated".to_string(), format!("...{} more", dropped));
+    }
+    if shortened > 0 || dropped > 0 {
+        log::warn!("Truncated analysis for {}: {} entries dropped, {} values shortened", url, dropped, shortened);
+    }
+}
+
+// Every signal identifier the built-in checks can report; custom rules add their own names.
+const BUILTIN_SIGNALS: &[&str] = &[
+    "at_symbol", "brand_context_mismatch", "cross_origin_password_form", "dangerous_download", "dash_in_domain",
+    "deep_path", "deep_path_with_brand", "display_mismatch", "double_slash", "embedded_dangerous_uri",
+    "embedded_url_in_path", "encoded_host", "excessive_query", "fuzzy_phishing_match", "high_risk_geo",
+    "improbable_ngrams", "in_top_sites", "ip_address", "ip_host_with_brand_path", "known_phishing_favicon",
+    "leetspeak_brand", "multiple_subdomains", "non_nfc_host", "numeric_or_hex_host", "open_redirect_param",
+    "parameter_pollution", "phishing_db", "protocol_token_in_host", "punycode_brand_spoof", "recently_trending_pattern",
+    "redirect_target_phishing", "rtl_in_host", "shared_hosting", "shortened", "suspicious_tld", "target_error_status",
+    "target_unreachable", "top_site_lookalike", "very_recent_cert_issuance", "weak_email_auth", "weak_tls",
+];
+
+// Typos in `suppressed_signals` would silently suppress nothing, so unknown names fail startup.
+fn validate_suppressed_signals() -> Result<(), String> {
+    for signal in &config().suppressed_signals {
+        let signal = signal.strip_prefix("has_").unwrap_or(signal);
+        if signal == "phishing_db" {
+            return Err("suppressed_signals: phishing_db cannot be suppressed; add an allow entry instead".to_string());
+        }
+        let composite = config().composite_rules.iter().any(|rule| rule.name == signal);
+        if !is_known_signal(signal) && !composite && !rules::detection_rules().iter().any(|rule| rule.name == signal) {
+            return Err(format!("suppressed_signals: unknown signal {}", signal));
+        }
+    }
+    Ok(())
+}
+
+fn is_known_signal(signal: &str) -> bool {
+    BUILTIN_SIGNALS.contains(&signal) || rules::custom_features().iter().any(|f| f.name == signal)
+}
+
+// A composite that names an unknown signal could never fire, so it fails startup like a bad suppression.
+fn validate_composite_rules() -> Result<(), String> {
+    let rules = &config().composite_rules;
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.name.is_empty() || is_known_signal(&rule.name) || rules[..i].iter().any(|r| r.name == rule.name) {
+            return Err(format!("composite_rules: name {:?} is empty or already taken", rule.name));
+        }
+        if rule.signals.len() < 2 {
+            return Err(format!("composite_rules: {} needs at least two signals", rule.name));
+        }
+        if let Some(signal) = rule.signals.iter().find(|s| !is_known_signal(s.strip_prefix("has_").unwrap_or(s))) {
+            return Err(format!("composite_rules: {} names unknown signal {}", rule.name, signal));
+        }
+        if !rule.weight.is_finite() {
+            return Err(format!("composite_rules: {} needs a numeric weight", rule.name));
+        }
+    }
+    Ok(())
+}
+
+// Rule names become signals, so they must be new; `signal` conditions must name a signal that exists.
+fn validate_detection_rules() -> Result<(), String> {
+    let rules = rules::load_detection_rules()?;
+    let composite = |name: &str| config().composite_rules.iter().any(|rule| rule.name == name);
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.name.is_empty() || is_known_signal(&rule.name) || composite(&rule.name) || rules[..i].iter().any(|r| r.name == rule.name) {
+            return Err(format!("detection rule: name {:?} is empty or already taken", rule.name));
+        }
+        if let Some(signal) = rule.condition.signals().into_iter().find(|s| !is_known_signal(s) && !composite(s)) {
+            return Err(format!("detection rule {}: unknown signal {}", rule.name, signal));
+        }
+        if !rule.score.is_finite() {
+            return Err(format!("detection rule {}: score must be a number", rule.name));
+        }
+    }
+    Ok(())
+}
+
+// Removes suppressed signals from the response, noting the ones that fired under `suppressed_signals`.
+fn drop_suppressed(signals: Vec<String>, analysis: &mut HashMap<String, String>) -> Vec<String> {
+    let (suppressed, signals): (Vec<String>, Vec<String>) = signals.into_iter().partition(|s| config().is_suppressed(s));
+    for signal in &suppressed {
+        if signal == "shortened" {
+            analysis.remove("shortener");
+            analysis.remove("shortener_match");
+            analysis.remove("short_code");
+        }
+        analysis.remove(signal);
+        analysis.remove(&format!("has_{}", signal));
+    }
+    if !suppressed.is_empty() {
+        analysis.insert("suppressed_signals".to_string(), suppressed.join(", "));
+    }
+    signals
+}
+
+// Stable identifiers of every signal that fired, sorted. Feature names drop their `has_` prefix.
+fn fired_signals(check_result: &UrlCheckResult, analysis: &HashMap<String, String>, url_features: &HashMap<String, bool>) -> Vec<String> {
+    let mut signals: Vec<String> = url_features
+        .iter()
+        .filter(|(_, fired)| **fired)
+        .map(|(feature, _)| feature.strip_prefix("has_").unwrap_or(feature).to_string())
+        .collect();
+    if check_result.is_phishing {
+        signals.push("phishing_db".to_string());
+    }
+    if analysis.contains_key("fuzzy_phishing_match") {
+        signals.push("fuzzy_phishing_match".to_string());
+    }
+    if check_result.is_shortened {
+        signals.push("shortened".to_string());
+    }
+    signals.sort();
+    signals
+}
+
+impl UrlAnalysis {
+    fn bucket(&self) -> RiskBucket {
+        self.risk_score.map_or(RiskBucket::Unknown, RiskBucket::from_score)
+    }
+}
+
+fn history_record(analysis: &UrlAnalysis) -> history::HistoryRecord {
+    history::HistoryRecord {
+        input_url: analysis.input_url.clone(),
+        normalized_url: analysis.normalized_url.clone(),
+        risk_score: analysis.risk_score,
+        bucket: analysis.bucket().as_str(),
+        is_phishing: analysis.is_phishing,
+    }
+}
+
+fn record_history(state: &AppState, analysis: &UrlAnalysis) {
+    if let Some(history) = state.history.clone() {
+        let record = history_record(analysis);
+        tokio::task::spawn_blocking(move || history.record(&record));
+    }
+}
+
+// Fires the high-risk webhook in the background; the response never waits on it.
+fn send_alert(state: &AppState, analysis: &UrlAnalysis) {
+    let Some(webhook) = state.webhook.clone() else {
+        return;
+    };
+    let Some(risk_score) = analysis.risk_score.filter(|score| *score >= config().webhook_threshold) else {
+        return;
+    };
+    let alert = webhook::Alert {
+        url: analysis.input_url.clone(),
+        normalized_url: analysis.normalized_url.clone(),
+        risk_score,
+        signals: analysis.signals.clone(),
+    };
+    let generation = state.feed_generation().unwrap_or_else(|| "none".to_string());
+    tokio::spawn(async move { webhook.send(alert, &generation, config().instance_name()).await });
+}
+
+fn record_stats(stats: &stats::Stats, analysis: &UrlAnalysis) {
+    stats.record(
+        analysis.normalized_url.clone(),
+        analysis.bucket(),
+        registrable_domain(&analysis.normalized_url),
+        structural_fingerprint(&analysis.normalized_url, |signal| analysis.signals.iter().any(|s| s == signal)),
+        analysis.signals.clone(),
+        analysis.is_phishing,
+    );
+}
+
+// Prefix of cache keys for `scope=domain` analyses.
+const DOMAIN_SCOPE_KEY: &str = "domain:";
+
+// Only plain analyses are cached: display text, timings and local-only runs change the result.
+async fn cached_analyze_url(url: &str, state: &AppState, options: &AnalysisOptions) -> Result<UrlAnalysis, Box<dyn std::error::Error + Send + Sync>> {
+    let cache = state.cache.as_ref().filter(|_| options.display_text.is_none() && !options.timings && !options.local_only);
+    let Some(cache) = cache else {
+        return analyze_url(url, state, options).await;
+    };
+    // Domain scope flags shortener homepages, so its entries must not answer URL-scope lookups.
+    let key = match options.scope {
+        Scope::Url => normalize_url(url),
+        Scope::Domain => format!("{}{}", DOMAIN_SCOPE_KEY, domain_scope_url(url)),
+    };
+    if let Some((mut analysis, trusted)) = cache.get(&key) {
+        analysis.input_url = url.to_string();
+        analysis.cached = true;
+        // A trusted verdict is returned exactly as stored, feed freshness included.
+        if !trusted {
+            analysis.feed_stale = state.feeds.is_stale();
+        }
+        return Ok(analysis);
+    }
+    let analysis = analyze_url(url, state, options).await?;
+    // A degraded result reflects a transient outage, so it is not worth reusing.
+    if !analysis.degraded {
+        let trusted = analysis.bucket() == RiskBucket::Low;
+        cache.insert(key, analysis.clone(), trusted);
+    }
+    Ok(analysis)
+}
+
+async fn analysis_response(url: &str, state: &AppState, options: &AnalysisOptions) -> (StatusCode, Json<AnalysisResponse>) {
+    let resolved_url = match resolve_against_base(url, options.base.as_deref()) {
+        Ok(resolved_url) => resolved_url,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(AnalysisResponse::error(url, e))),
+    };
+    let (input, url) = (url, resolved_url.as_deref().unwrap_or(url));
+    if !is_valid_url(url) {
+        return (StatusCode::BAD_REQUEST, Json(AnalysisResponse::error(input, "Invalid URL".to_string())));
+    }
+
+    match cached_analyze_url(url, state, options).await {
+        Ok(mut analysis) => {
+            analysis.profiles.retain(|name, _| options.profiles.contains(name));
+            if !options.raw_features {
+                analysis.features = None;
+            }
+            if options.explain {
+                analysis.explanation = Some(explain::explain(&analysis.signals, analysis.bucket()));
+            }
+            record_stats(&state.stats, &analysis);
+            send_alert(state, &analysis);
+            record_history(state, &analysis);
+            let (status, action) = match config().mode {
+                config::Mode::Score => (StatusCode::OK, None),
+                config::Mode::Enforce => match analysis.risk_score {
+                    Some(score) if score >= config().block_threshold => (StatusCode::FORBIDDEN, Some("block")),
+                    Some(_) => (StatusCode::OK, Some("allow")),
+                    // Neither safe nor known-bad; leave the decision to the caller.
+                    None => (StatusCode::OK, Some("review")),
+                },
+            };
+            let mut response = AnalysisResponse {
+                url: input.to_string(),
+                status: "success".to_string(),
+                data: Some(analysis),
+                error: None,
+                resolved_url,
+                action,
+                verdict: None,
+            };
+            if options.redact {
+                response.redact();
+            }
+            (status, Json(response))
+        }
+        Err(e) if e.is::<NetworkFailure>() => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(AnalysisResponse::error(input, format!("Analysis unavailable: {}", e))),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(AnalysisResponse::error(input, format!("Analysis failed: {}", e))),
+        ),
+    }
+}
+
+// Caller presented one of the configured API keys via `X-API-Key` or `Authorization: Bearer`.
+fn is_authenticated(headers: &HeaderMap) -> bool {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let key = header("x-api-key").or_else(|| header(AUTHORIZATION.as_str()).and_then(|v| v.strip_prefix("Bearer ")));
+    key.is_some_and(|key| config().api_keys.iter().any(|k| k == key))
+}
+
+// Whether detailed signals should be withheld from this caller.
+fn redact_for(headers: &HeaderMap) -> bool {
+    config().public_mode && !is_authenticated(headers)
+}
+
+// Drops null, false and empty-object fields at every level. `signals` is kept even when empty,
+// so a compact benign result still says that nothing fired, and `features` keeps its fixed keys.
+fn compact_json(value: &mut serde_json::Value) {
+    if let serde_json::Value::Object(map) = value {
+        map.retain(|key, value| {
+            if key == "features" {
+                return true;
+            }
+            compact_json(value);
+            let empty = match value {
+                serde_json::Value::Null | serde_json::Value::Bool(false) => true,
+                serde_json::Value::Object(map) => map.is_empty(),
+                _ => false,
+            };
+            key == "signals" || !empty
+        });
+    }
+}
+
+#[axum::debug_handler]
+async fn analyze_url_handler(query: Result<Query<AnalyzeOptions>, QueryRejection>, State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let params = match AnalyzeOptions::parse(query) {
+        Ok(params) => params,
+        Err((url, message)) => return (StatusCode::BAD_REQUEST, Json(AnalysisResponse::error(&url, message))).into_response(),
+    };
+    let options = AnalysisOptions {
+        timings: params.timings,
+        redact: redact_for(&headers),
+        scope: params.scope,
+        profiles: params.profile_names(),
+        base: params.base,
+        raw_features: params.features == Some(FeatureExport::Raw),
+        explain: params.explain == Some(Explain::Text),
+        ..Default::default()
+    };
+    let (status, Json(response)) = analysis_response(&params.url, &state, &options).await;
+    let verdict = verdict_headers(&response);
+    let mut http_response = match cef::format_response(&response) {
+        Some(line) if ResponseFormat::negotiate(params.format, &headers) == ResponseFormat::Cef => {
+            (status, [(CONTENT_TYPE, CEF_CONTENT_TYPE)], line).into_response()
+        }
+        _ if params.compact.unwrap_or(config().compact_responses) => {
+            let mut body = serde_json::to_value(&response).unwrap_or_default();
+            compact_json(&mut body);
+            (status, Json(body)).into_response()
+        }
+        _ => (status, Json(response)).into_response(),
+    };
+    http_response.headers_mut().extend(verdict);
+    http_response
+}
+
+// `X-Ferrum-Verdict` (allow/warn/block/review) and `X-Ferrum-Risk-Score` for callers that read
+// only headers, e.g. proxies using `HEAD /analyze` (axum answers HEAD with the GET handler, minus the body).
+fn verdict_headers(response: &AnalysisResponse) -> Vec<(HeaderName, HeaderValue)> {
+    let bucket = match (&response.data, &response.verdict) {
+        (Some(data), _) => data.bucket(),
+        (None, Some(verdict)) => RiskBucket::from_name(verdict.risk),
+        (None, None) => return Vec::new(),
+    };
+    let mut headers = vec![(HeaderName::from_static("x-ferrum-verdict"), HeaderValue::from_static(bucket.verdict()))];
+    if let Some(score) = response.data.as_ref().and_then(|data| data.risk_score)
+        && let Ok(value) = HeaderValue::from_str(&round_score(score).to_string())
+    {
+        headers.push((HeaderName::from_static("x-ferrum-risk-score"), value));
+    }
+    headers
+}
+
+#[axum::debug_handler]
+async fn analyze_link_handler(State(state): State<AppState>, headers: HeaderMap, Json(params): Json<LinkQuery>) -> (StatusCode, Json<AnalysisResponse>) {
+    let options = AnalysisOptions {
+        display_text: Some(params.display),
+        redact: redact_for(&headers),
+        base: params.base,
+        ..Default::default()
+    };
+    analysis_response(&params.url, &state, &options).await
+}
+
+// Runs `analyze` once per distinct normalized URL, at most `concurrency` at a time, and returns
+// a response per input in input order plus the number of distinct URLs. Results arrive in
+// completion order and are put back into input order through the normalized-URL map.
+async fn analyze_in_input_order<F, Fut>(urls: &[String], concurrency: usize, analyze: F) -> (Vec<AnalysisResponse>, usize)
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = AnalysisResponse>,
+{
+    let mut pending: HashMap<String, String> = HashMap::new();
+    for url in urls {
+        pending.entry(normalize_url(url)).or_insert_with(|| url.clone());
+    }
+    let unique: HashMap<String, AnalysisResponse> = stream::iter(pending)
+        .map(|(key, url)| {
+            let response = analyze(url);
+            async move { (key, response.await) }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    let results = urls
+        .iter()
+        .map(|url| {
+            let mut response = unique[&normalize_url(url)].clone();
+            response.url = url.clone();
+            if let Some(data) = response.data.as_mut() {
+                data.input_url = url.clone();
+            }
+            response
+        })
+        .collect();
+    (results, unique.len())
+}
+
+async fn analyze_batch_handler(State(state): State<AppState>, headers: HeaderMap, Json(params): Json<BatchQuery>) -> (StatusCode, Json<BatchResponse>) {
+    let max_batch_size = config().max_batch_size;
+    if params.urls.len() > max_batch_size {
+        let error = AnalysisResponse::error("", format!("Batch exceeds {} URLs", max_batch_size));
+        let summary = BatchSummary { total: params.urls.len(), unique: 0, dedup_ratio: 0.0 };
+        return (StatusCode::PAYLOAD_TOO_LARGE, Json(BatchResponse { results: vec![error], summary }));
+    }
+
+    let options = AnalysisOptions {
+        redact: redact_for(&headers),
+        base: params.base,
+        ..Default::default()
+    };
+    let (results, unique) = analyze_in_input_order(&params.urls, config().batch_concurrency, |url| {
+        let (state, options) = (&state, &options);
+        async move { analysis_response(&url, state, options).await.1.0 }
+    })
+    .await;
+    let summary = BatchSummary::new(results.len(), unique);
+    (StatusCode::OK, Json(BatchResponse { results, summary }))
+}
+
+// `GET /analyze/stream?url=...&url=...[&base=...]`, for `EventSource` clients.
+async fn analyze_stream_get_handler(State(state): State<AppState>, headers: HeaderMap, Query(params): Query<Vec<(String, String)>>) -> Response {
+    let urls = params.iter().filter(|(key, _)| key == "url").map(|(_, url)| url.clone()).collect();
+    let base = params.into_iter().find(|(key, _)| key == "base").map(|(_, base)| base);
+    analyze_stream(state, &headers, BatchQuery { urls, base })
+}
+
+// `POST /analyze/stream` with a `/analyze/batch` body, for lists too long for a query string.
+async fn analyze_stream_post_handler(State(state): State<AppState>, headers: HeaderMap, Json(params): Json<BatchQuery>) -> Response {
+    analyze_stream(state, &headers, params)
+}
+
+// The batch engine answered as Server-Sent Events: a `result` event per input URL (its input
+// position as the event id) as soon as its analysis completes, then a `done` event with counts.
+// A client that disconnects drops the stream, which cancels the analyses still in flight.
+fn analyze_stream(state: AppState, headers: &HeaderMap, params: BatchQuery) -> Response {
+    let max_batch_size = config().max_batch_size;
+    if params.urls.len() > max_batch_size {
+        let error = AnalysisResponse::error("", format!("Batch exceeds {} URLs", max_batch_size));
+        return (StatusCode::PAYLOAD_TOO_LARGE, Json(error)).into_response();
+    }
+    let options = Arc::new(AnalysisOptions {
+        redact: redact_for(headers),
+        base: params.base,
+        ..Default::default()
+    });
+    // Input positions of each normalized URL, so duplicates are analyzed once.
+    let mut pending: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+    for (position, url) in params.urls.into_iter().enumerate() {
+        pending.entry(normalize_url(&url)).or_default().push((position, url));
+    }
+    let summary = BatchSummary::new(pending.values().map(Vec::len).sum(), pending.len());
+    let counts = Arc::new(Mutex::new(BTreeMap::new()));
+    let results = stream::iter(pending.into_values())
+        .map(move |inputs| {
+            let (state, options) = (state.clone(), options.clone());
+            async move {
+                let (_, Json(response)) = analysis_response(&inputs[0].1, &state, &options).await;
+                (inputs, response)
+            }
+        })
+        .buffer_unordered(config().batch_concurrency.max(1));
+    let result_counts = counts.clone();
+    let events = results
+        .flat_map(move |(inputs, response)| {
+            let bucket = match (&response.data, &response.verdict) {
+                (Some(data), _) => data.bucket().as_str(),
+                (None, Some(verdict)) => RiskBucket::from_name(verdict.risk).as_str(),
+                (None, None) => "error",
+            };
+            *result_counts.lock().unwrap().entry(bucket).or_insert(0) += inputs.len();
+            stream::iter(inputs.into_iter().map(move |(position, url)| {
+                let mut response = response.clone();
+                response.url = url.clone();
+                if let Some(data) = response.data.as_mut() {
+                    data.input_url = url;
+                }
+                Event::default().event("result").id(position.to_string()).json_data(&response)
+            }))
+        })
+        .chain(stream::once(async move {
+            let counts = counts.lock().unwrap().clone();
+            Event::default().event("done").json_data(StreamSummary { summary, counts })
+        }));
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+async fn analyze_text_handler(State(state): State<AppState>, headers: HeaderMap, Json(params): Json<TextQuery>) -> Json<TextResponse> {
+    let redact = redact_for(&headers);
+    let options = AnalysisOptions::default();
+    let mut seen = HashSet::new();
+    let mut unique = extract::extract_urls(&params.text);
+    unique.retain(|url| seen.insert(normalize_url(url)));
+    if unique.len() > config().max_batch_size {
+        log::warn!("Text contains more than {} URLs, ignoring the rest", config().max_batch_size);
+        unique.truncate(config().max_batch_size);
+    }
+    let (mut urls, _) = analyze_in_input_order(&unique, config().batch_concurrency, |url| {
+        let (state, options) = (&state, &options);
+        async move { analysis_response(&url, state, options).await.1.0 }
+    })
+    .await;
+
+    let max_risk_score = urls
+        .iter()
+        .filter_map(|r| r.data.as_ref().and_then(|d| d.risk_score))
+        .fold(None, |max: Option<f32>, score| Some(max.map_or(score, |m| m.max(score))));
+    let verdict = match max_risk_score {
+        Some(score) if redact => RiskBucket::from_score(score).public_message().to_string(),
+        Some(score) => RiskBucket::from_score(score).assessment().to_string(),
+        None => "No URLs found".to_string(),
+    };
+    if redact {
+        urls.iter_mut().for_each(AnalysisResponse::redact);
+        return Json(TextResponse { urls, max_risk_score: None, verdict });
+    }
+    Json(TextResponse { urls, max_risk_score, verdict })
+}
+
+#[derive(Deserialize)]
+struct NormalizeQuery {
+    url: String,
+}
 
-// async fn check_redirect(url: &str) -> Result<(bool, String, bool), Box<dyn std::error::Error + Send + Sync>> {
-//     let normalized_url = normalize_url(url);
-//     let client = Client::builder()
-//         .timeout(Duration::from_secs(10))  // Increased timeout to 10s
-//         .redirect(reqwest::redirect::Policy::limited(5))
-//         .build()?;
-//     let response = client.get(&normalized_url).send().await.map_err(|e| {
-//         log::warn!("Redirect check failed for {}: {}", normalized_url, e);
-//         e
-//     })?;
-//     let final_url = response.url().to_string();
-//     let redirects = final_url != normalized_url;
-//     let is_cross_domain = if redirects {
-//         let original_domain = Url::parse(&normalized_url)?.host_str().unwrap_or("").to_string();
-//         let final_domain = Url::parse(&final_url)?.host_str().unwrap_or("").to_string();
-//         log::debug!("Redirect: {} -> {}, cross-domain: {}", normalized_url, final_url, original_domain != final_domain);
-//         original_domain != final_domain
-//     } else {
-//         false
-//     };
-//     Ok((redirects, final_url, is_cross_domain))
-// }
-// async fn checking_url_enhanced(url: &str, phishing_list: &Result<Value, String>) -> UrlCheckResult {
-    
-//     let normalized_url = normalize_url(url);
-//     let is_phishing_local = check_local_phishing_db(&normalized_url);
-//     let is_phishing_online = check_online_phishing_db(&normalized_url, phishing_list).await.unwrap_or_else(|e| {
-//         log::warn!("Online phishing check failed: {}", e);
-//         false
-//     });
-//     let redirect_result = check_redirect(&normalized_url).await.unwrap_or_else(|e| {
-//         log::warn!("Redirect check failed: {}", e);
-//         (false, normalized_url.clone(), false)
-//     });
-//     let is_shortened = if is_known_shortener(&normalized_url) {
-//         true
-//     } else {
-//         check_redirect(&normalized_url).await.map_or(false, |(redirects, _, is_cross_domain)| redirects && is_cross_domain)
-//     };
-//     UrlCheckResult {
-//         is_phishing: is_phishing_local || is_phishing_online,
-//         is_shortened,
-//     }
-// }
-
-async fn analyze_url(url: &str, phishing_list: Arc<Result<Value, String>>) -> Result<UrlAnalysis, Box<dyn std::error::Error + Send + Sync>> {
-    let normalized_url = normalize_url(url);
-    let mut analysis = HashMap::new();
-    let check_result = checking_url_enhanced(&normalized_url, &phishing_list).await;
-    // let (redirects, final_url, is_cross_domain) = check_redirect(&normalized_url).await.unwrap_or_else(|e| {
-        // log::warn!("Redirect check failed: {}", e);
-        // (false, normalized_url.clone(), false)
-    // });
-
-    // analysis.insert("redirect".to_string(), if redirects {
-    //     format!("URL redirects to: {}", final_url)
-    // } else {
-    //     "No redirection".to_string()
-    // });
+#[derive(Deserialize)]
+struct CompareQuery {
+    a: String,
+    b: String,
+}
 
-    if check_result.is_phishing {
-        analysis.insert("phishing_detected".to_string(), "URL found in phishing database".to_string());
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Relationship {
+    // Same host.
+    SameSite,
+    // Same registrable domain, different subdomain.
+    SameOrg,
+    // Different registrable domains listed under one brand in `brand_domains`.
+    SameBrand,
+    CrossSite,
+}
 
-    let url_features = extract_url_features(&normalized_url);
-    for (feature, value) in &url_features {
-        if *value {
-            analysis.insert(feature.clone(), "Suspicious feature detected".to_string());
-        }
-    } //is_cross_domain
-    let risk_score = calculate_risk_score(check_result.is_shortened, check_result.is_phishing, &url_features);
-    let risk_assessment = if risk_score >= 0.7 {
-        "High risk - Likely phishing"
-    } else if risk_score >= 0.4 {
-        "Medium risk - Suspicious"
+#[derive(Serialize)]
+struct CompareSide {
+    input: String,
+    normalized: String,
+    host: String,
+    registrable_domain: String,
+}
+
+#[derive(Serialize)]
+struct CompareResponse {
+    a: CompareSide,
+    b: CompareSide,
+    relationship: Relationship,
+    same_registrable_domain: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brand: Option<String>,
+    // `a_to_b` or `b_to_a` when one URL carries the other in a redirect parameter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirects: Option<&'static str>,
+}
+
+fn compare_side(url: &str) -> Result<CompareSide, String> {
+    resolve_against_base(url, None)?;
+    validate_url(url)?;
+    let normalized = normalize_url(url);
+    let host = Url::parse(&normalized).ok().and_then(|u| u.host_str().map(str::to_lowercase)).ok_or("URL has no host")?;
+    let registrable_domain = registrable_domain(&normalized).unwrap_or_else(|| host.clone());
+    Ok(CompareSide { input: url.to_string(), normalized, host, registrable_domain })
+}
+
+// Whether `from` wraps a redirect to `to`'s host through a query parameter like `?url=`.
+fn redirects_to(from: &CompareSide, to: &CompareSide) -> bool {
+    redirect_param_targets(&from.normalized).iter().any(|(_, target)| {
+        Url::parse(target).ok().and_then(|u| u.host_str().map(str::to_lowercase)).as_deref() == Some(to.host.as_str())
+    })
+}
+
+// How two URLs relate, e.g. a link's displayed text and its target. Purely local: redirects are
+// only seen when one URL carries the other in a redirect parameter, never by fetching.
+async fn compare_handler(Query(params): Query<CompareQuery>) -> Response {
+    let (a, b) = match (compare_side(&params.a), compare_side(&params.b)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) => return (StatusCode::BAD_REQUEST, Json(AnalysisResponse::error(&params.a, e))).into_response(),
+        (_, Err(e)) => return (StatusCode::BAD_REQUEST, Json(AnalysisResponse::error(&params.b, e))).into_response(),
+    };
+    let same_registrable_domain = a.registrable_domain == b.registrable_domain;
+    let brand = config()
+        .brand_domains
+        .iter()
+        .find(|(_, domains)| {
+            let listed = |side: &CompareSide| domains.iter().any(|d| d.eq_ignore_ascii_case(&side.registrable_domain));
+            listed(&a) && listed(&b)
+        })
+        .map(|(brand, _)| brand.clone());
+    let relationship = match (a.host == b.host, same_registrable_domain, &brand) {
+        (true, _, _) => Relationship::SameSite,
+        (_, true, _) => Relationship::SameOrg,
+        (_, _, Some(_)) => Relationship::SameBrand,
+        _ => Relationship::CrossSite,
+    };
+    let redirects = if redirects_to(&a, &b) {
+        Some("a_to_b")
+    } else if redirects_to(&b, &a) {
+        Some("b_to_a")
     } else {
-        "Low risk - Likely safe"
-    }.to_string();
-    analysis.insert("risk_assessment".to_string(), risk_assessment);
+        None
+    };
+    Json(CompareResponse { a, b, relationship, same_registrable_domain, brand, redirects }).into_response()
+}
 
-    Ok(UrlAnalysis {
-        url: normalized_url,
-        is_shortened: check_result.is_shortened,
-        is_phishing: check_result.is_phishing,
-        risk_score,
-        analysis,
+async fn normalize_handler(Query(params): Query<NormalizeQuery>) -> (StatusCode, Json<NormalizeResponse>) {
+    let reason = validate_url(&params.url).err();
+    let status = if reason.is_some() { StatusCode::BAD_REQUEST } else { StatusCode::OK };
+    (
+        status,
+        Json(NormalizeResponse {
+            normalized: normalize_url(&params.url),
+            input: params.url,
+            valid: reason.is_none(),
+            reason,
+        }),
+    )
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    served_by: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<&'static str>,
+    // Feeds whose latest fetch was rejected, as `category: detail` by feed URL.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    feed_errors: BTreeMap<String, String>,
+    // Feeds the upstream is rate limiting, with seconds until the next attempt. Transient, unlike
+    // other `feed_errors`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    feed_rate_limited: BTreeMap<String, u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    feed_generation: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    offline: bool,
+    // Configured checks that cannot run offline.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unavailable_checks: Vec<&'static str>,
+}
+
+async fn health_handler(State(state): State<AppState>) -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok",
+        served_by: config().instance_name(),
+        environment: config().environment(),
+        feed_errors: state.feeds.errors(),
+        feed_rate_limited: state.feeds.rate_limited(),
+        feed_generation: state.feed_generation(),
+        offline: config().offline,
+        unavailable_checks: if config().offline { config().network_checks() } else { Vec::new() },
     })
 }
 
-#[axum::debug_handler]
-async fn analyze_url_handler(Query(params): Query<UrlQuery>, State(phishing_list): State<Arc<Result<Value, String>>>) -> (StatusCode, Json<AnalysisResponse>) {
-    if !is_valid_url(&params.url) {  
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(AnalysisResponse {
-                url: params.url.clone(),
-                status: "error".to_string(),
-                data: None,
-                error: Some("Invalid URL".to_string()),
-            }),
+#[derive(Serialize)]
+struct ReadyResponse {
+    ready: bool,
+    feed_stale: bool,
+    // No recent analysis was flagged although enough ran; see `quiet_detection_window_secs`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    detection_quiet: bool,
+    // Age of the least recently loaded feed; absent while some feed never loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    feed_age_secs: Option<u64>,
+}
+
+// 503 only when the feeds are stale and `stale_feed_fails_readiness` is set, or detection is quiet
+// and `quiet_detection_fails_readiness` is.
+async fn readyz_handler(State(state): State<AppState>) -> (StatusCode, Json<ReadyResponse>) {
+    let feed_stale = state.feeds.is_stale();
+    let detection_quiet = detection_quiet(&state.stats).is_some();
+    let failing = (feed_stale && config().stale_feed_fails_readiness) || (detection_quiet && config().quiet_detection_fails_readiness);
+    let ready = !failing;
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let feed_age_secs = state.feeds.oldest_age().map(|age| age.as_secs());
+    (status, Json(ReadyResponse { ready, feed_stale, detection_quiet, feed_age_secs }))
+}
+
+// Analyses in the quiet window when none was flagged; `None` when disabled or something was.
+fn detection_quiet(stats: &stats::Stats) -> Option<usize> {
+    let window = Duration::from_secs(config().quiet_detection_window_secs);
+    (!window.is_zero()).then(|| stats.quiet_detection(window, config().quiet_detection_min_samples)).flatten()
+}
+
+fn router(state: AppState) -> Router {
+    let router = Router::new()
+        .route("/analyze", get(analyze_url_handler))
+        .route("/analyze/link", post(analyze_link_handler).layer(body_limit("/analyze/link")))
+        .route("/analyze/batch", post(analyze_batch_handler).layer(body_limit("/analyze/batch")))
+        .route(
+            "/analyze/stream",
+            get(analyze_stream_get_handler).post(analyze_stream_post_handler).layer(body_limit("/analyze/stream")),
+        )
+        .route("/analyze/text", post(analyze_text_handler).layer(body_limit("/analyze/text")))
+        .route("/normalize", get(normalize_handler))
+        .route("/compare", get(compare_handler))
+        .route("/stats", get(stats_handler))
+        .route("/stats/reset", post(stats_reset_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/health", get(health_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/rules/export", get(rules_export_handler))
+        .route("/history/rescan", post(rescan_handler).layer(body_limit("/history/rescan")))
+        .route("/cache/warm", post(cache_warm_handler).layer(body_limit("/cache/warm")))
+        .route("/cache/warm/{id}", get(cache_warm_status_handler));
+    #[cfg(feature = "graphql")]
+    let router = router.route("/graphql", post(graphql::handler).layer(body_limit("/graphql")));
+    router
+        // Must follow the routes: it is attached to the ones registered so far.
+        .method_not_allowed_fallback(method_not_allowed)
+        .layer(axum::middleware::from_fn(handler_timeout))
+        .layer(axum::middleware::map_response(instance_headers))
+        .layer(axum::middleware::map_response_with_state(state.clone(), feed_generation_header))
+        .with_state(state)
+}
+
+// A request with a method its route does not take gets the usual error envelope; axum adds the
+// `Allow` header listing the methods the route does take.
+async fn method_not_allowed(method: Method, uri: Uri) -> (StatusCode, Json<AnalysisResponse>) {
+    let error = AnalysisResponse::error("", format!("Method {} not allowed on {}", method, uri.path()));
+    (StatusCode::METHOD_NOT_ALLOWED, Json(error))
+}
+
+// Caps a POST route's body at its configured size (413 beyond it). axum's own 2 MB
+// extractor default is lifted so the configured limit is the only one.
+fn body_limit(route: &str) -> (DefaultBodyLimit, RequestBodyLimitLayer) {
+    (DefaultBodyLimit::disable(), RequestBodyLimitLayer::new(config().max_body_bytes(route)))
+}
+
+// Cuts off a handler still running after its route's `handler_timeout_secs`, whatever it is waiting
+// on, and answers 504 in the usual error envelope. Background jobs are not affected.
+async fn handler_timeout(request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let secs = config().handler_timeout_secs(&path);
+    if secs == 0 {
+        return next.run(request).await;
+    }
+    match tokio::time::timeout(Duration::from_secs(secs), next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            log::warn!("Request to {} timed out after {}s", path, secs);
+            let error = AnalysisResponse::error("", format!("Request timed out after {}s", secs));
+            (StatusCode::GATEWAY_TIMEOUT, Json(error)).into_response()
+        }
+    }
+}
+
+// `X-Ferrum-Feed-Generation` on every response, so clients caching verdicts see when the deny
+// list changed and can re-query what they hold.
+async fn feed_generation_header(State(state): State<AppState>, mut response: Response) -> Response {
+    if let Some(value) = state.feed_generation().and_then(|g| HeaderValue::from_str(&g).ok()) {
+        response.headers_mut().insert("x-ferrum-feed-generation", value);
+    }
+    response
+}
+
+// Tags every response with the answering instance so fleets behind one name can be told apart.
+async fn instance_headers(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+    for (name, value) in [("x-served-by", config().instance_name()), ("x-environment", config().environment())] {
+        if let Some(value) = value.and_then(|v| HeaderValue::from_str(v).ok()) {
+            headers.insert(name, value);
+        }
+    }
+    response
+}
+
+#[derive(Serialize)]
+struct StatsResponse {
+    #[serde(flatten)]
+    stats: stats::StatsSnapshot,
+    // Progress of the latest `/history/rescan` job.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rescan: Option<history::RescanStatus>,
+    // Recent `/cache/warm` jobs, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warm_jobs: Vec<cache::WarmStatus>,
+}
+
+async fn stats_handler(State(state): State<AppState>) -> Json<StatsResponse> {
+    Json(StatsResponse {
+        stats: state.stats.snapshot(state.cache.as_ref().map_or(0, |cache| cache.len())),
+        rescan: state.history.as_ref().and_then(|history| history.rescan.lock().unwrap().clone()),
+        warm_jobs: state.warm_jobs.all(),
+    })
+}
+
+// Returns the stats for the interval since the last reset and starts a new one; requires an API key.
+async fn stats_reset_handler(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<StatsResponse>, (StatusCode, &'static str)> {
+    if !is_authenticated(&headers) {
+        return Err((StatusCode::UNAUTHORIZED, "An API key is required"));
+    }
+    Ok(Json(StatsResponse {
+        stats: state.stats.reset(state.cache.as_ref().map_or(0, |cache| cache.len())),
+        rescan: state.history.as_ref().and_then(|history| history.rescan.lock().unwrap().clone()),
+        warm_jobs: state.warm_jobs.all(),
+    }))
+}
+
+// Prometheus text format: analysis counters plus outbound request metrics.
+async fn metrics_handler(State(state): State<AppState>) -> Response {
+    let (total_analyses, total_phishing) = state.stats.lifetime();
+    let cache_entries = state.cache.as_ref().map_or(0, |cache| cache.len());
+    let mut out = format!(
+        "# HELP ferrum_analyses_total Completed analyses.\n# TYPE ferrum_analyses_total counter\nferrum_analyses_total {}\n\
+         # HELP ferrum_phishing_total Analyses that matched the phishing database.\n# TYPE ferrum_phishing_total counter\nferrum_phishing_total {}\n\
+         # HELP ferrum_cache_entries Analyses currently cached.\n# TYPE ferrum_cache_entries gauge\nferrum_cache_entries {}\n\
+         # HELP ferrum_detection_quiet 1 while no recent analysis was flagged; see quiet_detection_window_secs.\n# TYPE ferrum_detection_quiet gauge\nferrum_detection_quiet {}\n",
+        total_analyses, total_phishing, cache_entries, detection_quiet(&state.stats).is_some() as u8,
+    );
+    outbound::OUTBOUND.render(&mut out);
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], out).into_response()
+}
+
+#[derive(Serialize)]
+struct RescanResponse {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl RescanResponse {
+    fn error(status: StatusCode, error: &str) -> (StatusCode, Json<RescanResponse>) {
+        (status, Json(RescanResponse { status: "error", error: Some(error.to_string()) }))
+    }
+}
+
+const DEFAULT_EXPORT_PAGE: usize = 1000;
+const MAX_EXPORT_PAGE: usize = 10_000;
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    // `deny` or `allow`; both when unset.
+    list: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RuleEntry {
+    list: &'static str,
+    entry: String,
+    source: String,
+}
+
+#[derive(Serialize)]
+struct RulesExport {
+    // Entry counts per list and per source, before paging.
+    deny: usize,
+    allow: usize,
+    sources: BTreeMap<String, usize>,
+    total: usize,
+    offset: usize,
+    limit: usize,
+    entries: Vec<RuleEntry>,
+}
+
+// Every deny and allow entry in effect, with the source it came from, ordered by list, source and entry.
+fn effective_rules(state: &AppState) -> Vec<RuleEntry> {
+    let mut rules = Vec::new();
+    let mut add = |list: &'static str, source: String, entries: &[String]| {
+        rules.extend(entries.iter().map(|entry| RuleEntry { list, entry: entry.clone(), source: source.clone() }));
+    };
+    for (url, entries) in state.feeds.entries() {
+        add("deny", format!("feed:{}", url), &entries);
+    }
+    add("deny", format!("file:{}", LOCAL_DENY_FILE), &live_sites(&load_local_deny_list()));
+    add("deny", "config:deny_urls".to_string(), &config().deny_urls);
+    add("allow", format!("file:{}", LOCAL_ALLOW_FILE), &state.allow_list.read().unwrap());
+    rules.sort_by(|a, b| (a.list, &a.source, &a.entry).cmp(&(b.list, &b.source, &b.entry)));
+    rules
+}
+
+// Read-only audit of the live deny/allow sets; requires an API key.
+async fn rules_export_handler(State(state): State<AppState>, headers: HeaderMap, Query(params): Query<ExportQuery>) -> Result<Json<RulesExport>, (StatusCode, &'static str)> {
+    if !is_authenticated(&headers) {
+        return Err((StatusCode::UNAUTHORIZED, "An API key is required"));
+    }
+    let mut rules = effective_rules(&state);
+    let mut sources = BTreeMap::new();
+    for rule in &rules {
+        *sources.entry(rule.source.clone()).or_insert(0) += 1;
+    }
+    let deny = rules.iter().filter(|rule| rule.list == "deny").count();
+    let allow = rules.len() - deny;
+    if let Some(list) = &params.list {
+        rules.retain(|rule| rule.list == list);
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_EXPORT_PAGE).min(MAX_EXPORT_PAGE);
+    let total = rules.len();
+    let entries = rules.into_iter().skip(params.offset).take(limit).collect();
+    Ok(Json(RulesExport { deny, allow, sources, total, offset: params.offset, limit, entries }))
+}
+
+// Starts a background re-analysis of previously seen URLs; progress and changed verdicts are in `/stats`.
+async fn rescan_handler(State(state): State<AppState>, filter: Option<Json<history::RescanFilter>>) -> (StatusCode, Json<RescanResponse>) {
+    let Some(history) = state.history.clone() else {
+        return RescanResponse::error(StatusCode::NOT_FOUND, "History is disabled; set history_db_path");
+    };
+    {
+        let mut rescan = history.rescan.lock().unwrap();
+        if rescan.as_ref().is_some_and(|status| status.running) {
+            return RescanResponse::error(StatusCode::CONFLICT, "A rescan is already running");
+        }
+        *rescan = Some(history::RescanStatus {
+            running: true,
+            started_at: history::now_secs(),
+            ..Default::default()
+        });
+    }
+    let filter = filter.map(|Json(filter)| filter).unwrap_or_default();
+    tokio::spawn(run_rescan(state, history, filter));
+    (StatusCode::ACCEPTED, Json(RescanResponse { status: "started", error: None }))
+}
+
+async fn run_rescan(state: AppState, history: Arc<history::History>, filter: history::RescanFilter) {
+    let lookup = history.clone();
+    let urls = match tokio::task::spawn_blocking(move || lookup.latest(&filter)).await {
+        Ok(Ok(urls)) => urls,
+        Ok(Err(e)) => {
+            log::warn!("Rescan failed to read history: {}", e);
+            Vec::new()
+        }
+        Err(e) => {
+            log::warn!("Rescan history lookup panicked: {}", e);
+            Vec::new()
+        }
+    };
+    history.update_rescan(|status| status.total = urls.len());
+    log::info!("Rescanning {} previously seen URLs", urls.len());
+
+    let options = AnalysisOptions::default();
+    let mut results = stream::iter(urls)
+        .map(|(url, before)| {
+            let (state, options) = (&state, &options);
+            async move { (analyze_url(&url, state, options).await, url, before) }
+        })
+        .buffer_unordered(config().batch_concurrency.max(1));
+    while let Some((result, url, before)) = results.next().await {
+        match result {
+            Ok(analysis) => {
+                record_history(&state, &analysis);
+                let after = analysis.bucket().as_str();
+                if after != before {
+                    let change = history::RescanChange { url: url.clone(), before, after: after.to_string() };
+                    history.update_rescan(|status| status.record_change(change));
+                }
+            }
+            Err(e) => log::debug!("Rescan of {} failed: {}", url, e),
+        }
+        history.update_rescan(|status| status.processed += 1);
+    }
+    history.update_rescan(|status| {
+        status.running = false;
+        status.finished_at = Some(history::now_secs());
+        log::info!("Rescan finished: {} of {} verdicts changed", status.changed_count, status.total);
+    });
+}
+
+#[derive(Deserialize)]
+struct WarmRequest {
+    urls: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct WarmResponse {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// Analyzes `urls` in the background so later requests for them are cache hits; poll `/cache/warm/{id}`.
+async fn cache_warm_handler(State(state): State<AppState>, Json(params): Json<WarmRequest>) -> (StatusCode, Json<WarmResponse>) {
+    if state.cache.is_none() {
+        let error = Some("Caching is disabled; set cache_ttl_secs".to_string());
+        return (StatusCode::OK, Json(WarmResponse { status: "skipped", job_id: None, error }));
+    }
+    let max_batch_size = config().max_batch_size;
+    if params.urls.len() > max_batch_size {
+        let error = Some(format!("Warm request exceeds {} URLs", max_batch_size));
+        return (StatusCode::PAYLOAD_TOO_LARGE, Json(WarmResponse { status: "error", job_id: None, error }));
+    }
+    let mut pending: HashMap<String, String> = HashMap::new();
+    for url in params.urls {
+        pending.entry(normalize_url(&url)).or_insert(url);
+    }
+    let id = state.warm_jobs.start(pending.len());
+    tokio::spawn(run_cache_warm(state, id, pending.into_values().collect()));
+    (StatusCode::ACCEPTED, Json(WarmResponse { status: "started", job_id: Some(id), error: None }))
+}
+
+async fn cache_warm_status_handler(State(state): State<AppState>, Path(id): Path<u64>) -> Result<Json<cache::WarmStatus>, StatusCode> {
+    state.warm_jobs.get(id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+// Warming fills the cache only: results are not counted in stats, history or webhooks.
+async fn run_cache_warm(state: AppState, id: u64, urls: Vec<String>) {
+    log::info!("Warming cache with {} URLs (job {})", urls.len(), id);
+    let options = AnalysisOptions::default();
+    let mut results = stream::iter(urls)
+        .map(|url| {
+            let (state, options) = (&state, &options);
+            async move {
+                if !is_valid_url(&url) {
+                    return false;
+                }
+                match cached_analyze_url(&url, state, options).await {
+                    Ok(analysis) => !analysis.degraded,
+                    Err(e) => {
+                        log::debug!("Cache warm of {} failed: {}", url, e);
+                        false
+                    }
+                }
+            }
+        })
+        .buffer_unordered(config().batch_concurrency.max(1));
+    while let Some(cached) = results.next().await {
+        state.warm_jobs.update(id, |status| {
+            status.processed += 1;
+            match cached {
+                true => status.cached += 1,
+                false => status.failed += 1,
+            }
+        });
+    }
+    state.warm_jobs.update(id, |status| {
+        status.running = false;
+        status.finished_at = Some(history::now_secs());
+        log::info!("Cache warm job {} finished: {} of {} cached", id, status.cached, status.total);
+    });
+}
+
+// End-to-end smoke test of the scoring pipeline with local-only checks.
+// Fails on a panic or if the known shortener is not detected.
+async fn run_self_test(state: &AppState) -> Result<(), String> {
+    const CASES: [(&str, &str); 3] = [
+        ("known-good", "https://www.example.com/"),
+        ("known shortener", "https://bit.ly/abc123"),
+        ("synthetic phishing", "http://192.0.2.10/paypal.com/secure-login"),
+    ];
+    for (name, url) in CASES {
+        let state = state.clone();
+        let options = AnalysisOptions {
+            local_only: true,
+            ..Default::default()
+        };
+        let analysis = tokio::spawn(async move { analyze_url(url, &state, &options).await.map_err(|e| e.to_string()) })
+            .await
+            .map_err(|e| format!("self-test '{}' panicked: {}", name, e))?
+            .map_err(|e| format!("self-test '{}' failed: {}", name, e))?;
+        log::info!(
+            "Self-test '{}': {} scored {} ({})",
+            name,
+            url,
+            analysis.risk_score.unwrap_or_default(),
+            analysis.bucket().as_str()
         );
+        if name == "known shortener" && !analysis.is_shortened {
+            return Err(format!("self-test '{}': {} was not detected as a shortener", name, url));
+        }
+    }
+    Ok(())
+}
+
+fn configure_server(
+    builder: &mut hyper_util::server::conn::auto::Builder<hyper_util::rt::TokioExecutor>,
+    server: &config::ServerConfig,
+) {
+    if !server.http2 {
+        *builder = builder.clone().http1_only();
+    } else if !server.http1 {
+        *builder = builder.clone().http2_only();
     }
+    builder.http1().keep_alive(server.keep_alive);
+    builder
+        .http2()
+        .timer(hyper_util::rt::TokioTimer::new())
+        .max_concurrent_streams(server.http2_max_concurrent_streams)
+        .keep_alive_interval(server.http2_keep_alive_interval_secs.map(Duration::from_secs))
+        .keep_alive_timeout(Duration::from_secs(server.http2_keep_alive_timeout_secs));
 
-    match analyze_url(&params.url, phishing_list).await { 
-        Ok(analysis) => (
-            StatusCode::OK,
-            Json(AnalysisResponse {
-                url: params.url.clone(),
-                status: "success".to_string(),
-                data: Some(analysis),
-                error: None,
-            }),
-        ),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(AnalysisResponse {
-                url: params.url.clone(),
-                status: "error".to_string(),
-                data: None,
-                error: Some(format!("Analysis failed: {}", e)),
-            }),
-        ),
+    let mut protocols = Vec::new();
+    if builder.is_http1_available() {
+        protocols.push(format!("HTTP/1.1 (keep-alive {})", if server.keep_alive { "on" } else { "off" }));
+    }
+    if builder.is_http2_available() {
+        let pings = match server.http2_keep_alive_interval_secs {
+            Some(secs) => format!("every {}s", secs),
+            None => "off".to_string(),
+        };
+        protocols.push(format!("HTTP/2 (max {} streams, keep-alive pings {})", server.http2_max_concurrent_streams, pings));
+    }
+    log::info!("Server protocols: {}", protocols.join(", "));
+}
+
+const QUIET_DETECTION_INTERVAL: Duration = Duration::from_secs(60);
+
+// In-flight requests get this long to finish once a shutdown signal arrives.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+// Stops the server gracefully on Ctrl-C or SIGTERM, so shutdown work such as saving the cache runs.
+async fn shutdown_on_signal(handle: axum_server::Handle) {
+    let ctrl_c = tokio::signal::ctrl_c();
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => { signal.recv().await; }
+            Err(e) => {
+                log::warn!("Cannot listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
     }
+    log::info!("Shutting down");
+    handle.graceful_shutdown(Some(SHUTDOWN_GRACE));
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     env_logger::init();
-    let phishing_list = Arc::new(fetch_phishing_list().await.map(|v| Ok(v)).unwrap_or_else(|e| Err(e.to_string())));
-    let app = Router::new()
-        .route("/analyze", get(analyze_url_handler))
-        .with_state(phishing_list.clone());
+    let config_path = std::env::var("FERRUM_CONFIG").unwrap_or_else(|_| "config.json".to_string());
+    config::init(config::Config::load(&config_path)?);
+    config().validate()?;
+    log::info!("Loaded {} custom feature rules", rules::custom_features().len());
+    validate_composite_rules()?;
+    validate_detection_rules()?;
+    log::info!("Loaded {} detection rules", rules::detection_rules().len());
+    validate_suppressed_signals()?;
+    check_local_deny_file()?;
+    // Offline, no client exists for anything to reach the network through.
+    let client = outbound_client(config())?;
+    if config().offline {
+        log::info!("Offline mode: no outbound connections; unavailable checks: {}", config().network_checks().join(", "));
+    }
+    let feeds = Arc::new(feeds::FeedSet::new(client.clone()));
+    let phishing_list = feeds.refresh().await;
+    match &phishing_list {
+        Ok(_) if config().offline => {}
+        Ok(list) => log::info!("Deny list ready: {} entries from {} feeds", list.len(), config().feeds.len()),
+        Err(e) => log::warn!("No phishing feed could be loaded: {}", e),
+    }
+    let phishing_list = Arc::new(RwLock::new(Arc::new(phishing_list)));
+    let geoip = config().geoip_db_path.as_deref().and_then(geoip::GeoIp::open).map(Arc::new);
+    let stats = Arc::new(stats::Stats::new(config().stats_window));
+    let cache = (config().cache_ttl_secs > 0).then(|| {
+        let (ttl, trust_window) = (Duration::from_secs(config().cache_ttl_secs), Duration::from_secs(config().trusted_cache_secs));
+        Arc::new(cache::AnalysisCache::new(config().cache_max_entries, ttl, trust_window))
+    });
+    // A missing file is a first start; an unreadable one is dropped rather than failing startup.
+    if let (Some(cache), Some(path)) = (&cache, &config().cache_file)
+        && std::path::Path::new(path).exists()
+    {
+        let generation = phishing_list.read().unwrap().as_ref().as_ref().ok().map(|list| list.generation.clone());
+        match cache.load(path, generation.as_deref()) {
+            Ok(loaded) => log::info!("Reloaded {} cache entries from {}", loaded, path),
+            Err(e) => log::warn!("Ignoring cache file {}", e),
+        }
+    }
+    if let Some(cache) = cache.clone() {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config().cache_sweep_interval_secs));
+            loop {
+                interval.tick().await;
+                let removed = cache.sweep();
+                if removed > 0 {
+                    log::debug!("Swept {} expired cache entries", removed);
+                }
+            }
+        });
+    }
+    // Checked once a minute so the alert fires even when nothing polls `/readyz`; logged on change only.
+    if config().quiet_detection_window_secs > 0 {
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(QUIET_DETECTION_INTERVAL);
+            let mut was_quiet = false;
+            loop {
+                interval.tick().await;
+                let quiet = detection_quiet(&stats);
+                match quiet {
+                    Some(count) if !was_quiet => log::error!(
+                        "Detection quiet: none of the last {} analyses in {}s was flagged; check the feeds and heuristics",
+                        count, config().quiet_detection_window_secs,
+                    ),
+                    None if was_quiet => log::info!("Detection no longer quiet"),
+                    _ => {}
+                }
+                was_quiet = quiet.is_some();
+            }
+        });
+    }
+    if config().feed_refresh_interval_secs > 0 && !config().feeds.is_empty() && !config().offline {
+        let (feeds, phishing_list, cache) = (feeds.clone(), phishing_list.clone(), cache.clone());
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config().feed_refresh_interval_secs));
+            // The first tick fires immediately and the feeds were just loaded.
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let list = Arc::new(feeds.refresh().await);
+                let generation = |list: &Result<feeds::DenyList, String>| list.as_ref().ok().map(|l| l.generation.clone());
+                let changed = generation(&list) != generation(&phishing_list.read().unwrap());
+                // Cached verdicts were computed against the old list; trusted low-risk ones
+                // stand unless the new list flags them.
+                if changed && let Some(cache) = &cache {
+                    let kept = match &*list {
+                        Ok(deny) => cache.invalidate_untrusted(|key| deny.check(key.trim_start_matches(DOMAIN_SCOPE_KEY)).is_none()),
+                        Err(_) => cache.invalidate_untrusted(|_| true),
+                    };
+                    log::info!("Deny list changed; kept {} trusted cache entries", kept);
+                }
+                *phishing_list.write().unwrap() = list;
+            }
+        });
+    }
+    let ct = client.clone().filter(|_| config().ct_check).map(|client| {
+        let timeout = Duration::from_secs(config().ct_timeout_secs);
+        Arc::new(ct::CtLookup::new(client, timeout, Duration::from_secs(config().ct_cache_ttl_secs)))
+    });
+    let email_auth = (config().email_auth_check && !config().offline)
+        .then(|| match mailauth::resolver(config().email_auth_resolver.as_deref()) {
+            Some(resolver) => Some(resolver),
+            None => {
+                log::warn!("email_auth_check is on but no DNS resolver is configured or in /etc/resolv.conf; skipping it");
+                None
+            }
+        })
+        .flatten()
+        .map(|resolver| {
+            let (timeout, ttl) = (Duration::from_secs(config().email_auth_timeout_secs), Duration::from_secs(config().email_auth_cache_ttl_secs));
+            Arc::new(mailauth::EmailAuthLookup::new(resolver, timeout, ttl))
+        });
+    let favicon = (config().favicon_check && !config().offline).then(|| {
+        let (timeout, ttl) = (Duration::from_secs(config().request_timeout_secs), Duration::from_secs(config().favicon_cache_ttl_secs));
+        Arc::new(favicon::FaviconLookup::new(timeout, config().favicon_max_bytes, ttl))
+    });
+    let top_sites = config().top_sites_path.as_deref().and_then(topsites::TopSites::open).map(Arc::new);
+    let history = config().history_db_path.as_deref().and_then(history::History::open).map(Arc::new);
+    let reputation = Arc::new(client.as_ref().map_or_else(Vec::new, |client| reputation::providers(client, &config().reputation_providers)));
+    let webhook = config().webhook_url.clone().zip(client).map(|(url, client)| {
+        Arc::new(webhook::Webhook::new(client, url, Duration::from_secs(config().webhook_cooldown_secs)))
+    });
+    let allow_list = Arc::new(RwLock::new(load_local_allow_list()));
+    #[cfg(unix)]
+    {
+        let allow_list = allow_list.clone();
+        let cache = cache.clone();
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                let entries = load_local_allow_list();
+                log::info!("Reloaded local allow list: {} entries", entries.len());
+                *allow_list.write().unwrap() = entries;
+                if let Ok(count) = reload_local_deny_list() {
+                    log::info!("Reloaded local deny list: {} entries", count);
+                }
+                // Cached verdicts may predate the reloaded lists.
+                if let Some(cache) = &cache {
+                    cache.clear();
+                }
+            }
+        });
+    }
+    let warm_jobs = Arc::new(cache::WarmJobs::default());
+    let persisted_cache = cache.clone();
+    let state = AppState { phishing_list, feeds, geoip, stats, cache, warm_jobs, ct, email_auth, favicon, top_sites, history, reputation, webhook, allow_list };
+    if config().self_test || std::env::args().any(|arg| arg == "--self-test") {
+        run_self_test(&state).await?;
+        log::info!("Self-test passed");
+    }
+    // `rusty scan ...` analyzes a file of URLs and exits instead of serving.
+    if std::env::args().nth(1).as_deref() == Some("scan") {
+        let args = scan::ScanArgs::parse(&std::env::args().skip(2).collect::<Vec<_>>())?;
+        return scan::run(&state, args).await;
+    }
+    let app = router(state);
 
     let addr: SocketAddr = "127.0.0.1:3000".parse()?;
     println!("URL Analysis Service running on http://{}", addr);
 
-    axum_server::bind(addr)
-        .serve(app.into_make_service())
-        .await?;
+    let handle = axum_server::Handle::new();
+    tokio::spawn(shutdown_on_signal(handle.clone()));
+    let mut server = axum_server::bind(addr).handle(handle);
+    configure_server(server.http_builder(), &config().server);
+    server.serve(app.into_make_service()).await?;
+    if let (Some(cache), Some(path)) = (persisted_cache, &config().cache_file) {
+        match cache.save(path) {
+            Ok(saved) => log::info!("Saved {} cache entries to {}", saved, path),
+            Err(e) => log::warn!("Failed to save cache file {}", e),
+        }
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Answers the first ClientHello with a TLS 1.0 ServerHello, as a server that speaks nothing newer.
+    pub(crate) async fn tls10_only_server() -> (SocketAddr, tokio::task::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut hello = vec![0; 4096];
+            let len = stream.read(&mut hello).await.unwrap();
+            hello.truncate(len);
+            let mut server_hello = vec![0x16, 0x03, 0x01, 0x00, 0x2a, 0x02, 0x00, 0x00, 0x26, 0x03, 0x01];
+            server_hello.extend_from_slice(&[0x42; 32]);
+            // No session id, TLS_RSA_WITH_AES_128_CBC_SHA, no compression.
+            server_hello.extend_from_slice(&[0x00, 0x00, 0x2f, 0x00]);
+            let _ = stream.write_all(&server_hello).await;
+            let _ = stream.read(&mut [0; 1024]).await;
+            hello
+        });
+        (addr, server)
+    }
+
+    // Versions listed in a ClientHello's supported_versions extension.
+    fn offered_versions(hello: &[u8]) -> Vec<u16> {
+        let word = |at: usize| u16::from_be_bytes([hello[at], hello[at + 1]]) as usize;
+        let mut at = 5 + 4 + 2 + 32;
+        at += 1 + hello[at] as usize;
+        at += 2 + word(at);
+        at += 1 + hello[at] as usize;
+        let end = at + 2 + word(at);
+        at += 2;
+        while at < end {
+            let (kind, len) = (word(at), word(at + 2));
+            if kind == 0x002b {
+                return hello[at + 5..at + 4 + len].chunks(2).map(|v| u16::from_be_bytes([v[0], v[1]])).collect();
+            }
+            at += 4 + len;
+        }
+        Vec::new()
+    }
+
+    #[tokio::test]
+    async fn outbound_clients_refuse_tls10() {
+        for min_tls_version in [config::MinTlsVersion::Tls12, config::MinTlsVersion::Tls13] {
+            let config = config::Config { min_tls_version, ..Default::default() };
+            let client = http_client_builder(&config).build().unwrap();
+            let (addr, server) = tls10_only_server().await;
+            let result = client.get(format!("https://{}/", addr)).send().await;
+            assert!(result.is_err_and(|e| e.is_connect()), "TLS 1.0 accepted with {:?}", min_tls_version);
+            // The handshake was attempted, so the refusal is the version check and not a dead socket.
+            let hello = server.await.unwrap();
+            assert_eq!(hello.first(), Some(&0x16));
+            let lowest = offered_versions(&hello).into_iter().min();
+            let expected = match min_tls_version {
+                config::MinTlsVersion::Tls12 => 0x0303,
+                config::MinTlsVersion::Tls13 => 0x0304,
+            };
+            assert_eq!(lowest, Some(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn unroutable_host_fails_within_connect_timeout() {
+        let config = config::Config { connect_timeout_secs: 1, request_timeout_secs: 30, ..Default::default() };
+        let client = http_client_builder(&config).build().unwrap();
+        let start = Instant::now();
+        // Some sandboxes reset the connection instead of dropping it; either way it must not wait
+        // out the request timeout.
+        assert!(client.get("http://10.255.255.1/").send().await.is_err());
+        assert!(start.elapsed() < Duration::from_secs(5), "took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn offline_builds_no_outbound_client() {
+        let offline = config::Config { offline: true, ..Default::default() };
+        assert!(outbound_client(&offline).unwrap().is_none());
+        assert!(outbound_client(&config::Config::default()).unwrap().is_some());
+    }
+
+    #[derive(Serialize)]
+    struct Scored {
+        #[serde(serialize_with = "serialize_optional_score")]
+        score: Option<f32>,
+    }
+
+    #[test]
+    fn scores_serialize_at_fixed_precision() {
+        let json = |score| serde_json::to_string(&Scored { score }).unwrap();
+        assert_eq!(json(Some(0.6)), r#"{"score":0.6}"#);
+        assert_eq!(json(Some(0.1 + 0.2)), r#"{"score":0.3}"#);
+        assert_eq!(json(Some(0.12345)), r#"{"score":0.123}"#);
+        assert_eq!(json(Some(0.9996)), r#"{"score":1.0}"#);
+        assert_eq!(json(None), r#"{"score":null}"#);
+    }
+
+    #[test]
+    fn phishing_hits_never_score_below_the_floor() {
+        let features = HashMap::new();
+        let score = calculate_risk_score(None, true, &features, None, &[], None);
+        assert_eq!(score, config().phishing_score_floor);
+        assert_eq!(RiskBucket::from_score(score), RiskBucket::High);
+        assert_eq!(calculate_risk_score(None, false, &features, None, &[], None), 0.0);
+        // Signals on top of the floor still add up, within the ceiling.
+        let features = HashMap::from([("has_ip_address".to_string(), true), ("has_at_symbol".to_string(), true)]);
+        assert_eq!(calculate_risk_score(None, true, &features, None, &[], None), config().phishing_score_ceiling);
+    }
+
+    // Every URL in a class canonicalizes to the class's first member.
+    fn assert_equivalent(classes: &[&[&str]], strip_trailing_slash: bool) {
+        for class in classes {
+            for url in *class {
+                assert_eq!(canonicalize_path(url, strip_trailing_slash).as_deref(), Some(class[0]), "{}", url);
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_paths_share_equivalence_classes() {
+        assert_equivalent(
+            &[
+                &["https://a.com/", "https://a.com", "https://a.com//"],
+                &["https://a.com/x/y", "https://a.com//x///y", "https://a.com/x//y"],
+                &["https://a.com/x/", "https://a.com/x//"],
+                &["https://a.com/x?q=a//b", "https://a.com//x?q=a//b"],
+            ],
+            false,
+        );
+        assert_equivalent(&[&["https://a.com/x", "https://a.com/x/", "https://a.com//x//"], &["https://a.com/", "https://a.com"]], true);
+    }
+
+    #[test]
+    fn canonical_paths_keep_distinct_urls_apart() {
+        // Case and the trailing slash (unless stripped) can matter to the server.
+        assert_ne!(canonicalize_path("https://a.com/Login", false), canonicalize_path("https://a.com/login", false));
+        assert_ne!(canonicalize_path("https://a.com/x/", false), canonicalize_path("https://a.com/x", false));
+        assert_eq!(canonicalize_path("mailto:a@b.com", false), None);
+    }
+
+    #[tokio::test]
+    async fn batch_results_keep_input_order() {
+        let urls: Vec<String> = ["slow.com", "fast.com", "medium.com", "https://slow.com", "instant.com"].map(String::from).to_vec();
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let analyze = |url: String| {
+            let started = started.clone();
+            async move {
+                started.lock().unwrap().push(url.clone());
+                let delay = match url.as_str() {
+                    "slow.com" => 60,
+                    "medium.com" => 30,
+                    _ => 0,
+                };
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                AnalysisResponse::error(&url, format!("analyzed {}", url))
+            }
+        };
+        let (results, unique) = analyze_in_input_order(&urls, 4, analyze).await;
+        assert_eq!(unique, 4);
+        assert_eq!(started.lock().unwrap().len(), 4);
+        let got: Vec<(&str, Option<&str>)> = results.iter().map(|r| (r.url.as_str(), r.error.as_deref())).collect();
+        assert_eq!(
+            got,
+            [
+                ("slow.com", Some("analyzed slow.com")),
+                ("fast.com", Some("analyzed fast.com")),
+                ("medium.com", Some("analyzed medium.com")),
+                // Same normalized URL as the first input: analyzed once, reported under its own spelling.
+                ("https://slow.com", Some("analyzed slow.com")),
+                ("instant.com", Some("analyzed instant.com")),
+            ]
+        );
+    }
+
+    fn download(url: &str) -> Option<(String, Option<String>)> {
+        dangerous_download(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn dangerous_download_sees_double_extensions_and_ignores_queries() {
+        assert_eq!(download("https://a.com/docs/file.pdf.exe"), Some(("exe".to_string(), Some("pdf".to_string()))));
+        assert_eq!(download("https://a.com/installer.apk?x=1"), Some(("apk".to_string(), None)));
+        assert_eq!(download("https://a.com/invoice.pdf%2Eexe"), Some(("exe".to_string(), Some("pdf".to_string()))));
+        assert_eq!(download("https://a.com/report.pdf?file=x.exe"), None);
+        assert_eq!(download("https://a.com/exe"), None);
+    }
+
+    #[test]
+    fn known_shortener_reports_exact_and_subdomain_matches() {
+        let exact = known_shortener("https://bit.ly/3xYz").unwrap();
+        assert_eq!((exact.domain, exact.kind), ("bit.ly", ShortenerMatchKind::Exact));
+        let subdomain = known_shortener("https://foo.bit.ly/3xYz").unwrap();
+        assert_eq!((subdomain.domain, subdomain.kind), ("bit.ly", ShortenerMatchKind::Subdomain));
+        let mixed_case = known_shortener("https://dwarfurl.com/a").unwrap();
+        assert_eq!((mixed_case.domain, mixed_case.kind), ("DwarfURL.com", ShortenerMatchKind::Exact));
+        assert!(known_shortener("https://notbit.ly/3xYz").is_none());
+    }
+
+    #[test]
+    fn known_shortener_prefers_the_most_specific_entry() {
+        // `link.zip.net` is an entry of its own, and more specific than any shorter suffix.
+        let nested = known_shortener("https://a.link.zip.net/x").unwrap();
+        assert_eq!((nested.domain, nested.kind), ("link.zip.net", ShortenerMatchKind::Subdomain));
+        let exact = known_shortener("https://link.zip.net/x").unwrap();
+        assert_eq!(exact.kind, ShortenerMatchKind::Exact);
+    }
+
+    #[test]
+    fn forty_query_params_are_excessive() {
+        let params: Vec<String> = (0..40).map(|i| format!("p{}=v{}", i, i)).collect();
+        let url = format!("https://shop.example/item?{}", params.join("&"));
+        assert_eq!(query_size(&Url::parse(&url).unwrap()), (40, url.split_once('?').unwrap().1.len()));
+        assert_eq!(extract_url_features(&url).get("excessive_query"), Some(&true));
+        // A typical analytics link stays under the default thresholds.
+        let utm = "https://shop.example/item?utm_source=news&utm_medium=email&utm_campaign=spring&utm_content=hero&gclid=abc123";
+        assert_eq!(extract_url_features(utm).get("excessive_query"), Some(&false));
+    }
+
+    #[test]
+    fn missing_deny_file_fails_startup_but_malformed_keeps_last_good_list() {
+        let dir = std::env::temp_dir().join(format!("ferrum-deny-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("caught.json");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let mut list = LocalDenyList::default();
+        let err = open_local_deny_file(&mut list, path, false).unwrap_err();
+        assert!(err.contains("not found"), "{}", err);
+        assert!(open_local_deny_file(&mut list, path, true).is_ok());
+        assert!(list.entries.is_empty());
+
+        fs::write(path, r#"{"flagged_sites": ["evil.example"]}"#).unwrap();
+        open_local_deny_file(&mut list, path, false).unwrap();
+        assert_eq!(list.entries.len(), 1);
+
+        fs::write(path, "{\"flagged_sites\": [\n  \"evil.example\",\n]}").unwrap();
+        let err = list.reload(path).unwrap_err();
+        assert!(err.contains("Malformed") && err.contains("line 3"), "{}", err);
+        assert_eq!(list.live(chrono::Utc::now())[0].site, "evil.example");
+        assert_eq!(list.last_error.as_deref(), Some(err.as_str()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shortener_homepage_is_not_a_short_link() {
+        let no_feed = Err("no feed".to_string());
+        let homepage = checking_url_enhanced("https://bit.ly/", &no_feed, None, &mut Timings::new(false)).await;
+        assert!(homepage.shortener.is_some());
+        assert!(!homepage.is_shortened);
+        assert_eq!(homepage.short_code, None);
+
+        let link = checking_url_enhanced("https://bit.ly/abc123", &no_feed, None, &mut Timings::new(false)).await;
+        assert!(link.is_shortened);
+        assert_eq!(link.short_code.as_deref(), Some("abc123"));
+    }
+
+    #[test]
+    fn percent_encoded_host_matches_the_deny_list() {
+        let url = "http://%65%76%69%6c.com/";
+        assert_eq!(encoded_host(url), Some(("%65%76%69%6c.com".to_string(), "evil.com".to_string())));
+        let entries = ["evil.com".to_string()];
+        assert_eq!(check_deny_entries(&normalize_url(url), entries.iter()), Some(MatchTier::Domain));
+        assert_eq!(encoded_host("http://evil.com/%65"), None);
+    }
+
+    #[test]
+    fn expired_deny_entries_stop_matching_without_a_reload() {
+        let json = r#"{"flagged_sites": [
+            {"site": "past.example", "expires_at": "2020-01-01T00:00:00Z"},
+            {"site": "future.example", "expires_at": "2999-01-01T00:00:00Z"},
+            "permanent.example"
+        ]}"#;
+        let list = LocalDenyList { entries: serde_json::from_str::<PhishingList>(json).unwrap().flagged_sites, last_error: None };
+        let live = list.live(chrono::Utc::now());
+        let sites: Vec<&str> = live.iter().map(|e| e.site.as_str()).collect();
+        assert_eq!(sites, ["future.example", "permanent.example"]);
+
+        let future = "2999-01-01T00:00:00Z".parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+        assert_eq!(deny_expiry("https://future.example/", live.clone()), Some(future));
+        assert_eq!(deny_expiry("https://past.example/", live.clone()), None);
+        assert_eq!(deny_expiry("https://permanent.example/", live), None);
+
+        // The same list, seen after the future entry's time has passed.
+        let later = future + chrono::Duration::seconds(1);
+        assert_eq!(list.live(later).len(), 1);
+    }
+
+    #[test]
+    fn combining_accent_in_host_is_normalized_and_flagged() {
+        let url = "https://cafe\u{301}.com/";
+        let (raw, normalized, replaced) = non_nfc_host(url).unwrap();
+        assert_eq!(raw, "cafe\u{301}.com");
+        assert_eq!(normalized, "caf\u{e9}.com");
+        assert_eq!(replaced, ["U+0301"]);
+        assert_eq!(non_nfc_host("https://caf\u{e9}.com/"), None);
+
+        let entries = ["caf\u{e9}.com".to_string()];
+        assert_eq!(check_deny_entries(url, entries.iter()), Some(MatchTier::Domain));
+    }
+
+    #[test]
+    fn internal_domains_drop_structural_signals_but_keep_phishing_matches() {
+        let internal = ["corp".to_string(), "intranet.example.com".to_string()];
+        assert_eq!(internal_domain("https://build-01.eu.wiki.corp/", &internal), Some("corp"));
+        assert_eq!(internal_domain("https://a.intranet.example.com/", &internal), Some("intranet.example.com"));
+        assert_eq!(internal_domain("https://corp.example.com/", &internal), None);
+        assert_eq!(internal_domain("https://intranet.example.com.evil.zip/", &internal), None);
+
+        let mut features = extract_url_features("https://build-01.eu.wiki.corp/");
+        features.insert("suspicious_tld".to_string(), true);
+        assert!(calculate_risk_score(None, false, &features, Some(1.0), &[], None) > 0.0);
+        let mut analysis = HashMap::from([
+            ("dash_in_domain".to_string(), "Suspicious feature detected".to_string()),
+            ("suspicious_tld".to_string(), "Suspicious feature detected".to_string()),
+        ]);
+        let dropped = drop_structural_signals(&mut features, &mut analysis);
+        assert!(dropped.contains(&"dash_in_domain") && dropped.contains(&"suspicious_tld"), "{:?}", dropped);
+        assert!(analysis.is_empty());
+        assert_eq!(calculate_risk_score(None, false, &features, Some(1.0), &[], None), 0.0);
+        assert!(calculate_risk_score(None, true, &features, None, &[], None) >= config().phishing_score_floor);
+    }
+
+    pub(crate) fn test_state() -> AppState {
+        AppState {
+            phishing_list: Arc::new(RwLock::new(Arc::new(Err("no feed".to_string())))),
+            feeds: Arc::new(feeds::FeedSet::new(None)),
+            geoip: None,
+            stats: Arc::new(stats::Stats::new(16)),
+            cache: None,
+            warm_jobs: Arc::new(cache::WarmJobs::default()),
+            ct: None,
+            email_auth: None,
+            favicon: None,
+            top_sites: None,
+            history: None,
+            reputation: Arc::new(Vec::new()),
+            webhook: None,
+            allow_list: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    #[tokio::test]
+    async fn wrong_method_gets_405_with_allow_header_and_error_envelope() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router(test_state())).await });
+        let client = Client::new();
+        for (request, allow) in [
+            (client.post(format!("{}/analyze?url=https://example.com", base)), "GET,HEAD"),
+            (client.get(format!("{}/analyze/batch", base)), "POST"),
+        ] {
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(response.headers()[axum::http::header::ALLOW].to_str().unwrap().replace(' ', ""), allow);
+            let body: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(body["status"], "error");
+            assert!(body["error"].as_str().unwrap().contains("not allowed"), "{}", body);
+        }
+    }
+
+    #[test]
+    fn conflicting_and_invalid_options_name_the_offending_option() {
+        let parse = |query: &str| {
+            let uri: Uri = format!("/analyze?url=https://example.com/&{}", query).parse().unwrap();
+            AnalyzeOptions::parse(Query::try_from_uri(&uri)).map(|_| ()).map_err(|(_, e)| e)
+        };
+        assert_eq!(parse("format=cef"), Ok(()));
+        assert_eq!(parse("compact=true&timings=true&explain=text"), Ok(()));
+        for (query, option) in [
+            ("format=cef&compact=true", "compact"),
+            ("format=cef&explain=text", "explain"),
+            ("format=cef&features=raw", "features"),
+            ("format=cef&timings=true", "timings"),
+        ] {
+            let error = parse(query).unwrap_err();
+            assert_eq!(error, format!("Conflicting options: {} cannot be combined with format=cef", option));
+        }
+        // compact=false asks for nothing CEF cannot carry.
+        assert_eq!(parse("format=cef&compact=false"), Ok(()));
+        assert_eq!(parse("profiles=nope").unwrap_err(), "Unknown risk profile: nope");
+        let error = parse("scope=host").unwrap_err();
+        assert!(error.starts_with("Invalid query: scope: unknown variant `host`"), "{}", error);
+    }
+
+    #[test]
+    fn punycode_homograph_is_decoded_and_matches_either_deny_form() {
+        let url = "https://xn--80ak6aa92e.com/login";
+        let unicode = "\u{430}\u{440}\u{440}\u{4cf}\u{435}.com";
+        assert_eq!(idn_host(url), Some(("xn--80ak6aa92e.com".to_string(), unicode.to_string())));
+        assert_eq!(punycode_brand_spoof(url), Some((unicode.to_string(), "apple".to_string())));
+        for entry in ["xn--80ak6aa92e.com", unicode] {
+            let entries = [entry.to_string()];
+            assert_eq!(check_deny_entries(url, entries.iter()), Some(MatchTier::Domain), "{}", entry);
+            let typed = format!("https://{}/login", unicode);
+            assert_eq!(check_deny_entries(&typed, entries.iter()), Some(MatchTier::Domain), "{}", entry);
+        }
+        assert_eq!(punycode_brand_spoof("https://xn--mnchen-3ya.de/"), None);
+    }
+
+    #[test]
+    fn duplicated_url_param_with_different_values_is_pollution() {
+        let url = "https://a.com/r?url=https://good.com&url=https://evil.com";
+        assert_eq!(polluted_params(url), ["url"]);
+        assert!(polluted_params("https://a.com/r?url=x&url=x").is_empty());
+        assert_eq!(
+            redirect_param_targets(url),
+            [("url".to_string(), "https://good.com/".to_string()), ("url".to_string(), "https://evil.com/".to_string())]
+        );
+    }
+}
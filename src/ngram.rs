@@ -0,0 +1,68 @@
+use crate::config::config;
+
+// log10 frequency of each letter pair (row: first letter, column: second) among the words of a
+// general English corpus, smoothed so unseen pairs sit at -6.
+#[rustfmt::skip]
+#[allow(clippy::approx_constant)]
+const BIGRAM_LOG_FREQ: [[f32; 26]; 26] = [
+    /* a */ [-3.01, -2.46, -2.24, -2.31, -2.97, -2.94, -2.60, -3.40, -2.58, -3.70, -2.84, -2.07, -2.38, -2.01, -3.59, -2.53, -3.92, -2.04, -2.25, -1.96, -2.72, -2.83, -3.22, -3.23, -2.90, -3.58],
+    /* b */ [-2.56, -3.06, -3.00, -3.02, -2.54, -3.13, -3.50, -3.90, -2.72, -3.14, -3.78, -2.53, -3.44, -3.77, -2.73, -3.42, -4.20, -2.90, -2.94, -3.47, -2.63, -4.01, -4.05, -3.56, -3.19, -3.80],
+    /* c */ [-2.27, -3.07, -2.74, -2.98, -2.27, -3.03, -3.74, -2.23, -2.75, -3.98, -2.43, -2.51, -3.13, -3.61, -2.03, -3.18, -4.01, -2.64, -2.98, -2.33, -2.80, -3.55, -3.90, -3.87, -3.35, -3.85],
+    /* d */ [-2.45, -2.89, -2.94, -2.71, -2.00, -2.97, -3.47, -3.46, -2.30, -3.78, -3.52, -2.97, -3.27, -3.35, -2.60, -3.33, -4.00, -2.87, -2.77, -3.20, -2.84, -3.62, -3.63, -3.94, -3.41, -4.10],
+    /* e */ [-2.24, -2.73, -2.15, -2.03, -2.50, -2.49, -2.78, -3.24, -2.82, -3.52, -3.26, -2.28, -2.43, -1.93, -3.03, -2.57, -3.10, -1.74, -1.84, -2.13, -3.20, -2.64, -2.94, -2.41, -2.75, -3.60],
+    /* f */ [-2.66, -3.11, -2.91, -3.03, -2.62, -2.64, -3.81, -4.10, -2.36, -4.30, -4.12, -2.94, -3.67, -3.65, -2.59, -3.65, -4.27, -2.87, -2.99, -3.10, -2.99, -4.06, -3.93, -4.11, -3.35, -4.30],
+    /* g */ [-2.90, -3.57, -3.23, -3.42, -2.25, -3.62, -3.29, -3.06, -2.74, -4.04, -4.14, -3.01, -3.32, -2.87, -2.98, -3.25, -4.16, -2.75, -2.87, -3.10, -3.00, -3.76, -3.82, -4.16, -3.77, -3.81],
+    /* h */ [-2.34, -3.72, -3.61, -3.59, -2.33, -3.84, -3.91, -3.91, -2.64, -4.20, -3.67, -3.41, -3.38, -3.48, -2.59, -3.65, -4.28, -2.99, -3.39, -2.95, -3.08, -4.05, -3.71, -4.37, -3.58, -4.22],
+    /* i */ [-2.59, -2.55, -2.31, -2.56, -2.53, -2.67, -2.53, -3.81, -3.62, -3.68, -3.17, -2.31, -2.47, -1.73, -2.20, -2.72, -3.80, -2.63, -2.24, -2.15, -3.55, -2.74, -3.60, -3.02, -3.83, -2.82],
+    /* j */ [-2.99, -4.08, -3.82, -3.72, -3.07, -4.08, -4.18, -4.15, -3.47, -4.14, -4.03, -4.06, -3.86, -3.95, -3.22, -3.81, -4.22, -4.05, -3.35, -3.70, -3.37, -4.09, -4.06, -4.37, -4.30, -4.29],
+    /* k */ [-2.93, -3.51, -3.53, -3.56, -2.40, -3.68, -3.56, -3.59, -2.83, -4.12, -3.88, -3.40, -3.58, -3.55, -3.07, -3.60, -4.19, -3.44, -2.96, -3.41, -3.36, -4.01, -3.63, -4.24, -3.70, -4.19],
+    /* l */ [-2.26, -3.30, -3.20, -2.77, -1.99, -3.28, -3.46, -3.72, -2.04, -3.93, -3.46, -2.33, -3.39, -3.49, -2.26, -3.21, -4.19, -3.55, -2.68, -2.63, -2.80, -3.31, -3.72, -3.89, -2.68, -3.78],
+    /* m */ [-2.17, -2.95, -3.29, -3.26, -2.18, -3.68, -3.84, -3.94, -2.45, -4.11, -3.62, -3.06, -2.90, -3.61, -2.50, -2.42, -4.16, -3.67, -2.91, -3.42, -2.98, -3.80, -3.95, -3.85, -3.49, -4.21],
+    /* n */ [-2.37, -3.32, -2.38, -2.25, -2.22, -2.74, -2.07, -3.37, -2.41, -3.67, -2.99, -3.13, -3.26, -2.79, -2.43, -3.07, -3.89, -3.31, -2.24, -2.05, -2.82, -3.03, -3.57, -3.89, -3.30, -3.69],
+    /* o */ [-3.03, -2.81, -2.45, -2.46, -3.29, -2.89, -2.87, -3.48, -3.05, -3.58, -3.04, -2.45, -2.40, -1.88, -2.67, -2.49, -4.19, -2.05, -2.51, -2.55, -2.44, -2.74, -2.70, -3.34, -3.54, -3.69],
+    /* p */ [-2.30, -3.69, -3.21, -3.29, -2.30, -3.48, -3.34, -3.02, -2.68, -4.24, -3.29, -2.58, -3.46, -3.41, -2.48, -2.79, -4.11, -2.32, -2.86, -2.58, -2.86, -3.84, -3.68, -4.00, -3.08, -3.88],
+    /* q */ [-4.23, -4.33, -4.14, -4.18, -4.08, -4.26, -4.25, -4.37, -4.09, -4.43, -4.40, -3.87, -4.18, -4.15, -4.25, -4.20, -4.22, -3.94, -4.05, -4.14, -2.80, -4.24, -4.29, -4.28, -4.34, -4.37],
+    /* r */ [-2.13, -3.24, -2.73, -2.74, -1.79, -3.14, -2.83, -3.68, -2.11, -4.05, -2.98, -2.93, -2.69, -2.80, -2.18, -3.14, -3.96, -2.63, -2.39, -2.40, -2.80, -3.06, -3.56, -3.92, -2.69, -3.90],
+    /* s */ [-2.57, -3.45, -2.55, -3.28, -2.02, -3.24, -3.48, -2.52, -2.28, -4.04, -3.02, -2.79, -3.07, -3.28, -2.55, -2.60, -3.59, -3.25, -2.31, -1.84, -2.54, -3.50, -3.27, -3.84, -2.84, -3.72],
+    /* t */ [-2.19, -3.33, -2.64, -2.97, -1.80, -3.04, -3.46, -2.30, -1.95, -3.67, -3.10, -2.80, -2.96, -3.25, -2.27, -2.88, -4.03, -2.16, -2.39, -2.52, -2.73, -3.56, -3.23, -3.51, -2.62, -3.52],
+    /* u */ [-3.01, -2.80, -2.97, -3.17, -2.75, -3.08, -3.07, -3.97, -2.85, -4.05, -3.57, -2.54, -2.76, -2.33, -3.58, -2.71, -4.26, -2.46, -2.49, -2.42, -3.88, -3.85, -4.02, -3.56, -3.92, -3.67],
+    /* v */ [-2.61, -3.95, -3.64, -3.99, -2.29, -3.90, -3.94, -4.19, -2.68, -4.34, -4.14, -3.84, -3.46, -3.86, -3.22, -3.83, -4.32, -3.67, -3.52, -3.71, -3.94, -4.03, -4.16, -4.11, -4.12, -4.23],
+    /* w */ [-2.80, -3.94, -3.65, -3.70, -2.87, -3.97, -3.94, -3.27, -2.74, -4.26, -3.86, -3.69, -3.80, -3.30, -3.04, -3.89, -4.26, -3.05, -3.18, -3.82, -3.93, -4.23, -3.88, -4.20, -4.17, -4.29],
+    /* x */ [-3.44, -3.90, -3.18, -3.72, -3.10, -3.62, -3.91, -3.93, -3.18, -4.35, -3.75, -3.71, -3.22, -4.01, -3.75, -2.99, -4.23, -3.78, -3.09, -2.79, -3.94, -4.01, -4.08, -3.55, -3.74, -4.11],
+    /* y */ [-3.21, -3.57, -3.41, -3.64, -3.29, -3.68, -3.71, -4.01, -3.42, -4.20, -3.98, -3.28, -3.25, -3.04, -3.37, -2.77, -4.23, -3.56, -2.90, -3.12, -3.56, -4.02, -3.80, -4.20, -3.97, -3.96],
+    /* z */ [-3.26, -4.08, -4.04, -4.02, -2.80, -4.15, -4.14, -3.80, -3.23, -4.24, -4.12, -3.77, -3.86, -3.83, -3.57, -4.19, -4.38, -4.16, -3.98, -3.88, -3.92, -4.27, -4.09, -4.34, -3.86, -3.69],
+];
+
+/// Mean log10 frequency of the letter pairs in `label`, with digits and dashes splitting it into
+/// runs. English words and names score around -2.5 and random strings around -3.5. `None` when
+/// the label has fewer than `ngram_min_length` letters, where the average is too noisy.
+pub fn label_score(label: &str) -> Option<f32> {
+    let letters = label.chars().filter(char::is_ascii_alphabetic).count();
+    if letters < config().ngram_min_length.max(2) {
+        return None;
+    }
+    let mut total = 0.0;
+    let mut pairs = 0;
+    for run in label.to_ascii_lowercase().split(|c: char| !c.is_ascii_lowercase()) {
+        for pair in run.as_bytes().windows(2) {
+            total += BIGRAM_LOG_FREQ[usize::from(pair[0] - b'a')][usize::from(pair[1] - b'a')];
+            pairs += 1;
+        }
+    }
+    (pairs > 0).then(|| total / pairs as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn real_names_score_above_the_threshold_and_random_labels_below() {
+        let threshold = config().ngram_threshold;
+        let facebook = label_score("facebook").unwrap();
+        let random = label_score("xkqzpwj").unwrap();
+        assert!(facebook > threshold, "facebook scored {}", facebook);
+        assert!(random < threshold, "xkqzpwj scored {}", random);
+        assert_eq!(label_score("ab"), None);
+    }
+}
@@ -0,0 +1,145 @@
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+// Hosts tracked by name; later ones are counted as `other` so a stream of phishing hosts
+// cannot grow the label set without bound.
+const MAX_HOSTS: usize = 256;
+
+#[derive(Default)]
+struct HostCounts {
+    requests: u64,
+    errors: u64,
+}
+
+#[derive(Default)]
+struct Histogram {
+    // Cumulative counts per entry of `BUCKETS`, then `+Inf`.
+    buckets: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (i, bound) in BUCKETS.iter().enumerate() {
+            if seconds <= *bound {
+                self.buckets[i] += 1;
+            }
+        }
+        self.buckets[BUCKETS.len()] += 1;
+        self.sum += seconds;
+    }
+}
+
+#[derive(Default)]
+struct Recorded {
+    // Keyed by (kind, host), e.g. ("ct", "crt.sh").
+    hosts: BTreeMap<(&'static str, String), HostCounts>,
+    latency: BTreeMap<&'static str, Histogram>,
+}
+
+// Counters for every outbound call (feeds, DNS, TLS probe, CT, reputation, content peek, webhook).
+#[derive(Default)]
+pub struct OutboundMetrics {
+    in_flight: AtomicUsize,
+    peak_in_flight: AtomicUsize,
+    recorded: Mutex<Recorded>,
+}
+
+pub static OUTBOUND: Lazy<OutboundMetrics> = Lazy::new(OutboundMetrics::default);
+
+impl OutboundMetrics {
+    fn record(&self, kind: &'static str, host: &str, elapsed: Duration, ok: bool) {
+        let mut recorded = self.recorded.lock().unwrap();
+        let known = recorded.hosts.contains_key(&(kind, host.to_string()));
+        let host = if known || recorded.hosts.len() < MAX_HOSTS { host } else { "other" };
+        let counts = recorded.hosts.entry((kind, host.to_string())).or_default();
+        counts.requests += 1;
+        if !ok {
+            counts.errors += 1;
+        }
+        recorded.latency.entry(kind).or_default().observe(elapsed.as_secs_f64());
+    }
+
+    /// Prometheus text exposition of the outbound counters.
+    pub fn render(&self, out: &mut String) {
+        let recorded = self.recorded.lock().unwrap();
+        let _ = writeln!(out, "# HELP ferrum_outbound_requests_total Outbound requests by kind and host.");
+        let _ = writeln!(out, "# TYPE ferrum_outbound_requests_total counter");
+        for ((kind, host), counts) in &recorded.hosts {
+            let _ = writeln!(out, "ferrum_outbound_requests_total{{kind=\"{}\",host=\"{}\"}} {}", kind, escape_label(host), counts.requests);
+        }
+        let _ = writeln!(out, "# HELP ferrum_outbound_errors_total Failed or timed-out outbound requests by kind and host.");
+        let _ = writeln!(out, "# TYPE ferrum_outbound_errors_total counter");
+        for ((kind, host), counts) in &recorded.hosts {
+            let _ = writeln!(out, "ferrum_outbound_errors_total{{kind=\"{}\",host=\"{}\"}} {}", kind, escape_label(host), counts.errors);
+        }
+        let _ = writeln!(out, "# HELP ferrum_outbound_request_duration_seconds Outbound request latency by kind.");
+        let _ = writeln!(out, "# TYPE ferrum_outbound_request_duration_seconds histogram");
+        for (kind, histogram) in &recorded.latency {
+            for (bound, count) in BUCKETS.iter().map(|b| b.to_string()).chain(["+Inf".to_string()]).zip(histogram.buckets) {
+                let _ = writeln!(out, "ferrum_outbound_request_duration_seconds_bucket{{kind=\"{}\",le=\"{}\"}} {}", kind, bound, count);
+            }
+            let _ = writeln!(out, "ferrum_outbound_request_duration_seconds_sum{{kind=\"{}\"}} {}", kind, histogram.sum);
+            let _ = writeln!(out, "ferrum_outbound_request_duration_seconds_count{{kind=\"{}\"}} {}", kind, histogram.buckets[BUCKETS.len()]);
+        }
+        let _ = writeln!(out, "# HELP ferrum_outbound_in_flight Outbound requests currently open.");
+        let _ = writeln!(out, "# TYPE ferrum_outbound_in_flight gauge");
+        let _ = writeln!(out, "ferrum_outbound_in_flight {}", self.in_flight.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# HELP ferrum_outbound_in_flight_peak Most outbound requests open at once since startup.");
+        let _ = writeln!(out, "# TYPE ferrum_outbound_in_flight_peak gauge");
+        let _ = writeln!(out, "ferrum_outbound_in_flight_peak {}", self.peak_in_flight.load(Ordering::Relaxed));
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Open call; a call dropped before completing (e.g. by an outer timeout) is recorded as an error.
+struct InFlight<'a> {
+    kind: &'static str,
+    host: &'a str,
+    start: Instant,
+    done: bool,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        OUTBOUND.in_flight.fetch_sub(1, Ordering::Relaxed);
+        if !self.done {
+            let elapsed = self.start.elapsed();
+            log::debug!("outbound kind={} host={} ms={:.1} cancelled", self.kind, self.host, elapsed.as_secs_f64() * 1000.0);
+            OUTBOUND.record(self.kind, self.host, elapsed, false);
+        }
+    }
+}
+
+/// Runs one outbound call, recording its latency and outcome under `kind` and `host`
+/// and logging it at debug level.
+pub async fn track<T, E: Display>(kind: &'static str, host: &str, call: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    let open = OUTBOUND.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+    OUTBOUND.peak_in_flight.fetch_max(open, Ordering::Relaxed);
+    let mut guard = InFlight { kind, host, start: Instant::now(), done: false };
+    let result = call.await;
+    guard.done = true;
+    let elapsed = guard.start.elapsed();
+    let ms = elapsed.as_secs_f64() * 1000.0;
+    match &result {
+        Ok(_) => log::debug!("outbound kind={} host={} ms={:.1} ok", kind, host, ms),
+        Err(e) => log::debug!("outbound kind={} host={} ms={:.1} error={}", kind, host, ms, e),
+    }
+    OUTBOUND.record(kind, host, elapsed, result.is_ok());
+    result
+}
+
+/// Host part of `url` for labelling, or the input itself when it does not parse.
+pub fn host_of(url: &str) -> String {
+    url::Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_string)).unwrap_or_else(|| url.to_string())
+}
+
@@ -0,0 +1,371 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Client;
+use reqwest::redirect::Policy;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::Duration;
+use url::{Host, Url};
+
+use crate::config::config;
+use crate::{http_client_builder, outbound, registrable_domain};
+
+// Pinned clients kept for connection reuse, one per host and checked address.
+const MAX_CLIENTS: usize = 256;
+static CLIENTS: Lazy<Mutex<HashMap<(String, SocketAddr), Client>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static FORM: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<form\b([^>]*)>(.*?)(?:</form\s*>|\z)").unwrap());
+static PASSWORD_INPUT: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?i)<input\b[^>]*\btype\s*=\s*["']?password\b"#).unwrap());
+static ACTION: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?i)\baction\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).unwrap());
+
+#[derive(Debug)]
+pub enum PeekOutcome {
+    // No password form, or every one posts back to the page's own domain.
+    Clean,
+    // Absolute action URL of a password form posting to another registrable domain.
+    CrossOrigin(String),
+    // The host resolved to an address the peek may not connect to; nothing was fetched.
+    Blocked(IpAddr),
+}
+
+// SSRF guard: loopback, private, link-local, shared (CGNAT), multicast and unspecified
+// addresses are never fetched, including IPv4-mapped IPv6 forms of them.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || a == 0
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+// Body of a guarded fetch, or the address that stopped it.
+pub enum Fetched {
+    Body(Vec<u8>),
+    Blocked(IpAddr),
+}
+
+/// The URL's host and the one address it resolves to, or that address when `allowed` (`is_public_ip`
+/// outside tests) rejects it. Callers connect to the returned address rather than resolving again.
+pub async fn public_addr(
+    url: &Url,
+    timeout: Duration,
+    allowed: fn(IpAddr) -> bool,
+) -> Result<Result<(String, SocketAddr), IpAddr>, Box<dyn std::error::Error + Send + Sync>> {
+    let port = url.port_or_known_default().ok_or("URL has no port")?;
+    let (host, addr) = match url.host().ok_or("URL has no host")? {
+        Host::Ipv4(ip) => (ip.to_string(), SocketAddr::new(ip.into(), port)),
+        Host::Ipv6(ip) => (ip.to_string(), SocketAddr::new(ip.into(), port)),
+        Host::Domain(domain) => {
+            let lookup = outbound::track("dns", domain, tokio::net::lookup_host((domain, port)));
+            let mut addrs = tokio::time::timeout(timeout, lookup).await??;
+            (domain.to_string(), addrs.next().ok_or("host has no addresses")?)
+        }
+    };
+    if !allowed(addr.ip()) {
+        return Ok(Err(addr.ip()));
+    }
+    Ok(Ok((host, addr)))
+}
+
+// A client without redirects that connects only to the public address checked here (the resolve
+// is pinned, so a second DNS answer cannot swap in a private one), with the host it serves.
+// Otherwise it has the shared outbound settings; `timeout` applies per request. `allowed` is
+// `is_public_ip` outside tests.
+async fn public_client(
+    url: &Url,
+    timeout: Duration,
+    allowed: fn(IpAddr) -> bool,
+) -> Result<Result<(Client, String), IpAddr>, Box<dyn std::error::Error + Send + Sync>> {
+    let (host, addr) = match public_addr(url, timeout, allowed).await? {
+        Ok(target) => target,
+        Err(ip) => return Ok(Err(ip)),
+    };
+    let mut clients = CLIENTS.lock().unwrap();
+    if let Some(client) = clients.get(&(host.clone(), addr)) {
+        return Ok(Ok((client.clone(), host)));
+    }
+    let client = http_client_builder(config()).redirect(Policy::none()).resolve(&host, addr).build()?;
+    if clients.len() >= MAX_CLIENTS {
+        clients.clear();
+    }
+    clients.insert((host.clone(), addr), client.clone());
+    Ok(Ok((client, host)))
+}
+
+/// GETs at most `max_bytes` of `url` with no redirects, from a public address only.
+pub async fn fetch_public(url: &Url, max_bytes: usize, timeout: Duration, kind: &'static str) -> Result<Fetched, Box<dyn std::error::Error + Send + Sync>> {
+    let (client, host) = match public_client(url, timeout, is_public_ip).await? {
+        Ok(client) => client,
+        Err(ip) => return Ok(Fetched::Blocked(ip)),
+    };
+    // The body is read inside the tracked call, so a slow download still counts as in flight.
+    let request = async {
+        let mut response = client.get(url.as_str()).timeout(timeout).send().await?.error_for_status()?;
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= max_bytes {
+                body.truncate(max_bytes);
+                break;
+            }
+        }
+        Ok::<_, reqwest::Error>(body)
+    };
+    Ok(Fetched::Body(outbound::track(kind, &host, request).await?))
+}
+
+// How the target answered a single request.
+#[derive(Debug)]
+pub struct Reachability {
+    pub status: u16,
+    // Where a 3xx points, resolved against the target; not followed.
+    pub location: Option<String>,
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Probe {
+    Reached(Reachability),
+    Blocked(IpAddr),
+}
+
+/// One HEAD request to `url` (a GET, body unread, when HEAD is refused with 405 or 501), from a
+/// public address only. Errors mean the target could not be reached at all.
+pub async fn probe(url: &Url, timeout: Duration) -> Result<Probe, Box<dyn std::error::Error + Send + Sync>> {
+    probe_as(url, timeout, "reachability", is_public_ip).await
+}
+
+async fn probe_as(
+    url: &Url,
+    timeout: Duration,
+    kind: &'static str,
+    allowed: fn(IpAddr) -> bool,
+) -> Result<Probe, Box<dyn std::error::Error + Send + Sync>> {
+    let (client, host) = match public_client(url, timeout, allowed).await? {
+        Ok(client) => client,
+        Err(ip) => return Ok(Probe::Blocked(ip)),
+    };
+    let request = async {
+        let response = client.head(url.as_str()).timeout(timeout).send().await?;
+        match response.status().as_u16() {
+            405 | 501 => client.get(url.as_str()).timeout(timeout).send().await,
+            _ => Ok(response),
+        }
+    };
+    let response = outbound::track(kind, &host, request).await?;
+    let header = |name| response.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    Ok(Probe::Reached(Reachability {
+        status: response.status().as_u16(),
+        location: header(reqwest::header::LOCATION).and_then(|location| url.join(&location).ok()).map(|u| u.to_string()),
+        content_type: header(reqwest::header::CONTENT_TYPE),
+    }))
+}
+
+#[derive(Debug)]
+pub enum RedirectOutcome {
+    // Where the chain ended after `hops` redirects, and whether that is another registrable
+    // domain than the start.
+    Followed { final_url: String, hops: usize, cross_domain: bool },
+    // A hop resolved to an address the check may not connect to; the chain stops there.
+    Blocked(IpAddr),
+    // The request allowance ran out after `hops` redirects, before the chain ended.
+    OutOfBudget { hops: usize },
+}
+
+/// Follows up to `max_hops` redirects from `url`, as `Policy::limited` would, but with every hop
+/// resolved and checked against the non-public address guard before it is requested. At most
+/// `max_requests` requests are made, the first included.
+pub async fn follow_redirects(
+    url: &Url,
+    max_hops: usize,
+    max_requests: usize,
+    timeout: Duration,
+) -> Result<RedirectOutcome, Box<dyn std::error::Error + Send + Sync>> {
+    follow_redirects_to(url, max_hops, max_requests, timeout, is_public_ip).await
+}
+
+async fn follow_redirects_to(
+    url: &Url,
+    max_hops: usize,
+    max_requests: usize,
+    timeout: Duration,
+    allowed: fn(IpAddr) -> bool,
+) -> Result<RedirectOutcome, Box<dyn std::error::Error + Send + Sync>> {
+    let chain = async {
+        let mut current = url.clone();
+        for hops in 0..=max_hops {
+            if hops >= max_requests {
+                return Ok(RedirectOutcome::OutOfBudget { hops });
+            }
+            let reached = match probe_as(&current, timeout, "redirect", allowed).await? {
+                Probe::Reached(reached) => reached,
+                Probe::Blocked(ip) => return Ok(RedirectOutcome::Blocked(ip)),
+            };
+            let next = reached.location.filter(|_| (300..400).contains(&reached.status)).and_then(|location| Url::parse(&location).ok());
+            match next {
+                Some(next) if matches!(next.scheme(), "http" | "https") => current = next,
+                _ => {
+                    let cross_domain = registrable_domain(current.as_str()) != registrable_domain(url.as_str());
+                    return Ok(RedirectOutcome::Followed { final_url: current.to_string(), hops, cross_domain });
+                }
+            }
+        }
+        Err(format!("more than {} redirects", max_hops).into())
+    };
+    tokio::time::timeout(timeout, chain).await.map_err(|_| format!("timed out after {}s", timeout.as_secs()))?
+}
+
+/// Fetches at most `max_bytes` of the page and looks for a password input whose form posts
+/// to a different registrable domain.
+pub async fn password_form(url: &Url, max_bytes: usize, timeout: Duration) -> Result<PeekOutcome, Box<dyn std::error::Error + Send + Sync>> {
+    let body = match fetch_public(url, max_bytes, timeout, "content_peek").await? {
+        Fetched::Body(body) => body,
+        Fetched::Blocked(ip) => return Ok(PeekOutcome::Blocked(ip)),
+    };
+    Ok(match cross_origin_action(&String::from_utf8_lossy(&body), url) {
+        Some(action) => PeekOutcome::CrossOrigin(action),
+        None => PeekOutcome::Clean,
+    })
+}
+
+fn cross_origin_action(html: &str, page: &Url) -> Option<String> {
+    let page_domain = registrable_domain(page.as_str())?;
+    FORM.captures_iter(html).find_map(|form| {
+        if !PASSWORD_INPUT.is_match(&form[2]) {
+            return None;
+        }
+        // A form without an action posts back to the page itself.
+        let action = ACTION.captures(&form[1])?;
+        let action = action.iter().skip(1).flatten().next()?.as_str().trim().replace("&amp;", "&");
+        let target = page.join(&action).ok()?;
+        if !matches!(target.scheme(), "http" | "https") {
+            return None;
+        }
+        (registrable_domain(target.as_str())? != page_domain).then(|| target.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Answers `/same` with a redirect on the same host, `/cross` with one to 127.0.0.2, `/loop`
+    // with one to itself, `/deep/<n>` with one to `/deep/<n-1>` and `/slow` with one to `/hang`,
+    // which never answers.
+    async fn redirect_server() -> u16 {
+        let listener = TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut request = vec![0; 4096];
+                    let len = stream.read(&mut request).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&request[..len]).to_string();
+                    let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    let location = match path.as_str() {
+                        "/same" => Some("/final".to_string()),
+                        "/cross" => Some(format!("http://127.0.0.2:{}/final", port)),
+                        "/loop" => Some("/loop".to_string()),
+                        "/slow" => Some("/hang".to_string()),
+                        deep if deep.starts_with("/deep/") => {
+                            let depth: usize = deep["/deep/".len()..].parse().unwrap();
+                            (depth > 0).then(|| format!("/deep/{}", depth - 1))
+                        }
+                        "/hang" => return std::future::pending().await,
+                        _ => None,
+                    };
+                    let response = match location {
+                        Some(location) => format!("HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", location),
+                        None => "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                    };
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        port
+    }
+
+    async fn follow(port: u16, path: &str, timeout: Duration) -> Result<RedirectOutcome, Box<dyn std::error::Error + Send + Sync>> {
+        let url = Url::parse(&format!("http://127.0.0.1:{}{}", port, path)).unwrap();
+        follow_redirects_to(&url, 5, 10, timeout, |_| true).await
+    }
+
+    #[tokio::test]
+    async fn same_domain_redirect_is_not_cross_domain() {
+        let port = redirect_server().await;
+        match follow(port, "/same", Duration::from_secs(5)).await.unwrap() {
+            RedirectOutcome::Followed { final_url, hops, cross_domain } => {
+                assert_eq!(final_url, format!("http://127.0.0.1:{}/final", port));
+                assert_eq!((hops, cross_domain), (1, false));
+            }
+            outcome => panic!("unexpected {:?}", outcome),
+        }
+    }
+
+    #[tokio::test]
+    async fn cross_domain_redirect_is_flagged() {
+        let port = redirect_server().await;
+        match follow(port, "/cross", Duration::from_secs(5)).await.unwrap() {
+            RedirectOutcome::Followed { final_url, hops, cross_domain } => {
+                assert_eq!(final_url, format!("http://127.0.0.2:{}/final", port));
+                assert_eq!((hops, cross_domain), (1, true));
+            }
+            outcome => panic!("unexpected {:?}", outcome),
+        }
+    }
+
+    #[tokio::test]
+    async fn redirect_loop_gives_up_after_max_hops() {
+        let port = redirect_server().await;
+        let error = follow(port, "/loop", Duration::from_secs(5)).await.unwrap_err();
+        assert_eq!(error.to_string(), "more than 5 redirects");
+    }
+
+    #[tokio::test]
+    async fn hanging_hop_times_out() {
+        let port = redirect_server().await;
+        let start = std::time::Instant::now();
+        let error = follow(port, "/slow", Duration::from_secs(1)).await.unwrap_err();
+        // Either the hop's own request timeout or the deadline for the whole chain ends it.
+        let timed_out = error.downcast_ref::<reqwest::Error>().is_some_and(reqwest::Error::is_timeout) || error.to_string().contains("timed out");
+        assert!(timed_out, "{:?}", error);
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn loopback_start_is_blocked() {
+        let port = redirect_server().await;
+        let url = Url::parse(&format!("http://127.0.0.1:{}/same", port)).unwrap();
+        let outcome = follow_redirects(&url, 5, 10, Duration::from_secs(5)).await.unwrap();
+        assert!(matches!(outcome, RedirectOutcome::Blocked(ip) if ip.is_loopback()));
+    }
+
+    #[tokio::test]
+    async fn deep_chain_stops_when_the_request_budget_runs_out() {
+        let port = redirect_server().await;
+        let url = Url::parse(&format!("http://127.0.0.1:{}/deep/4", port)).unwrap();
+        let outcome = follow_redirects_to(&url, 5, 3, Duration::from_secs(5), |_| true).await.unwrap();
+        assert!(matches!(outcome, RedirectOutcome::OutOfBudget { hops: 3 }), "{:?}", outcome);
+        // With enough budget the same chain ends normally.
+        let outcome = follow_redirects_to(&url, 5, 5, Duration::from_secs(5), |_| true).await.unwrap();
+        assert!(matches!(outcome, RedirectOutcome::Followed { hops: 4, .. }), "{:?}", outcome);
+    }
+}
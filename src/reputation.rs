@@ -0,0 +1,81 @@
+use futures::future::BoxFuture;
+use reqwest::Client;
+use serde::Deserialize;
+use std::time::Duration;
+use url::Url;
+
+use crate::config::{ReputationProviderConfig, config};
+use crate::outbound;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReputationScore {
+    // 0.0 (clean) to 1.0 (malicious); clamped on receipt.
+    pub score: f32,
+    // Provider's own explanation, reported in `analysis` when present.
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+// A provider's answer for one analysis, as fed into the risk score.
+#[derive(Debug, Clone)]
+pub struct Contribution {
+    pub provider: String,
+    pub weight: f32,
+    pub score: f32,
+}
+
+// An external opinion on a URL, blended into the risk score as `weight * score`.
+pub trait ReputationProvider: Send + Sync {
+    fn name(&self) -> &str;
+    fn weight(&self) -> f32;
+    // `None` when the provider has no opinion or could not be reached.
+    fn score<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, Option<ReputationScore>>;
+}
+
+// A JSON endpoint queried as `GET <endpoint>?url=<url>`, answering `{ "score": 0.8, "detail": "..." }`.
+pub struct HttpProvider {
+    client: Client,
+    config: ReputationProviderConfig,
+}
+
+impl ReputationProvider for HttpProvider {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn weight(&self) -> f32 {
+        self.config.weight
+    }
+
+    fn score<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, Option<ReputationScore>> {
+        Box::pin(async move {
+            let mut request = self
+                .client
+                .get(&self.config.endpoint)
+                .query(&[("url", url.as_str())])
+                .timeout(Duration::from_secs(self.config.timeout_secs.unwrap_or(config().request_timeout_secs)));
+            if let Some(key) = &self.config.api_key {
+                request = request.bearer_auth(key);
+            }
+            let request = async { request.send().await?.error_for_status()?.json::<ReputationScore>().await };
+            let result = outbound::track("reputation", &outbound::host_of(&self.config.endpoint), request).await;
+            match result {
+                Ok(mut score) => {
+                    score.score = score.score.clamp(0.0, 1.0);
+                    Some(score)
+                }
+                Err(e) => {
+                    log::debug!("Reputation provider {} failed for {}: {}", self.config.name, url, e);
+                    None
+                }
+            }
+        })
+    }
+}
+
+pub fn providers(client: &Client, configs: &[ReputationProviderConfig]) -> Vec<Box<dyn ReputationProvider>> {
+    configs
+        .iter()
+        .map(|config| Box::new(HttpProvider { client: client.clone(), config: config.clone() }) as Box<dyn ReputationProvider>)
+        .collect()
+}
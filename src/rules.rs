@@ -0,0 +1,191 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use url::Url;
+
+use crate::config::{RuleTarget, config};
+use crate::features::{METRICS, RawFeatures};
+
+pub struct CustomFeature {
+    pub name: String,
+    pub target: RuleTarget,
+    pub regex: Regex,
+    pub weight: f32,
+}
+
+static CUSTOM_FEATURES: Lazy<Vec<CustomFeature>> = Lazy::new(|| {
+    config()
+        .custom_features
+        .iter()
+        .filter_map(|rule| match Regex::new(&rule.pattern) {
+            Ok(regex) => Some(CustomFeature {
+                name: rule.name.clone(),
+                target: rule.target,
+                regex,
+                weight: rule.weight,
+            }),
+            Err(e) => {
+                log::warn!("Skipping custom feature '{}': invalid regex: {}", rule.name, e);
+                None
+            }
+        })
+        .collect()
+});
+
+pub fn custom_features() -> &'static [CustomFeature] {
+    &CUSTOM_FEATURES
+}
+
+impl CustomFeature {
+    pub fn matches(&self, parsed_url: &Url) -> bool {
+        match self.target {
+            RuleTarget::Host => parsed_url.host_str().is_some_and(|host| self.regex.is_match(host)),
+            RuleTarget::Path => self.regex.is_match(parsed_url.path()),
+            RuleTarget::Url => self.regex.is_match(parsed_url.as_str()),
+        }
+    }
+}
+
+// One entry of `detection_rules_file`. Rule names are reported as signals, like custom features.
+#[derive(Debug, Deserialize)]
+struct DetectionRuleSpec {
+    name: String,
+    condition: ConditionSpec,
+    #[serde(default)]
+    score: f32,
+    #[serde(default)]
+    verdict: Option<Verdict>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DetectionRuleFile {
+    rules: Vec<DetectionRuleSpec>,
+}
+
+// Written as a single-key object, e.g. `{"all": [{"host": "^login\\."}, {"tld": ["zip"]}]}`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+enum ConditionSpec {
+    All(Vec<ConditionSpec>),
+    Any(Vec<ConditionSpec>),
+    Not(Box<ConditionSpec>),
+    Host(String),
+    Path(String),
+    Tld(Vec<String>),
+    // A signal that fired, by its identifier in `data.signals`.
+    Signal(String),
+    // A numeric feature from `features=raw`, within the inclusive bounds given.
+    Feature { name: String, min: Option<f64>, max: Option<f64> },
+}
+
+// Lowest bucket a match holds the score at, under the thresholds of the profile being scored.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Medium,
+    High,
+}
+
+pub enum Condition {
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+    Host(Regex),
+    Path(Regex),
+    Tld(Vec<String>),
+    Signal(String),
+    Feature { name: String, min: Option<f64>, max: Option<f64> },
+}
+
+pub struct DetectionRule {
+    pub name: String,
+    pub condition: Condition,
+    pub score: f32,
+    pub verdict: Option<Verdict>,
+}
+
+// What a condition is evaluated against: the parsed URL, the signals fired so far and the feature vector.
+pub struct RuleInput<'a> {
+    pub parsed_url: &'a Url,
+    pub signals: &'a [String],
+    pub features: &'a RawFeatures,
+}
+
+static DETECTION_RULES: Lazy<Result<Vec<DetectionRule>, String>> = Lazy::new(|| {
+    let Some(path) = &config().detection_rules_file else {
+        return Ok(Vec::new());
+    };
+    let text = std::fs::read_to_string(path).map_err(|e| format!("detection_rules_file {}: {}", path, e))?;
+    let file: DetectionRuleFile = serde_json::from_str(&text).map_err(|e| format!("detection_rules_file {}: {}", path, e))?;
+    file.rules
+        .into_iter()
+        .map(|rule| {
+            let condition = compile(rule.condition).map_err(|e| format!("detection rule {}: {}", rule.name, e))?;
+            Ok(DetectionRule { name: rule.name, condition, score: rule.score, verdict: rule.verdict })
+        })
+        .collect()
+});
+
+/// Loads and compiles `detection_rules_file`, reporting the first problem. Unlike custom
+/// features, a broken rule fails startup: a half-loaded rule set would silently miss detections.
+pub fn load_detection_rules() -> Result<&'static [DetectionRule], String> {
+    DETECTION_RULES.as_deref().map_err(Clone::clone)
+}
+
+pub fn detection_rules() -> &'static [DetectionRule] {
+    DETECTION_RULES.as_deref().unwrap_or_default()
+}
+
+fn compile(spec: ConditionSpec) -> Result<Condition, String> {
+    let regex = |pattern: &str| Regex::new(pattern).map_err(|e| format!("invalid regex /{}/: {}", pattern, e));
+    Ok(match spec {
+        ConditionSpec::All(specs) => Condition::All(specs.into_iter().map(compile).collect::<Result<_, _>>()?),
+        ConditionSpec::Any(specs) => Condition::Any(specs.into_iter().map(compile).collect::<Result<_, _>>()?),
+        ConditionSpec::Not(spec) => Condition::Not(Box::new(compile(*spec)?)),
+        ConditionSpec::Host(pattern) => Condition::Host(regex(&pattern)?),
+        ConditionSpec::Path(pattern) => Condition::Path(regex(&pattern)?),
+        ConditionSpec::Tld(tlds) => Condition::Tld(tlds.iter().map(|tld| tld.trim_start_matches('.').to_lowercase()).collect()),
+        ConditionSpec::Signal(signal) => Condition::Signal(signal.strip_prefix("has_").unwrap_or(&signal).to_string()),
+        ConditionSpec::Feature { name, min, max } => {
+            if !METRICS.contains(&name.as_str()) {
+                return Err(format!("unknown feature {} (expected one of {})", name, METRICS.join(", ")));
+            }
+            if min.is_none() && max.is_none() {
+                return Err(format!("feature {} needs min or max", name));
+            }
+            Condition::Feature { name, min, max }
+        }
+    })
+}
+
+impl Condition {
+    pub fn matches(&self, input: &RuleInput) -> bool {
+        match self {
+            Condition::All(conditions) => conditions.iter().all(|c| c.matches(input)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.matches(input)),
+            Condition::Not(condition) => !condition.matches(input),
+            Condition::Host(regex) => input.parsed_url.host_str().is_some_and(|host| regex.is_match(host)),
+            Condition::Path(regex) => regex.is_match(input.parsed_url.path()),
+            Condition::Tld(tlds) => input
+                .parsed_url
+                .domain()
+                .and_then(|domain| domain.trim_end_matches('.').rsplit('.').next())
+                .is_some_and(|tld| tlds.iter().any(|listed| listed.eq_ignore_ascii_case(tld))),
+            Condition::Signal(signal) => input.signals.iter().any(|s| s == signal),
+            Condition::Feature { name, min, max } => input
+                .features
+                .metric(name)
+                .is_some_and(|value| min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)),
+        }
+    }
+
+    // Signal identifiers the condition refers to, for startup validation.
+    pub fn signals(&self) -> Vec<&str> {
+        match self {
+            Condition::All(conditions) | Condition::Any(conditions) => conditions.iter().flat_map(Condition::signals).collect(),
+            Condition::Not(condition) => condition.signals(),
+            Condition::Signal(signal) => vec![signal.as_str()],
+            _ => Vec::new(),
+        }
+    }
+}
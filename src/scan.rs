@@ -0,0 +1,90 @@
+use futures::stream::{self, StreamExt};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, IsTerminal, Write};
+
+use crate::config::config;
+use crate::{AnalysisOptions, AppState, analysis_response};
+
+const USAGE: &str = "usage: rusty scan --input <urls.txt> --output <results.jsonl> [--concurrency <n>]";
+
+pub struct ScanArgs {
+    input: String,
+    output: String,
+    concurrency: usize,
+}
+
+impl ScanArgs {
+    /// Parses the arguments after `scan`; `--concurrency` defaults to `batch_concurrency`.
+    pub fn parse(args: &[String]) -> Result<ScanArgs, String> {
+        let (mut input, mut output, mut concurrency) = (None, None, config().batch_concurrency);
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("{} needs a value\n{}", flag, USAGE))?;
+            match flag.as_str() {
+                "--input" => input = Some(value.clone()),
+                "--output" => output = Some(value.clone()),
+                "--concurrency" => concurrency = value.parse().map_err(|_| format!("--concurrency: not a number: {}\n{}", value, USAGE))?,
+                _ => return Err(format!("unknown argument {}\n{}", flag, USAGE)),
+            }
+        }
+        match (input, output) {
+            (Some(input), Some(output)) => Ok(ScanArgs { input, output, concurrency: concurrency.max(1) }),
+            _ => Err(USAGE.to_string()),
+        }
+    }
+}
+
+/// Analyzes every URL in the input file (one per line; blank lines and `#` comments skipped)
+/// and writes one analysis response per line to the output file, in completion order.
+/// On Ctrl-C the results so far are flushed and the scan fails.
+pub async fn run(state: &AppState, args: ScanArgs) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let content = fs::read_to_string(&args.input).map_err(|e| format!("{}: {}", args.input, e))?;
+    let urls: Vec<String> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    let mut out = BufWriter::new(File::create(&args.output).map_err(|e| format!("{}: {}", args.output, e))?);
+    let total = urls.len();
+    let mut results = stream::iter(urls)
+        .map(|url| {
+            let state = state.clone();
+            async move { analysis_response(&url, &state, &AnalysisOptions::default()).await.1.0 }
+        })
+        .buffer_unordered(args.concurrency);
+    let progress = std::io::stderr().is_terminal();
+    let mut counts: BTreeMap<&'static str, usize> = BTreeMap::new();
+    let mut done = 0;
+    let interrupt = tokio::signal::ctrl_c();
+    tokio::pin!(interrupt);
+    let interrupted = loop {
+        tokio::select! {
+            response = results.next() => {
+                let Some(response) = response else {
+                    break false;
+                };
+                serde_json::to_writer(&mut out, &response)?;
+                out.write_all(b"\n")?;
+                let verdict = response.data.as_ref().map_or("error", |data| data.bucket().as_str());
+                *counts.entry(verdict).or_insert(0) += 1;
+                done += 1;
+                if progress {
+                    eprint!("\r{} / {} URLs ({}%)", done, total, done * 100 / total);
+                }
+            }
+            _ = &mut interrupt => break true,
+        }
+    };
+    out.flush()?;
+    if progress && done > 0 {
+        eprintln!();
+    }
+    let summary: Vec<String> = counts.iter().map(|(verdict, count)| format!("{} {}", count, verdict)).collect();
+    println!("Scanned {} of {} URLs into {}: {}", done, total, args.output, if summary.is_empty() { "nothing".to_string() } else { summary.join(", ") });
+    if interrupted {
+        return Err(format!("Scan interrupted; {} partial results written to {}", done, args.output).into());
+    }
+    Ok(())
+}
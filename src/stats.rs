@@ -0,0 +1,195 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::RiskBucket;
+
+const TOP_N: usize = 10;
+// Interval counts share one atomic, total in the low half and phishing hits in the high half,
+// so a reset swaps both at once and never splits an analysis between two intervals.
+const PHISHING_UNIT: u64 = 1 << 32;
+
+struct Sample {
+    at: Instant,
+    url: String,
+    bucket: RiskBucket,
+    domain: Option<String>,
+    // Host shape shared by URLs from one campaign; see `structural_fingerprint`.
+    fingerprint: Option<String>,
+    signals: Vec<String>,
+    is_phishing: bool,
+}
+
+// Lifetime counters are atomics; the rolling window is a ring buffer behind a short-held lock.
+pub struct Stats {
+    total: AtomicU64,
+    phishing: AtomicU64,
+    // Counts since startup or the last `reset`, packed as described at `PHISHING_UNIT`.
+    interval: AtomicU64,
+    interval_started: AtomicU64,
+    window: Mutex<VecDeque<Sample>>,
+    capacity: usize,
+}
+
+#[derive(Serialize)]
+pub struct CountEntry {
+    pub name: String,
+    pub count: usize,
+}
+
+// Other high-risk URLs in the window that share a URL's domain or fingerprint.
+pub struct Correlation {
+    pub domain: usize,
+    pub fingerprint: usize,
+}
+
+#[derive(Serialize)]
+pub struct StatsSnapshot {
+    // Analyses since `since` (unix seconds), the startup or last reset.
+    pub since: u64,
+    pub total_analyses: u64,
+    pub total_phishing: u64,
+    pub window_capacity: usize,
+    pub window_analyses: usize,
+    pub risk_buckets: HashMap<&'static str, usize>,
+    pub phishing_hit_rate: f32,
+    pub top_flagged_domains: Vec<CountEntry>,
+    pub top_signals: Vec<CountEntry>,
+    pub cache_entries: usize,
+}
+
+impl Stats {
+    pub fn new(capacity: usize) -> Stats {
+        Stats {
+            total: AtomicU64::new(0),
+            phishing: AtomicU64::new(0),
+            interval: AtomicU64::new(0),
+            interval_started: AtomicU64::new(unix_now()),
+            window: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn record(
+        &self,
+        url: String,
+        bucket: RiskBucket,
+        domain: Option<String>,
+        fingerprint: Option<String>,
+        signals: Vec<String>,
+        is_phishing: bool,
+    ) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        if is_phishing {
+            self.phishing.fetch_add(1, Ordering::Relaxed);
+        }
+        self.interval.fetch_add(1 + if is_phishing { PHISHING_UNIT } else { 0 }, Ordering::Relaxed);
+        if self.capacity == 0 {
+            return;
+        }
+        let mut window = self.window.lock().unwrap();
+        if window.len() == self.capacity {
+            window.pop_front();
+        }
+        window.push_back(Sample { at: Instant::now(), url, bucket, domain, fingerprint, signals, is_phishing });
+    }
+
+    // Analyses in the last `window` when there are at least `min_samples` of them and none was a
+    // phishing-database hit or a high-risk verdict, i.e. detection looks to have gone quiet.
+    pub fn quiet_detection(&self, window: Duration, min_samples: usize) -> Option<usize> {
+        let since = Instant::now().checked_sub(window);
+        let window = self.window.lock().unwrap();
+        let recent = window.iter().rev().take_while(|sample| since.is_none_or(|since| sample.at >= since));
+        let mut count = 0;
+        for sample in recent {
+            if sample.is_phishing || sample.bucket == RiskBucket::High {
+                return None;
+            }
+            count += 1;
+        }
+        (count >= min_samples).then_some(count)
+    }
+
+    // Counts distinct URLs other than `url`, so repeat lookups of one URL never correlate with themselves.
+    pub fn correlated(&self, url: &str, domain: Option<&str>, fingerprint: Option<&str>) -> Correlation {
+        let mut by_domain: HashSet<&str> = HashSet::new();
+        let mut by_fingerprint: HashSet<&str> = HashSet::new();
+        let window = self.window.lock().unwrap();
+        for sample in window.iter().filter(|sample| sample.bucket == RiskBucket::High && sample.url != url) {
+            if domain.is_some() && sample.domain.as_deref() == domain {
+                by_domain.insert(&sample.url);
+            }
+            if fingerprint.is_some() && sample.fingerprint.as_deref() == fingerprint {
+                by_fingerprint.insert(&sample.url);
+            }
+        }
+        Correlation { domain: by_domain.len(), fingerprint: by_fingerprint.len() }
+    }
+
+    // Counts since startup, for monotonic Prometheus counters; `reset` leaves these alone.
+    pub fn lifetime(&self) -> (u64, u64) {
+        (self.total.load(Ordering::Relaxed), self.phishing.load(Ordering::Relaxed))
+    }
+
+    pub fn snapshot(&self, cache_entries: usize) -> StatsSnapshot {
+        let since = self.interval_started.load(Ordering::Relaxed);
+        self.summarize(since, self.interval.load(Ordering::Relaxed), cache_entries)
+    }
+
+    // Snapshot of the interval that just ended, with its counters zeroed for the next one.
+    // The rolling window is not cleared, since trending and quiet-detection rely on it.
+    pub fn reset(&self, cache_entries: usize) -> StatsSnapshot {
+        let since = self.interval_started.swap(unix_now(), Ordering::Relaxed);
+        self.summarize(since, self.interval.swap(0, Ordering::Relaxed), cache_entries)
+    }
+
+    fn summarize(&self, since: u64, interval: u64, cache_entries: usize) -> StatsSnapshot {
+        let mut risk_buckets: HashMap<&'static str, usize> =
+            [RiskBucket::Low, RiskBucket::Medium, RiskBucket::High, RiskBucket::Unknown].iter().map(|b| (b.as_str(), 0)).collect();
+        let mut flagged_domains: HashMap<String, usize> = HashMap::new();
+        let mut signals: HashMap<String, usize> = HashMap::new();
+        let mut phishing_hits = 0;
+
+        let window = self.window.lock().unwrap();
+        for sample in window.iter() {
+            *risk_buckets.entry(sample.bucket.as_str()).or_default() += 1;
+            if sample.is_phishing {
+                phishing_hits += 1;
+                if let Some(domain) = &sample.domain {
+                    *flagged_domains.entry(domain.clone()).or_default() += 1;
+                }
+            }
+            for signal in &sample.signals {
+                *signals.entry(signal.clone()).or_default() += 1;
+            }
+        }
+        let window_analyses = window.len();
+        drop(window);
+
+        StatsSnapshot {
+            since,
+            total_analyses: interval % PHISHING_UNIT,
+            total_phishing: interval / PHISHING_UNIT,
+            window_capacity: self.capacity,
+            window_analyses,
+            risk_buckets,
+            phishing_hit_rate: if window_analyses == 0 { 0.0 } else { phishing_hits as f32 / window_analyses as f32 },
+            top_flagged_domains: top_counts(flagged_domains),
+            top_signals: top_counts(signals),
+            cache_entries,
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn top_counts(counts: HashMap<String, usize>) -> Vec<CountEntry> {
+    let mut entries: Vec<CountEntry> = counts.into_iter().map(|(name, count)| CountEntry { name, count }).collect();
+    entries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    entries.truncate(TOP_N);
+    entries
+}
@@ -0,0 +1,156 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{
+    self, AlertDescription, ClientConfig, DigitallySignedStruct, PeerIncompatible, ProtocolVersion, SignatureScheme,
+};
+use url::Url;
+
+use crate::peek;
+
+// Result of probing a URL's host, or the non-public address that stopped the probe.
+#[derive(Debug)]
+pub enum TlsCheck {
+    Probed(TlsProbe),
+    Blocked(IpAddr),
+}
+
+#[derive(Debug)]
+pub struct TlsProbe {
+    // Negotiated protocol, e.g. "TLS 1.3"; `None` when the handshake was refused.
+    pub version: Option<&'static str>,
+    // The server only offers TLS 1.1 or older.
+    pub weak: bool,
+}
+
+// The probe only records which protocol the server negotiates, so any certificate is accepted.
+// Nothing is sent over the connection once the handshake completes.
+#[derive(Debug)]
+struct ProbeVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for ProbeVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn version_name(version: ProtocolVersion) -> &'static str {
+    match version {
+        ProtocolVersion::TLSv1_3 => "TLS 1.3",
+        ProtocolVersion::TLSv1_2 => "TLS 1.2",
+        _ => "TLS (other)",
+    }
+}
+
+// Handshake failures that mean the server cannot speak TLS 1.2 or newer.
+fn is_legacy_only(error: &rustls::Error) -> bool {
+    matches!(
+        error,
+        rustls::Error::PeerIncompatible(PeerIncompatible::ServerDoesNotSupportTls12Or13)
+            | rustls::Error::AlertReceived(AlertDescription::ProtocolVersion)
+    )
+}
+
+/// Probes the TLS version of an `https` URL's host, from a public address only.
+pub async fn check_tls(url: &Url, timeout: Duration) -> Result<TlsCheck, Box<dyn std::error::Error + Send + Sync>> {
+    check_tls_as(url, timeout, peek::is_public_ip).await
+}
+
+async fn check_tls_as(url: &Url, timeout: Duration, allowed: fn(IpAddr) -> bool) -> Result<TlsCheck, Box<dyn std::error::Error + Send + Sync>> {
+    match peek::public_addr(url, timeout, allowed).await? {
+        Ok((host, addr)) => Ok(TlsCheck::Probed(probe_tls(&host, addr, timeout).await?)),
+        Err(ip) => Ok(TlsCheck::Blocked(ip)),
+    }
+}
+
+// Handshakes with `addr`, already checked by the caller, presenting `host` as the server name.
+async fn probe_tls(host: &str, addr: SocketAddr, timeout: Duration) -> Result<TlsProbe, Box<dyn std::error::Error + Send + Sync>> {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13, &rustls::version::TLS12])?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(ProbeVerifier(provider)))
+        .with_no_client_auth();
+    let connector = TlsConnector::from(Arc::new(config));
+    let server_name = ServerName::try_from(host.to_string())?;
+
+    let tcp = tokio::time::timeout(timeout, TcpStream::connect(addr)).await??;
+    match tokio::time::timeout(timeout, connector.connect(server_name, tcp)).await? {
+        Ok(stream) => Ok(TlsProbe {
+            version: stream.get_ref().1.protocol_version().map(version_name),
+            weak: false,
+        }),
+        Err(e) => {
+            let legacy_only = e
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+                .is_some_and(is_legacy_only);
+            if legacy_only {
+                Ok(TlsProbe { version: None, weak: true })
+            } else {
+                Err(e.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::tls10_only_server;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn private_targets_are_refused_before_connecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        for url in [format!("https://127.0.0.1:{}/", port), format!("https://localhost:{}/", port)] {
+            let check = check_tls(&Url::parse(&url).unwrap(), Duration::from_secs(2)).await.unwrap();
+            assert!(matches!(check, TlsCheck::Blocked(ip) if ip.is_loopback()), "{}: {:?}", url, check);
+        }
+        let accepted = tokio::time::timeout(Duration::from_millis(200), listener.accept()).await;
+        assert!(accepted.is_err(), "the probe connected to a loopback port");
+    }
+
+    #[tokio::test]
+    async fn legacy_only_server_is_weak() {
+        let (addr, _server) = tls10_only_server().await;
+        let url = Url::parse(&format!("https://{}/", addr)).unwrap();
+        let check = check_tls_as(&url, Duration::from_secs(2), |_| true).await.unwrap();
+        assert!(matches!(check, TlsCheck::Probed(TlsProbe { version: None, weak: true })), "{:?}", check);
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::fuzzy::{self, FuzzyMatch};
+
+// Popularity list such as Tranco or Umbrella: one `rank,domain` (or bare `domain`) per line.
+pub struct TopSites {
+    ranks: HashMap<String, usize>,
+    // Domains ordered by rank, for typosquat comparisons against the head of the list.
+    by_rank: Vec<String>,
+}
+
+impl TopSites {
+    pub fn open(path: &str) -> Option<TopSites> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                log::warn!("Failed to read top sites list {}: {}", path, e);
+                return None;
+            }
+        };
+        let mut entries: Vec<(usize, String)> = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .enumerate()
+            .map(|(i, line)| match line.split_once(',') {
+                Some((rank, domain)) => (rank.trim().parse().unwrap_or(i + 1), domain.trim().to_lowercase()),
+                None => (i + 1, line.to_lowercase()),
+            })
+            .collect();
+        entries.sort_by_key(|(rank, _)| *rank);
+        let mut ranks = HashMap::with_capacity(entries.len());
+        for (rank, domain) in &entries {
+            ranks.entry(domain.clone()).or_insert(*rank);
+        }
+        log::info!("Loaded {} top sites from {}", ranks.len(), path);
+        Some(TopSites {
+            ranks,
+            by_rank: entries.into_iter().map(|(_, domain)| domain).collect(),
+        })
+    }
+
+    pub fn rank(&self, domain: &str) -> Option<usize> {
+        self.ranks.get(domain).copied()
+    }
+
+    /// Closest entry among the `limit` most popular domains within edit distance 1, excluding exact matches.
+    pub fn lookalike(&self, domain: &str, limit: usize) -> Option<FuzzyMatch> {
+        fuzzy::closest_match(domain, self.by_rank.iter().take(limit).map(String::as_str), 1)
+    }
+}
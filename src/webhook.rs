@@ -0,0 +1,96 @@
+use lru::LruCache;
+use reqwest::Client;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::outbound;
+
+const MAX_ATTEMPTS: u32 = 3;
+const MAX_TRACKED_URLS: usize = 10_000;
+
+#[derive(Serialize)]
+struct AlertPayload<'a> {
+    idempotency_key: &'a str,
+    url: &'a str,
+    normalized_url: &'a str,
+    risk_score: f32,
+    signals: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    served_by: Option<&'a str>,
+}
+
+pub struct Alert {
+    pub url: String,
+    pub normalized_url: String,
+    pub risk_score: f32,
+    pub signals: Vec<String>,
+}
+
+// High-risk alerts POSTed to `webhook_url`, at most once per URL per cooldown window.
+pub struct Webhook {
+    client: Client,
+    url: String,
+    cooldown: Duration,
+    // When each normalized URL was last alerted.
+    sent: Mutex<LruCache<String, Instant>>,
+}
+
+/// Stable for one URL, deny-list generation and UTC day, so retries and repeats dedup on the receiver.
+pub fn idempotency_key(normalized_url: &str, generation: &str) -> String {
+    let day = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 86_400);
+    let digest = Sha256::digest(format!("{}\n{}\n{}", normalized_url, generation, day));
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Webhook {
+    pub fn new(client: Client, url: String, cooldown: Duration) -> Webhook {
+        Webhook {
+            client,
+            url,
+            cooldown,
+            sent: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_TRACKED_URLS).unwrap())),
+        }
+    }
+
+    // Claims the alert slot for a URL; false while its cooldown is still running.
+    fn claim(&self, normalized_url: &str) -> bool {
+        let mut sent = self.sent.lock().unwrap();
+        let now = Instant::now();
+        if sent.get(normalized_url).is_some_and(|last| now.duration_since(*last) < self.cooldown) {
+            return false;
+        }
+        sent.put(normalized_url.to_string(), now);
+        true
+    }
+
+    pub async fn send(&self, alert: Alert, generation: &str, served_by: Option<&str>) {
+        if !self.claim(&alert.normalized_url) {
+            log::debug!("Webhook alert for {} suppressed by cooldown", alert.normalized_url);
+            return;
+        }
+        let key = idempotency_key(&alert.normalized_url, generation);
+        let payload = AlertPayload {
+            idempotency_key: &key,
+            url: &alert.url,
+            normalized_url: &alert.normalized_url,
+            risk_score: alert.risk_score,
+            signals: &alert.signals,
+            served_by,
+        };
+        for attempt in 1..=MAX_ATTEMPTS {
+            let request = self.client.post(&self.url).header("Idempotency-Key", &key).json(&payload).send();
+            let result = outbound::track("webhook", &outbound::host_of(&self.url), async { request.await?.error_for_status() }).await;
+            match result {
+                Ok(_) => return,
+                Err(e) if attempt < MAX_ATTEMPTS => {
+                    log::debug!("Webhook attempt {} for {} failed: {}", attempt, alert.normalized_url, e);
+                    tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+                }
+                Err(e) => log::warn!("Webhook alert for {} failed after {} attempts: {}", alert.normalized_url, MAX_ATTEMPTS, e),
+            }
+        }
+    }
+}